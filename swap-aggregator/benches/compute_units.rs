@@ -0,0 +1,268 @@
+//! Compute units consumed by the aggregator, per route type and admin instruction
+//!
+//! Runs the SBF build of the program against the mock venues of the
+//! integration tests, so the numbers track the aggregator side of a swap and
+//! not the cost of the real venues. Build the program first:
+//!
+//! ```sh
+//! cargo build-sbf
+//! SBF_OUT_DIR=target/deploy cargo bench --bench compute_units -- --save compute_units.txt
+//! SBF_OUT_DIR=target/deploy cargo bench --bench compute_units -- --baseline compute_units.txt --threshold 5
+//! ```
+//!
+//! The table is printed in the format `--baseline` reads back, so a saved run
+//! can be committed and diffed. With a baseline, the run fails when a case
+//! consumes more than `--threshold` percent above it.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use {
+  common::*,
+
+  solana_program::{pubkey::Pubkey, system_program},
+  solana_program_test::{find_file, ProgramTest},
+  solana_sdk::{
+    account::Account,
+    instruction::Instruction,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+  },
+
+  swap_aggregator::{
+    client::{self, RoutePool},
+    constant::DEFAULT_FEE_CHANGE_DELAY,
+    instruction::AggregatorPath,
+    processor::DiscountTier,
+  },
+
+  std::{collections::BTreeMap, fs, path::PathBuf, process},
+};
+
+const AMOUNT_IN: u64 = 10_000_000;
+
+/// Default regression threshold, in percent of the baseline
+const DEFAULT_THRESHOLD: f64 = 5.0;
+
+/// Compute unit limit of the measured transactions, above the default so a costly case still reports
+const COMPUTE_UNIT_LIMIT: u64 = 1_400_000;
+
+struct Options {
+  baseline: Option<PathBuf>,
+  save: Option<PathBuf>,
+  threshold: f64,
+}
+
+fn parse_options() -> Options {
+  let mut options = Options { baseline: None, save: None, threshold: DEFAULT_THRESHOLD };
+  let mut args = std::env::args().skip(1);
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--baseline" => options.baseline = args.next().map(PathBuf::from),
+      "--save" => options.save = args.next().map(PathBuf::from),
+      "--threshold" => {
+        options.threshold = args
+          .next()
+          .and_then(|threshold| threshold.parse().ok())
+          .unwrap_or_else(|| fail("--threshold takes a percentage"));
+      },
+      // added by cargo bench
+      "--bench" => {},
+      _ => fail(&format!("unknown argument {}", arg)),
+    }
+  }
+  options
+}
+
+fn fail(message: &str) -> ! {
+  eprintln!("Error: {}", message);
+  process::exit(1);
+}
+
+/// Test environment around the SBF build of the aggregator, with an initialized state owned by `owner`
+fn bench_env(owner: &Keypair) -> (ProgramTest, TestEnv, Pubkey) {
+  let program_id = Pubkey::new_unique();
+  let mut program_test = ProgramTest::default();
+  program_test.prefer_bpf(true);
+  program_test.add_program("swap_aggregator", program_id, None);
+  program_test.set_compute_max_units(COMPUTE_UNIT_LIMIT);
+
+  let (mut program_test, env) = TestEnv::with_program_test(program_test, program_id);
+  add_program_state(&mut program_test, &program_id, &initialized_state(&owner.pubkey()));
+  program_test.add_account(owner.pubkey(), Account {
+    lamports: 1_000_000_000,
+    owner: system_program::id(),
+    ..Account::default()
+  });
+
+  // fee vault of the second leg of the two leg routes
+  let fee_vault_b = add_token_account(&mut program_test, &env.mint_b, &client::state_address(&program_id), 0);
+  (program_test, env, fee_vault_b)
+}
+
+/// Compute units consumed by `instructions`, signed by the payer and `signer`
+async fn measure(program_test: ProgramTest, instructions: &[Instruction], signer: &Keypair) -> u64 {
+  let mut context = program_test.start_with_context().await;
+  let transaction = Transaction::new_signed_with_payer(
+    instructions,
+    Some(&context.payer.pubkey()),
+    &[&context.payer, signer],
+    context.last_blockhash,
+  );
+  let outcome = context.banks_client.process_transaction_with_metadata(transaction).await.unwrap();
+  let metadata = outcome.metadata.unwrap();
+  if let Err(error) = outcome.result {
+    for log_message in &metadata.log_messages {
+      eprintln!("  {}", log_message);
+    }
+    fail(&format!("transaction failed: {:?}", error));
+  }
+  metadata.compute_units_consumed
+}
+
+async fn single_leg(pool: fn(&TestEnv) -> &RoutePool) -> u64 {
+  let owner = Keypair::new();
+  let (program_test, env, _) = bench_env(&owner);
+  let instruction = env.route_swap(pool(&env), AMOUNT_IN, 1);
+  measure(program_test, &[instruction], &env.user).await
+}
+
+/// `mint_a` to `mint_b` through `first`, then part of it back to `mint_a` through `second`
+async fn two_legs(first: fn(&TestEnv) -> &RoutePool, second: fn(&TestEnv) -> &RoutePool) -> u64 {
+  let owner = Keypair::new();
+  let (program_test, env, fee_vault_b) = bench_env(&owner);
+  let instructions = [
+    env.route_swap(first(&env), AMOUNT_IN, 1),
+    client::route_swap(
+      &env.program_id,
+      &env.user.pubkey(),
+      &env.destination,
+      &env.source,
+      &fee_vault_b,
+      &env.mint_b,
+      second(&env),
+      None,
+      AMOUNT_IN / 4,
+      1,
+    ).unwrap(),
+  ];
+  measure(program_test, &instructions, &env.user).await
+}
+
+async fn admin(instruction: fn(&TestEnv, &Pubkey) -> Instruction) -> u64 {
+  let owner = Keypair::new();
+  let (program_test, env, _) = bench_env(&owner);
+  let instruction = instruction(&env, &owner.pubkey());
+  measure(program_test, &[instruction], &owner).await
+}
+
+async fn run_cases() -> BTreeMap<&'static str, u64> {
+  let mut results = BTreeMap::new();
+
+  results.insert("route_swap/raydium", single_leg(|env| &env.raydium).await);
+  results.insert("route_swap/saber", single_leg(|env| &env.saber).await);
+  results.insert("route_swap/mercurial", single_leg(|env| &env.mercurial).await);
+
+  results.insert("two_legs/raydium+saber", two_legs(|env| &env.raydium, |env| &env.saber).await);
+  results.insert("two_legs/saber+mercurial", two_legs(|env| &env.saber, |env| &env.mercurial).await);
+  results.insert("two_legs/mercurial+raydium", two_legs(|env| &env.mercurial, |env| &env.raydium).await);
+
+  results.insert("admin/update_state", admin(|env, owner| {
+    client::update_state(&env.program_id, owner, owner, FEE_NUMERATOR, FEE_DENOMINATOR).unwrap()
+  }).await);
+  results.insert("admin/queue_fee_change", admin(|env, owner| {
+    client::queue_fee_change(&env.program_id, owner, 2, 1000, DEFAULT_FEE_CHANGE_DELAY).unwrap()
+  }).await);
+  results.insert("admin/set_pause", admin(|env, owner| {
+    client::set_pause(&env.program_id, owner, true).unwrap()
+  }).await);
+  results.insert("admin/set_route_enabled", admin(|env, owner| {
+    client::set_route_enabled(&env.program_id, owner, AggregatorPath::Saber, false).unwrap()
+  }).await);
+  results.insert("admin/set_roles", admin(|env, owner| {
+    client::set_roles(&env.program_id, owner, &Pubkey::new_unique(), &Pubkey::new_unique(), &Pubkey::new_unique()).unwrap()
+  }).await);
+  results.insert("admin/set_admin_signers", admin(|env, owner| {
+    client::set_admin_signers(&env.program_id, owner, &[*owner, Pubkey::new_unique()], 1).unwrap()
+  }).await);
+  results.insert("admin/update_fee_beneficiaries", admin(|env, owner| {
+    client::update_fee_beneficiaries(&env.program_id, owner, &[(Pubkey::new_unique(), 3), (Pubkey::new_unique(), 1)]).unwrap()
+  }).await);
+  results.insert("admin/update_discount_tiers", admin(|env, owner| {
+    let tiers = vec![DiscountTier { min_amount: 1_000, discount_bps: 2_500 }];
+    client::update_discount_tiers(&env.program_id, owner, &env.mint_b, tiers).unwrap()
+  }).await);
+
+  results
+}
+
+/// Reads the cases of a saved table
+fn read_baseline(path: &PathBuf) -> BTreeMap<String, u64> {
+  let table = fs::read_to_string(path).unwrap_or_else(|error| fail(&format!("{}: {}", path.display(), error)));
+  table
+    .lines()
+    .filter_map(|line| {
+      let mut columns = line.split('|').map(str::trim).filter(|column| !column.is_empty());
+      let case = columns.next()?;
+      let units = columns.next()?.parse().ok()?;
+      Some((case.to_string(), units))
+    })
+    .collect()
+}
+
+fn format_table(results: &BTreeMap<&'static str, u64>) -> String {
+  let mut table = String::from("| case | compute units |\n|---|---|\n");
+  for (case, units) in results {
+    table.push_str(&format!("| {} | {} |\n", case, units));
+  }
+  table
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+  let options = parse_options();
+  if find_file("swap_aggregator.so").is_none() {
+    fail("swap_aggregator.so not found, run cargo build-sbf and set SBF_OUT_DIR=target/deploy");
+  }
+
+  let results = run_cases().await;
+  let table = format_table(&results);
+  print!("{}", table);
+
+  if let Some(path) = &options.save {
+    fs::write(path, &table).unwrap_or_else(|error| fail(&format!("{}: {}", path.display(), error)));
+  }
+
+  let baseline = match &options.baseline {
+    Some(path) => read_baseline(path),
+    None => return,
+  };
+  let mut regressions = 0;
+  println!();
+  println!("| case | baseline | compute units | change |");
+  println!("|---|---|---|---|");
+  for (case, units) in &results {
+    let previous = match baseline.get(*case) {
+      Some(previous) => *previous,
+      None => {
+        println!("| {} | - | {} | new |", case, units);
+        continue;
+      },
+    };
+    let change = (*units as f64 - previous as f64) * 100.0 / previous.max(1) as f64;
+    let regression = change > options.threshold;
+    regressions += regression as usize;
+    println!(
+      "| {} | {} | {} | {:+.2}%{} |",
+      case,
+      previous,
+      units,
+      change,
+      if regression { " REGRESSION" } else { "" },
+    );
+  }
+
+  if regressions > 0 {
+    fail(&format!("{} case(s) above the {}% threshold", regressions, options.threshold));
+  }
+}
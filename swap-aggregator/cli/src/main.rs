@@ -0,0 +1,667 @@
+//! Command line tool for the swap aggregator
+//!
+//! Shows and updates the program state, quotes routes from a pool registry,
+//! sends route swaps and decodes aggregator transactions.
+
+mod pools;
+
+use {
+  base64::{engine::general_purpose::STANDARD, Engine},
+  borsh::BorshDeserialize,
+
+  clap::{Parser, Subcommand},
+
+  solana_client::{
+    rpc_client::RpcClient,
+    rpc_config::{RpcSimulateTransactionConfig, RpcTransactionConfig},
+  },
+  solana_sdk::{
+    commitment_config::CommitmentConfig,
+    instruction::{CompiledInstruction, Instruction},
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair, Signature, Signer},
+    transaction::Transaction,
+  },
+  solana_transaction_status::{
+    UiInnerInstructions,
+    UiInstruction,
+    UiLoadedAddresses,
+    UiReturnDataEncoding,
+    UiTransactionEncoding,
+  },
+
+  spl_associated_token_account::get_associated_token_address,
+
+  swap_aggregator::{
+    client,
+    decoder::{decode_transaction, AccountRole, InnerInstructions},
+    instruction::RouteOptions,
+    processor::ProgramState,
+    registry::PoolRegistry,
+    return_data::SimulationResult,
+    router::{Route, Router, DEFAULT_MAX_HOPS},
+  },
+
+  std::{error::Error, path::PathBuf},
+};
+
+type CliResult = Result<(), Box<dyn Error>>;
+
+#[derive(Parser)]
+#[clap(name = "swap-aggregator-cli", version, about = "Command line tool for the swap aggregator")]
+struct Cli {
+  /// RPC URL of the cluster
+  #[clap(long, short = 'u', global = true, default_value = "http://localhost:8899")]
+  url: String,
+
+  /// Keypair file of the signer and fee payer
+  #[clap(long, short = 'k', global = true, default_value = "~/.config/solana/id.json")]
+  keypair: String,
+
+  /// Swap aggregator program id
+  #[clap(long, global = true)]
+  program_id: Option<Pubkey>,
+
+  #[clap(subcommand)]
+  command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+  /// Program state
+  #[clap(subcommand)]
+  State(StateCommand),
+
+  /// Quotes the best route from a pool registry for the input left after the aggregator fee
+  Quote {
+    /// Pool registry file, JSON or TOML
+    #[clap(long)]
+    registry: PathBuf,
+
+    #[clap(long)]
+    input_mint: Pubkey,
+
+    #[clap(long)]
+    output_mint: Pubkey,
+
+    /// Input amount, in native units
+    #[clap(long)]
+    amount: u64,
+
+    /// Maximum number of legs of the route, at most 2
+    #[clap(long, default_value_t = DEFAULT_MAX_HOPS)]
+    max_hops: usize,
+
+    /// Token account of the discount mint held by the swapper
+    #[clap(long)]
+    discount_account: Option<Pubkey>,
+  },
+
+  /// Sends a RouteSwap through the best route of a pool registry
+  Swap {
+    /// Pool registry file, JSON or TOML
+    #[clap(long)]
+    registry: PathBuf,
+
+    #[clap(long)]
+    input_mint: Pubkey,
+
+    #[clap(long)]
+    output_mint: Pubkey,
+
+    /// Input amount, in native units
+    #[clap(long)]
+    amount: u64,
+
+    /// Accepted slippage on the quoted amount out, in basis points
+    #[clap(long, default_value_t = 50)]
+    slippage_bps: u64,
+
+    /// Source token account, the associated token account of the signer by default
+    #[clap(long)]
+    source: Option<Pubkey>,
+
+    /// Destination token account, the associated token account of the signer by default
+    #[clap(long)]
+    destination: Option<Pubkey>,
+
+    /// Fee vault, the associated token account of the program state by default
+    #[clap(long)]
+    fee_vault: Option<Pubkey>,
+
+    /// Token account of the discount mint held by the signer
+    #[clap(long)]
+    discount_account: Option<Pubkey>,
+
+    /// Quotes the route by simulating it instead of sending the swap, each leg through its venue
+    #[clap(long)]
+    simulate: bool,
+
+    /// Swaps the SOL of the signer when the input or output mint is the native mint,
+    /// through a temporary wrapped SOL account instead of the source or destination
+    #[clap(long)]
+    native_sol: bool,
+
+    /// Creates the associated token account of the output mint when the signer has none
+    #[clap(long)]
+    create_destination: bool,
+
+    /// Also considers two leg routes, swapped through an intermediate account of the program
+    #[clap(long)]
+    two_legs: bool,
+
+    /// Swaps what a partial fill of the second leg leaves in the intermediate account into the destination,
+    /// without it a partial fill aborts the swap
+    #[clap(long)]
+    sweep_residual: bool,
+  },
+
+  /// Decodes the aggregator instructions of a transaction, with their account roles, venue calls and events
+  DecodeTx {
+    signature: Signature,
+  },
+}
+
+#[derive(Subcommand)]
+enum StateCommand {
+  /// Decodes the program state
+  Show,
+
+  /// Sets the state owner, and the fee ratio when the state is created
+  Update {
+    /// New state owner, the signer by default
+    #[clap(long)]
+    new_owner: Option<Pubkey>,
+
+    /// Fee numerator, set when the state is created and the current one afterwards
+    #[clap(long)]
+    fee_numerator: Option<u64>,
+
+    /// Fee denominator, set when the state is created and the current one afterwards
+    #[clap(long)]
+    fee_denominator: Option<u64>,
+  },
+
+  /// Queues a fee ratio change behind the timelock
+  QueueFeeChange {
+    #[clap(long)]
+    fee_numerator: u64,
+
+    #[clap(long)]
+    fee_denominator: u64,
+
+    /// Delay of the following fee changes in seconds, the current one by default
+    #[clap(long)]
+    fee_change_delay: Option<i64>,
+  },
+
+  /// Applies the queued fee ratio change once its timelock is over
+  ExecuteFeeChange,
+
+  /// Grows a state account of an older program version to the current layout
+  Migrate,
+}
+
+struct Config {
+  rpc_client: RpcClient,
+  keypair_path: String,
+  program_id: Option<Pubkey>,
+}
+
+impl Config {
+  fn program_id(&self) -> Result<Pubkey, Box<dyn Error>> {
+    self.program_id.ok_or_else(|| "--program-id is required".into())
+  }
+
+  fn signer(&self) -> Result<Keypair, Box<dyn Error>> {
+    let path = match self.keypair_path.strip_prefix("~/") {
+      Some(path) => format!("{}/{}", std::env::var("HOME")?, path),
+      None => self.keypair_path.clone(),
+    };
+    read_keypair_file(&path).map_err(|error| format!("cannot read keypair {}: {}", path, error).into())
+  }
+
+  fn send(&self, signer: &Keypair, instructions: &[Instruction]) -> CliResult {
+    let blockhash = self.rpc_client.get_latest_blockhash()?;
+    let transaction = Transaction::new_signed_with_payer(instructions, Some(&signer.pubkey()), &[signer], blockhash);
+    let signature = self.rpc_client.send_and_confirm_transaction(&transaction)?;
+    println!("Signature: {}", signature);
+    Ok(())
+  }
+
+  /// Simulates `instructions` without signature checks and returns the return data of the transaction
+  fn simulate_return_data(&self, payer: &Pubkey, instructions: &[Instruction]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let transaction = Transaction::new_with_payer(instructions, Some(payer));
+    let response = self.rpc_client.simulate_transaction_with_config(&transaction, RpcSimulateTransactionConfig {
+      sig_verify: false,
+      replace_recent_blockhash: true,
+      commitment: Some(CommitmentConfig::confirmed()),
+      ..RpcSimulateTransactionConfig::default()
+    })?;
+
+    let return_data = match response.value.return_data {
+      Some(return_data) => return_data,
+      None => {
+        for log_message in response.value.logs.unwrap_or_default() {
+          println!("  {}", log_message);
+        }
+        return Err(format!("simulation returned no data: {:?}", response.value.err).into());
+      },
+    };
+    let (data, encoding) = return_data.data;
+    if encoding != UiReturnDataEncoding::Base64 {
+      return Err(format!("unexpected return data encoding {:?}", encoding).into());
+    }
+    Ok(STANDARD.decode(data)?)
+  }
+}
+
+fn load_router(config: &Config, registry: &PathBuf, max_hops: usize) -> Result<Router, Box<dyn Error>> {
+  let registry_pools = PoolRegistry::load(registry)?.pools()?;
+  let router_pools = pools::load_router_pools(&config.rpc_client, &registry_pools);
+
+  let slot = config.rpc_client.get_slot()?;
+  let current_ts = config.rpc_client.get_block_time(slot)?;
+
+  Ok(Router::new(router_pools, max_hops, current_ts))
+}
+
+fn print_route(route: &Route) {
+  for (index, leg) in route.legs.iter().enumerate() {
+    println!("Leg {}: {:?} {} -> {}", index + 1, leg.path, leg.source_mint, leg.destination_mint);
+    println!("  amount in:    {}", leg.amount_in);
+    println!("  amount out:   {}", leg.quote.amount_out);
+    println!("  fee paid:     {}", leg.quote.fee_paid);
+    println!("  price impact: {:.4}%", leg.quote.price_impact * 100.0);
+  }
+  println!("Amount in:  {}", route.amount_in);
+  println!("Amount out: {}", route.amount_out);
+}
+
+fn process_state_show(config: &Config) -> CliResult {
+  let state_address = client::state_address(&config.program_id()?);
+  let state = load_state(config, &state_address)?;
+
+  println!("State: {}", state_address);
+  println!("Initialized: {}", state.is_initialized);
+  println!("Bump: {}", state.bump);
+  println!("Owner: {}", state.state_owner);
+  println!("Fee: {}/{}", state.fee_numerator, state.fee_denominator);
+  println!("Fee change delay: {}s", state.fee_change_delay);
+  println!("Paused: {}", state.is_paused);
+  println!("Disabled routes: {:#010b}", state.disabled_routes);
+  println!("Fee admin: {}", state.fee_admin);
+  println!("Route admin: {}", state.route_admin);
+  println!("Pause guardian: {}", state.pause_guardian);
+  println!("Admin threshold: {} of {}", state.admin_threshold, state.admin_signer_count);
+  for admin_signer in state.admin_signers.iter().take(state.admin_signer_count as usize) {
+    println!("  {}", admin_signer);
+  }
+  println!("Beneficiaries:");
+  for beneficiary in state.beneficiaries.iter().filter(|beneficiary| beneficiary.weight > 0) {
+    println!("  {} weight {}", beneficiary.owner, beneficiary.weight);
+  }
+  println!("Discount mint: {}", state.discount_mint);
+  for tier in state.discount_tiers.iter().filter(|tier| tier.min_amount > 0) {
+    println!("  from {}: {} bps", tier.min_amount, tier.discount_bps);
+  }
+  let pending = &state.pending_fee_change;
+  if pending.is_pending {
+    println!(
+      "Pending fee change: {}/{} with a {}s delay from {}",
+      pending.fee_numerator, pending.fee_denominator, state.pending_fee_change_delay, pending.effective_timestamp,
+    );
+  }
+  Ok(())
+}
+
+fn load_state(config: &Config, state_address: &Pubkey) -> Result<ProgramState, Box<dyn Error>> {
+  let account = config.rpc_client.get_account(state_address)?;
+  if account.data.len() < ProgramState::LEN {
+    return Err(format!("state {} has an older layout, run `state migrate`", state_address).into());
+  }
+  Ok(ProgramState::try_from_slice(&account.data)?)
+}
+
+fn process_state(config: &Config, command: &StateCommand) -> CliResult {
+  if let StateCommand::Show = command {
+    return process_state_show(config);
+  }
+
+  let program_id = config.program_id()?;
+  let signer = config.signer()?;
+  let instruction = match command {
+    StateCommand::Show => unreachable!(),
+    StateCommand::Update { new_owner, fee_numerator, fee_denominator } => {
+      let state_address = client::state_address(&program_id);
+      let is_created = config.rpc_client.get_account_with_commitment(&state_address, config.rpc_client.commitment())?.value.is_some();
+      // the program rejects a fee ratio other than the stored one of an existing state
+      let (fee_numerator, fee_denominator) = match (fee_numerator, fee_denominator) {
+        (Some(fee_numerator), Some(fee_denominator)) => (*fee_numerator, *fee_denominator),
+        _ if is_created => {
+          let state = load_state(config, &state_address)?;
+          (state.fee_numerator, state.fee_denominator)
+        },
+        _ => return Err("--fee-numerator and --fee-denominator are both required to create the state".into()),
+      };
+      client::update_state(
+        &program_id,
+        &signer.pubkey(),
+        &new_owner.unwrap_or_else(|| signer.pubkey()),
+        fee_numerator,
+        fee_denominator,
+      )?
+    },
+    StateCommand::QueueFeeChange { fee_numerator, fee_denominator, fee_change_delay } => {
+      let fee_change_delay = match fee_change_delay {
+        Some(fee_change_delay) => *fee_change_delay,
+        None => load_state(config, &client::state_address(&program_id))?.fee_change_delay,
+      };
+      client::queue_fee_change(&program_id, &signer.pubkey(), *fee_numerator, *fee_denominator, fee_change_delay)?
+    },
+    StateCommand::ExecuteFeeChange => client::execute_fee_change(&program_id, &signer.pubkey())?,
+    StateCommand::Migrate => client::migrate_state(&program_id, &signer.pubkey())?,
+  };
+
+  config.send(&signer, &[instruction])
+}
+
+/// part of `amount` left to the route once the aggregator fee of the program state,
+/// discounted for the balance of `discount_account`, is taken
+fn amount_after_fee(config: &Config, amount: u64, discount_account: Option<&Pubkey>) -> Result<u64, Box<dyn Error>> {
+  let state = load_state(config, &client::state_address(&config.program_id()?))?;
+  let discount_balance = match discount_account {
+    Some(discount_account) => Some(config.rpc_client.get_token_account_balance(discount_account)?.amount.parse::<u64>()?),
+    None => None,
+  };
+
+  let fee = state.fee_amount(amount, discount_balance);
+  println!("Aggregator fee: {}", fee);
+  amount.checked_sub(fee).filter(|amount| *amount > 0).ok_or_else(|| "amount does not cover the aggregator fee".into())
+}
+
+fn process_quote(
+  config: &Config,
+  registry: &PathBuf,
+  input_mint: &Pubkey,
+  output_mint: &Pubkey,
+  amount: u64,
+  max_hops: usize,
+  discount_account: Option<Pubkey>,
+) -> CliResult {
+  let router = load_router(config, registry, max_hops)?;
+  let route_amount = amount_after_fee(config, amount, discount_account.as_ref())?;
+  let route = router
+    .find_best_route(input_mint, output_mint, route_amount)
+    .ok_or("no route found")?;
+  print_route(&route);
+  Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn process_swap(
+  config: &Config,
+  registry: &PathBuf,
+  input_mint: &Pubkey,
+  output_mint: &Pubkey,
+  amount: u64,
+  slippage_bps: u64,
+  source: Option<Pubkey>,
+  destination: Option<Pubkey>,
+  fee_vault: Option<Pubkey>,
+  discount_account: Option<Pubkey>,
+  simulate: bool,
+  native_sol: bool,
+  create_destination: bool,
+  two_legs: bool,
+  sweep_residual: bool,
+) -> CliResult {
+  let program_id = config.program_id()?;
+  let signer = config.signer()?;
+
+  // a RouteSwap executes a single leg, a RouteSwapWithOptions up to two
+  let router = load_router(config, registry, if two_legs { 2 } else { 1 })?;
+  // the program takes its fee from the input before the first leg
+  let route_amount = amount_after_fee(config, amount, discount_account.as_ref())?;
+  let route = router
+    .find_best_route(input_mint, output_mint, route_amount)
+    .ok_or("no route found")?;
+  print_route(&route);
+
+  let leg = &route.legs[0];
+  let minimum_amount_out = (route.amount_out as u128 * (10000 - slippage_bps.min(10000)) as u128 / 10000) as u64;
+  let state_address = client::state_address(&program_id);
+
+  let options = RouteOptions {
+    wrap_native_input: native_sol && *input_mint == spl_token::native_mint::id(),
+    unwrap_native_output: native_sol && *output_mint == spl_token::native_mint::id(),
+    create_destination_account: create_destination,
+    // only a two leg route has an intermediate account
+    sweep_intermediate_residual: sweep_residual && route.legs.len() > 1,
+  };
+  let native_account = client::native_token_address(&program_id, &signer.pubkey());
+
+  let source = match options.wrap_native_input {
+    true => native_account,
+    false => source.unwrap_or_else(|| get_associated_token_address(&signer.pubkey(), input_mint)),
+  };
+  let destination = match options.unwrap_native_output {
+    true => native_account,
+    false => destination.unwrap_or_else(|| get_associated_token_address(&signer.pubkey(), output_mint)),
+  };
+  let fee_vault = fee_vault.unwrap_or_else(|| get_associated_token_address(&state_address, input_mint));
+  let pool = &router.pools()[leg.pool_index].pool;
+  let second_leg = route.legs.get(1).map(|leg| (&router.pools()[leg.pool_index].pool, &leg.source_mint));
+
+  if simulate {
+    // the options instruction has the intermediate account a second leg swaps through
+    let instruction = if options == RouteOptions::default() && second_leg.is_none() {
+      client::simulate_route(
+        &program_id,
+        &signer.pubkey(),
+        &source,
+        &destination,
+        &fee_vault,
+        input_mint,
+        pool,
+        discount_account.as_ref(),
+        amount,
+      )?
+    } else {
+      client::simulate_route_with_options(
+        &program_id,
+        &signer.pubkey(),
+        &source,
+        &destination,
+        &fee_vault,
+        input_mint,
+        output_mint,
+        pool,
+        second_leg,
+        discount_account.as_ref(),
+        amount,
+        options,
+      )?
+    };
+    let result = SimulationResult::try_from_slice(&config.simulate_return_data(&signer.pubkey(), &[instruction])?)?;
+    println!("Simulated fee:        {}", result.fee);
+    for (index, leg_amount_out) in result.legs_amount_out.iter().enumerate() {
+      println!("Simulated leg {} out: {}", index + 1, leg_amount_out);
+    }
+    println!("Simulated amount out: {}", result.amount_out);
+    return Ok(());
+  }
+
+  let instruction = if options == RouteOptions::default() && second_leg.is_none() {
+    client::route_swap(
+      &program_id,
+      &signer.pubkey(),
+      &source,
+      &destination,
+      &fee_vault,
+      input_mint,
+      pool,
+      discount_account.as_ref(),
+      amount,
+      minimum_amount_out,
+    )?
+  } else {
+    client::route_swap_with_options(
+      &program_id,
+      &signer.pubkey(),
+      &source,
+      &destination,
+      &fee_vault,
+      input_mint,
+      output_mint,
+      pool,
+      second_leg,
+      discount_account.as_ref(),
+      amount,
+      minimum_amount_out,
+      options,
+    )?
+  };
+
+  config.send(&signer, &[instruction])
+}
+
+fn process_decode_tx(config: &Config, signature: &Signature) -> CliResult {
+  let program_id = config.program_id()?;
+  let transaction = config.rpc_client.get_transaction_with_config(signature, RpcTransactionConfig {
+    encoding: Some(UiTransactionEncoding::Base64),
+    commitment: Some(CommitmentConfig::confirmed()),
+    max_supported_transaction_version: Some(0),
+  })?;
+
+  let versioned_transaction = transaction.transaction.transaction
+    .decode()
+    .ok_or("cannot decode the transaction")?;
+  let meta = transaction.transaction.meta.ok_or("transaction without status meta")?;
+
+  // compiled indices refer to the static keys, then the loaded writable and readonly addresses
+  let mut account_keys = versioned_transaction.message.static_account_keys().to_vec();
+  let loaded_addresses: Option<UiLoadedAddresses> = meta.loaded_addresses.into();
+  if let Some(loaded_addresses) = loaded_addresses {
+    for address in loaded_addresses.writable.iter().chain(loaded_addresses.readonly.iter()) {
+      account_keys.push(address.parse()?);
+    }
+  }
+
+  let ui_inner_instructions: Option<Vec<UiInnerInstructions>> = meta.inner_instructions.into();
+  let inner_instructions = ui_inner_instructions
+    .unwrap_or_default()
+    .into_iter()
+    .map(|inner| Ok(InnerInstructions {
+      index: inner.index,
+      instructions: inner.instructions
+        .into_iter()
+        .filter_map(|instruction| match instruction {
+          UiInstruction::Compiled(instruction) => Some(instruction),
+          UiInstruction::Parsed(_) => None,
+        })
+        .map(|instruction| Ok(CompiledInstruction {
+          program_id_index: instruction.program_id_index,
+          accounts: instruction.accounts,
+          data: bs58::decode(&instruction.data).into_vec()?,
+        }))
+        .collect::<Result<_, bs58::decode::Error>>()?,
+    }))
+    .collect::<Result<Vec<_>, bs58::decode::Error>>()?;
+
+  let log_messages: Option<Vec<String>> = meta.log_messages.into();
+  let log_messages = log_messages.unwrap_or_default();
+  let decoded = decode_transaction(
+    &program_id,
+    &account_keys,
+    versioned_transaction.message.instructions(),
+    &inner_instructions,
+    &log_messages,
+  );
+
+  let account_name = |account: &AccountRole| account.pubkey
+    .map_or_else(|| "missing account key".to_string(), |pubkey| pubkey.to_string());
+
+  println!("Slot: {}", transaction.slot);
+  for instruction in decoded.iter() {
+    match &instruction.instruction {
+      Some(aggregator_instruction) => println!("Instruction {}: {:?}", instruction.index, aggregator_instruction),
+      None => println!("Instruction {}: unknown data", instruction.index),
+    }
+    for (position, account) in instruction.accounts.iter().enumerate() {
+      println!("  {}. {}: {}", position, account.role, account_name(account));
+    }
+    for venue_instruction in instruction.venue_instructions.iter() {
+      println!("  Venue {}: {:?}", venue_instruction.program_id, venue_instruction.instruction);
+      for (position, account) in venue_instruction.accounts.iter().enumerate() {
+        println!("    {}. {}: {}", position, account.role, account_name(account));
+      }
+    }
+    for event in instruction.events.iter() {
+      println!("  Event: {:?}", event);
+    }
+  }
+
+  if let Some(err) = meta.err {
+    println!("Error: {:?}", err);
+  }
+  println!("Logs:");
+  for log_message in log_messages.iter() {
+    println!("  {}", log_message);
+  }
+  Ok(())
+}
+
+fn main() {
+  let cli = Cli::parse();
+  let config = Config {
+    rpc_client: RpcClient::new_with_commitment(cli.url.clone(), CommitmentConfig::confirmed()),
+    keypair_path: cli.keypair.clone(),
+    program_id: cli.program_id,
+  };
+
+  let result = match &cli.command {
+    Command::State(command) => process_state(&config, command),
+    Command::Quote { registry, input_mint, output_mint, amount, max_hops, discount_account } => {
+      process_quote(&config, registry, input_mint, output_mint, *amount, *max_hops, *discount_account)
+    },
+    Command::Swap {
+      registry,
+      input_mint,
+      output_mint,
+      amount,
+      slippage_bps,
+      source,
+      destination,
+      fee_vault,
+      discount_account,
+      simulate,
+      native_sol,
+      create_destination,
+      two_legs,
+      sweep_residual,
+    } => process_swap(
+      &config,
+      registry,
+      input_mint,
+      output_mint,
+      *amount,
+      *slippage_bps,
+      *source,
+      *destination,
+      *fee_vault,
+      *discount_account,
+      *simulate,
+      *native_sol,
+      *create_destination,
+      *two_legs,
+      *sweep_residual,
+    ),
+    Command::DecodeTx { signature } => process_decode_tx(&config, signature),
+  };
+
+  if let Err(error) = result {
+    eprintln!("Error: {}", error);
+    std::process::exit(1);
+  }
+}
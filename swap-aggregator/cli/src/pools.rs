@@ -0,0 +1,102 @@
+//! Pricing data of the registry pools, read from chain
+
+use {
+  solana_client::rpc_client::RpcClient,
+  solana_sdk::{
+    account::Account,
+    program_pack::Pack,
+    pubkey::Pubkey,
+  },
+
+  stable_swap_client::state::SwapInfo,
+
+  swap_aggregator::{
+    client::RoutePool,
+    quote::{MercurialPoolState, RaydiumPoolState},
+    registry::RegistryPool,
+    router::{PoolState, RouterPool},
+  },
+
+  std::error::Error,
+};
+
+/// Reads the accounts of `pubkeys`, all of them must exist
+fn get_accounts(rpc_client: &RpcClient, pubkeys: &[Pubkey]) -> Result<Vec<Account>, Box<dyn Error>> {
+  let accounts = rpc_client.get_multiple_accounts(pubkeys)?;
+  pubkeys
+    .iter()
+    .zip(accounts)
+    .map(|(pubkey, account)| account.ok_or_else(|| format!("account {} not found", pubkey).into()))
+    .collect()
+}
+
+fn token_account(account: &Account) -> Result<spl_token::state::Account, Box<dyn Error>> {
+  Ok(spl_token::state::Account::unpack(&account.data)?)
+}
+
+/// Reads the pricing data of a registry pool
+pub fn load_router_pool(rpc_client: &RpcClient, registry_pool: &RegistryPool) -> Result<RouterPool, Box<dyn Error>> {
+  let mut mints = registry_pool.mints.clone();
+
+  let state = match &registry_pool.pool {
+    RoutePool::Raydium(pool) => {
+      let accounts = get_accounts(rpc_client, &[
+        pool.amm_id,
+        pool.amm_open_orders,
+        pool.pool_coin_token_account,
+        pool.pool_pc_token_account,
+      ])?;
+      PoolState::Raydium(RaydiumPoolState::from_account_data(
+        &accounts[0].data,
+        &accounts[1].data,
+        &accounts[2].data,
+        &accounts[3].data,
+      )?)
+    },
+    RoutePool::Saber(pool) => {
+      let accounts = get_accounts(rpc_client, &[pool.swap, pool.token_a_reserve, pool.token_b_reserve])?;
+      PoolState::Saber {
+        swap_info: Box::new(SwapInfo::unpack(&accounts[0].data)?),
+        token_a_amount: token_account(&accounts[1])?.amount,
+        token_b_amount: token_account(&accounts[2])?.amount,
+      }
+    },
+    RoutePool::Mercurial(pool) => {
+      let mut pubkeys = vec![pool.swap];
+      pubkeys.extend_from_slice(&pool.token_accounts);
+      let accounts = get_accounts(rpc_client, &pubkeys)?;
+
+      let token_accounts = accounts[1..]
+        .iter()
+        .map(token_account)
+        .collect::<Result<Vec<_>, _>>()?;
+      // the mints can be left out of the registry
+      if mints.is_empty() {
+        mints = token_accounts.iter().map(|token_account| token_account.mint).collect();
+      }
+
+      let balances: Vec<u64> = token_accounts.iter().map(|token_account| token_account.amount).collect();
+      PoolState::Mercurial(MercurialPoolState::from_account_data(&accounts[0].data, &balances)?)
+    },
+  };
+
+  Ok(RouterPool {
+    pool: registry_pool.pool.clone(),
+    mints,
+    state,
+  })
+}
+
+/// Reads the pricing data of the registry pools, skipping the pools that cannot be read
+pub fn load_router_pools(rpc_client: &RpcClient, registry_pools: &[RegistryPool]) -> Vec<RouterPool> {
+  registry_pools
+    .iter()
+    .filter_map(|registry_pool| match load_router_pool(rpc_client, registry_pool) {
+      Ok(router_pool) => Some(router_pool),
+      Err(error) => {
+        eprintln!("Skipping pool {}: {}", registry_pool.name, error);
+        None
+      },
+    })
+    .collect()
+}
@@ -0,0 +1,403 @@
+//! Confirmed aggregator transactions from an RPC endpoint
+//!
+//! Transactions are fetched in base64 and decoded with the program decoder,
+//! so only the swap events logged by the program are trusted for amounts.
+
+use {
+  crate::store::{ErrorRecord, IndexedTransaction, LegRecord, SwapRecord},
+
+  num_traits::FromPrimitive,
+
+  solana_client::{
+    rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient},
+    rpc_config::RpcTransactionConfig,
+  },
+  solana_sdk::{
+    commitment_config::CommitmentConfig,
+    instruction::{CompiledInstruction, InstructionError},
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction::TransactionError,
+  },
+  solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta,
+    UiInnerInstructions,
+    UiInstruction,
+    UiLoadedAddresses,
+    UiTransactionEncoding,
+  },
+
+  swap_aggregator::{
+    decoder::{decode_transaction, DecodedInstruction, InnerInstructions},
+    error::AggregatorError,
+    event::AggregatorEvent,
+  },
+
+  std::{error::Error, str::FromStr},
+};
+
+/// Signatures returned per `getSignaturesForAddress` request, the RPC maximum
+const SIGNATURES_PAGE: usize = 1000;
+
+/// Signatures of the program after `until`, oldest first
+pub fn new_signatures(
+  rpc_client: &RpcClient,
+  program_id: &Pubkey,
+  until: Option<Signature>,
+) -> Result<Vec<Signature>, Box<dyn Error>> {
+  let mut signatures = Vec::new();
+  let mut before = None;
+  loop {
+    let page = rpc_client.get_signatures_for_address_with_config(program_id, GetConfirmedSignaturesForAddress2Config {
+      before,
+      until,
+      limit: Some(SIGNATURES_PAGE),
+      commitment: Some(CommitmentConfig::confirmed()),
+    })?;
+    let page_len = page.len();
+    for status in page {
+      signatures.push(Signature::from_str(&status.signature)?);
+    }
+    if page_len < SIGNATURES_PAGE {
+      break;
+    }
+    before = signatures.last().copied();
+  }
+  signatures.reverse();
+  Ok(signatures)
+}
+
+/// Fetches and decodes a confirmed transaction
+pub fn fetch_transaction(
+  rpc_client: &RpcClient,
+  program_id: &Pubkey,
+  signature: &Signature,
+) -> Result<IndexedTransaction, Box<dyn Error>> {
+  let transaction = rpc_client.get_transaction_with_config(signature, RpcTransactionConfig {
+    encoding: Some(UiTransactionEncoding::Base64),
+    commitment: Some(CommitmentConfig::confirmed()),
+    max_supported_transaction_version: Some(0),
+  })?;
+  index_transaction(program_id, signature, transaction)
+}
+
+/// Records of a confirmed transaction
+pub fn index_transaction(
+  program_id: &Pubkey,
+  signature: &Signature,
+  transaction: EncodedConfirmedTransactionWithStatusMeta,
+) -> Result<IndexedTransaction, Box<dyn Error>> {
+  let versioned_transaction = transaction.transaction.transaction
+    .decode()
+    .ok_or("cannot decode the transaction")?;
+  let meta = transaction.transaction.meta.ok_or("transaction without status meta")?;
+
+  // compiled indices refer to the static keys, then the loaded writable and readonly addresses
+  let mut account_keys = versioned_transaction.message.static_account_keys().to_vec();
+  let loaded_addresses: Option<UiLoadedAddresses> = meta.loaded_addresses.into();
+  if let Some(loaded_addresses) = loaded_addresses {
+    for address in loaded_addresses.writable.iter().chain(loaded_addresses.readonly.iter()) {
+      account_keys.push(Pubkey::from_str(address)?);
+    }
+  }
+
+  let ui_inner_instructions: Option<Vec<UiInnerInstructions>> = meta.inner_instructions.into();
+  let inner_instructions = ui_inner_instructions
+    .unwrap_or_default()
+    .into_iter()
+    .map(|inner| Ok(InnerInstructions {
+      index: inner.index,
+      instructions: inner.instructions
+        .into_iter()
+        .filter_map(|instruction| match instruction {
+          UiInstruction::Compiled(instruction) => Some(instruction),
+          UiInstruction::Parsed(_) => None,
+        })
+        .map(|instruction| Ok(CompiledInstruction {
+          program_id_index: instruction.program_id_index,
+          accounts: instruction.accounts,
+          data: bs58::decode(&instruction.data).into_vec()?,
+        }))
+        .collect::<Result<_, bs58::decode::Error>>()?,
+    }))
+    .collect::<Result<Vec<_>, bs58::decode::Error>>()?;
+
+  let log_messages: Option<Vec<String>> = meta.log_messages.into();
+  let log_messages = log_messages.unwrap_or_default();
+  let decoded = decode_transaction(
+    program_id,
+    &account_keys,
+    versioned_transaction.message.instructions(),
+    &inner_instructions,
+    &log_messages,
+  );
+
+  Ok(IndexedTransaction {
+    signature: signature.to_string(),
+    slot: transaction.slot,
+    block_time: transaction.block_time,
+    swaps: decoded.iter().flat_map(swap_records).collect(),
+    error: meta.err.map(|error| error_record(program_id, &decoded, &log_messages, error)),
+  })
+}
+
+fn swap_records(decoded: &DecodedInstruction) -> Vec<SwapRecord> {
+  let fee_vault = decoded.accounts
+    .iter()
+    .find(|account| account.role == "fee vault")
+    .and_then(|account| account.pubkey)
+    .map(|pubkey| pubkey.to_string());
+
+  decoded.events
+    .iter()
+    .filter_map(|event| match event {
+      AggregatorEvent::Swap(swap) => Some(swap),
+      _ => None,
+    })
+    .map(|swap| {
+      // each leg swaps the output of the previous one, the first swaps the input left after the fee
+      let mut leg_in = swap.amount_in.saturating_sub(swap.fee);
+      let legs = swap.legs
+        .iter()
+        .map(|leg| {
+          let amount_in = std::mem::replace(&mut leg_in, leg.amount_out);
+          LegRecord { venue: format!("{:?}", leg.venue), amount_in, amount_out: leg.amount_out }
+        })
+        .collect();
+
+      SwapRecord {
+        instruction_index: decoded.index,
+        user: swap.user.to_string(),
+        input_mint: swap.input_mint.to_string(),
+        output_mint: swap.output_mint.to_string(),
+        amount_in: swap.amount_in,
+        fee: swap.fee,
+        fee_vault: fee_vault.clone(),
+        amount_out: swap.amount_out,
+        legs,
+      }
+    })
+    .collect()
+}
+
+/// Program that raised the error, the first to log its failure since the
+/// failure of a CPI is logged again by each caller
+fn failed_program(log_messages: &[String]) -> Option<Pubkey> {
+  log_messages.iter().find_map(|log_message| {
+    let (program, rest) = log_message.strip_prefix("Program ")?.split_once(' ')?;
+    if !rest.starts_with("failed") {
+      return None;
+    }
+    Pubkey::from_str(program).ok()
+  })
+}
+
+fn error_record(
+  program_id: &Pubkey,
+  decoded: &[DecodedInstruction],
+  log_messages: &[String],
+  error: TransactionError,
+) -> ErrorRecord {
+  let failed_program = failed_program(log_messages);
+  let (instruction_index, error_code, aggregator_error) = match &error {
+    TransactionError::InstructionError(index, InstructionError::Custom(code)) => {
+      // a custom code is only an aggregator error when the aggregator itself failed, not a venue it called
+      let aggregator_error = if decoded.iter().any(|decoded| decoded.index == *index) && failed_program == Some(*program_id) {
+        AggregatorError::from_u32(*code).map(|error| format!("{:?}", error))
+      } else {
+        None
+      };
+      (Some(*index), Some(*code), aggregator_error)
+    },
+    TransactionError::InstructionError(index, _) => (Some(*index), None, None),
+    _ => (None, None, None),
+  };
+
+  ErrorRecord {
+    instruction_index,
+    error: error.to_string(),
+    failed_program: failed_program.map(|program| program.to_string()),
+    error_code,
+    aggregator_error,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+
+    base64::{engine::general_purpose::STANDARD, Engine},
+
+    borsh::BorshSerialize,
+
+    solana_sdk::{
+      instruction::Instruction,
+      message::Message,
+      transaction::{Result as TransactionResult, Transaction},
+    },
+    solana_transaction_status::{
+      ConfirmedTransactionWithStatusMeta,
+      TransactionStatusMeta,
+      TransactionWithStatusMeta,
+      VersionedTransactionWithStatusMeta,
+    },
+
+    swap_aggregator::{
+      client::{self, RaydiumPool, RoutePool},
+      event::{SwapEvent, SwapLegEvent},
+      instruction::AggregatorPath,
+    },
+  };
+
+  const BLOCK_TIME: i64 = 1_700_000_000;
+
+  fn raydium_pool() -> RoutePool {
+    RoutePool::Raydium(RaydiumPool {
+      amm_id: Pubkey::new_unique(),
+      amm_authority: Pubkey::new_unique(),
+      amm_open_orders: Pubkey::new_unique(),
+      amm_target_orders: Pubkey::new_unique(),
+      pool_coin_token_account: Pubkey::new_unique(),
+      pool_pc_token_account: Pubkey::new_unique(),
+      serum_program_id: Pubkey::new_unique(),
+      serum_market: Pubkey::new_unique(),
+      serum_bids: Pubkey::new_unique(),
+      serum_asks: Pubkey::new_unique(),
+      serum_event_queue: Pubkey::new_unique(),
+      serum_coin_vault_account: Pubkey::new_unique(),
+      serum_pc_vault_account: Pubkey::new_unique(),
+      serum_vault_signer: Pubkey::new_unique(),
+      program_id: Pubkey::new_unique(),
+    })
+  }
+
+  /// Route swap of `amount_in`, with the accounts of the swap event
+  fn route_swap(program_id: &Pubkey, pool: &RoutePool, amount_in: u64) -> (Instruction, Pubkey) {
+    let fee_vault = Pubkey::new_unique();
+    let instruction = client::route_swap(
+      program_id,
+      &Pubkey::new_unique(),
+      &Pubkey::new_unique(),
+      &Pubkey::new_unique(),
+      &fee_vault,
+      &Pubkey::new_unique(),
+      pool,
+      None,
+      amount_in,
+      0,
+    ).unwrap();
+    (instruction, fee_vault)
+  }
+
+  /// Confirmed transaction of `instruction` as the RPC returns it in base64
+  fn confirmed_transaction(
+    instruction: Instruction,
+    status: TransactionResult<()>,
+    log_messages: Vec<String>,
+  ) -> EncodedConfirmedTransactionWithStatusMeta {
+    let transaction = Transaction::new_unsigned(Message::new(&[instruction], Some(&Pubkey::new_unique())));
+    ConfirmedTransactionWithStatusMeta {
+      slot: 7,
+      tx_with_meta: TransactionWithStatusMeta::Complete(VersionedTransactionWithStatusMeta {
+        transaction: transaction.into(),
+        meta: TransactionStatusMeta { status, log_messages: Some(log_messages), ..TransactionStatusMeta::default() },
+      }),
+      block_time: Some(BLOCK_TIME),
+    }.encode(UiTransactionEncoding::Base64, Some(0)).unwrap()
+  }
+
+  #[test]
+  fn indexes_the_swap_events() {
+    let program_id = Pubkey::new_unique();
+    let pool = raydium_pool();
+    let (instruction, fee_vault) = route_swap(&program_id, &pool, u64::MAX);
+    let event = SwapEvent {
+      user: Pubkey::new_unique(),
+      input_mint: Pubkey::new_unique(),
+      output_mint: Pubkey::new_unique(),
+      amount_in: u64::MAX,
+      fee: 1000,
+      legs: vec![SwapLegEvent { venue: AggregatorPath::Raydium, amount_out: u64::MAX - 5000 }],
+      amount_out: u64::MAX - 5000,
+    };
+    let log_messages = vec![
+      format!("Program {} invoke [1]", program_id),
+      format!("Program data: {}", STANDARD.encode(AggregatorEvent::Swap(event.clone()).try_to_vec().unwrap())),
+      format!("Program {} success", program_id),
+    ];
+
+    let signature = Signature::new_unique();
+    let transaction = confirmed_transaction(instruction, Ok(()), log_messages);
+    let indexed = index_transaction(&program_id, &signature, transaction).unwrap();
+
+    assert_eq!(indexed, IndexedTransaction {
+      signature: signature.to_string(),
+      slot: 7,
+      block_time: Some(BLOCK_TIME),
+      swaps: vec![SwapRecord {
+        instruction_index: 0,
+        user: event.user.to_string(),
+        input_mint: event.input_mint.to_string(),
+        output_mint: event.output_mint.to_string(),
+        amount_in: u64::MAX,
+        fee: 1000,
+        fee_vault: Some(fee_vault.to_string()),
+        amount_out: u64::MAX - 5000,
+        legs: vec![LegRecord { venue: "Raydium".to_string(), amount_in: u64::MAX - 1000, amount_out: u64::MAX - 5000 }],
+      }],
+      error: None,
+    });
+  }
+
+  #[test]
+  fn names_the_errors_of_the_aggregator_only() {
+    let program_id = Pubkey::new_unique();
+    let pool = raydium_pool();
+    let venue_program_id = match &pool {
+      RoutePool::Raydium(pool) => pool.program_id,
+      _ => unreachable!(),
+    };
+    let code = AggregatorError::ProgramPaused as u32;
+    let error = TransactionError::InstructionError(0, InstructionError::Custom(code));
+
+    let (instruction, _) = route_swap(&program_id, &pool, 100);
+    let log_messages = vec![
+      format!("Program {} invoke [1]", program_id),
+      format!("Program {} failed: custom program error: {:#x}", program_id, code),
+    ];
+    let transaction = confirmed_transaction(instruction, Err(error.clone()), log_messages);
+    let indexed = index_transaction(&program_id, &Signature::new_unique(), transaction).unwrap();
+    assert_eq!(indexed.error, Some(ErrorRecord {
+      instruction_index: Some(0),
+      error: error.to_string(),
+      failed_program: Some(program_id.to_string()),
+      error_code: Some(code),
+      aggregator_error: Some("ProgramPaused".to_string()),
+    }));
+
+    // the same code raised by the venue is the venue's error
+    let (instruction, _) = route_swap(&program_id, &pool, 100);
+    let log_messages = vec![
+      format!("Program {} invoke [1]", program_id),
+      format!("Program {} invoke [2]", venue_program_id),
+      format!("Program {} failed: custom program error: {:#x}", venue_program_id, code),
+      format!("Program {} failed: custom program error: {:#x}", program_id, code),
+    ];
+    let transaction = confirmed_transaction(instruction, Err(error.clone()), log_messages);
+    let indexed = index_transaction(&program_id, &Signature::new_unique(), transaction).unwrap();
+    assert_eq!(indexed.error, Some(ErrorRecord {
+      instruction_index: Some(0),
+      error: error.to_string(),
+      failed_program: Some(venue_program_id.to_string()),
+      error_code: Some(code),
+      aggregator_error: None,
+    }));
+
+    // without logs the failing program is unknown
+    let (instruction, _) = route_swap(&program_id, &pool, 100);
+    let transaction = confirmed_transaction(instruction, Err(error.clone()), vec![]);
+    let indexed = index_transaction(&program_id, &Signature::new_unique(), transaction).unwrap();
+    assert_eq!(indexed.error.unwrap().aggregator_error, None);
+  }
+}
@@ -0,0 +1,150 @@
+//! SQLite indexer of swap aggregator transactions
+//!
+//! `sync` reads the confirmed transactions of the program from an RPC
+//! endpoint, a local test validator works as well as a cluster, and stores
+//! their swaps, fees, venue legs and errors. Each sync resumes after the last
+//! stored transaction. The other commands are the built-in reports.
+
+mod fetch;
+mod store;
+
+use {
+  clap::{Parser, Subcommand},
+
+  solana_client::rpc_client::RpcClient,
+  solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature},
+
+  store::Store,
+
+  std::{error::Error, path::PathBuf, str::FromStr},
+};
+
+type IndexerResult = Result<(), Box<dyn Error>>;
+
+#[derive(Parser)]
+#[clap(name = "swap-aggregator-indexer", version, about = "SQLite indexer of swap aggregator transactions")]
+struct Cli {
+  /// SQLite database file, created on first use
+  #[clap(long, global = true, default_value = "swap-aggregator.sqlite")]
+  db: PathBuf,
+
+  #[clap(subcommand)]
+  command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+  /// Indexes the transactions confirmed since the last sync
+  Sync {
+    /// RPC URL of the cluster
+    #[clap(long, short = 'u', default_value = "http://localhost:8899")]
+    url: String,
+
+    /// Swap aggregator program id
+    #[clap(long)]
+    program_id: Pubkey,
+
+    /// Maximum number of transactions to index, the oldest first
+    #[clap(long)]
+    limit: Option<usize>,
+  },
+
+  /// Swapped volume per UTC day and pair
+  DailyVolume,
+
+  /// Aggregator fees per input mint
+  FeeRevenue,
+
+  /// Swapped volume per UTC day, venue and pair
+  VenueVolume,
+}
+
+fn process_sync(store: &mut Store, url: &str, program_id: &Pubkey, limit: Option<usize>) -> IndexerResult {
+  let rpc_client = RpcClient::new_with_commitment(url.to_string(), CommitmentConfig::confirmed());
+
+  let until = store.newest_signature()?.map(|signature| Signature::from_str(&signature)).transpose()?;
+  let mut signatures = fetch::new_signatures(&rpc_client, program_id, until)?;
+  if let Some(limit) = limit {
+    signatures.truncate(limit);
+  }
+
+  let (mut swaps, mut errors) = (0, 0);
+  for signature in &signatures {
+    let transaction = fetch::fetch_transaction(&rpc_client, program_id, signature)?;
+    swaps += transaction.swaps.len();
+    errors += transaction.error.is_some() as usize;
+    store.insert(&transaction)?;
+  }
+
+  println!("Indexed {} transactions, {} swaps, {} errors", signatures.len(), swaps, errors);
+  Ok(())
+}
+
+/// Prints rows as a markdown table
+fn print_table(headers: &[&str], rows: Vec<Vec<String>>) {
+  println!("| {} |", headers.join(" | "));
+  println!("|{}", "---|".repeat(headers.len()));
+  for row in rows {
+    println!("| {} |", row.join(" | "));
+  }
+}
+
+fn process_daily_volume(store: &Store) -> IndexerResult {
+  let rows = store.daily_volume()?
+    .into_iter()
+    .map(|volume| vec![
+      volume.day,
+      volume.input_mint,
+      volume.output_mint,
+      volume.swaps.to_string(),
+      volume.amount_in.to_string(),
+      volume.amount_out.to_string(),
+    ])
+    .collect();
+  print_table(&["day", "input mint", "output mint", "swaps", "amount in", "amount out"], rows);
+  Ok(())
+}
+
+fn process_fee_revenue(store: &Store) -> IndexerResult {
+  let rows = store.fee_revenue()?
+    .into_iter()
+    .map(|revenue| vec![revenue.mint, revenue.swaps.to_string(), revenue.fees.to_string()])
+    .collect();
+  print_table(&["mint", "swaps", "fees"], rows);
+  Ok(())
+}
+
+fn process_venue_volume(store: &Store) -> IndexerResult {
+  let rows = store.venue_volume()?
+    .into_iter()
+    .map(|volume| vec![
+      volume.day,
+      volume.venue,
+      volume.input_mint,
+      volume.output_mint,
+      volume.legs.to_string(),
+      volume.amount_in.to_string(),
+      volume.amount_out.to_string(),
+    ])
+    .collect();
+  print_table(&["day", "venue", "input mint", "output mint", "legs", "amount in", "amount out"], rows);
+  Ok(())
+}
+
+fn main() {
+  let cli = Cli::parse();
+
+  let result = Store::open(&cli.db)
+    .map_err(Box::<dyn Error>::from)
+    .and_then(|mut store| match &cli.command {
+      Command::Sync { url, program_id, limit } => process_sync(&mut store, url, program_id, *limit),
+      Command::DailyVolume => process_daily_volume(&store),
+      Command::FeeRevenue => process_fee_revenue(&store),
+      Command::VenueVolume => process_venue_volume(&store),
+    });
+
+  if let Err(error) = result {
+    eprintln!("Error: {}", error);
+    std::process::exit(1);
+  }
+}
@@ -0,0 +1,413 @@
+//! SQLite storage of the indexed transactions
+//!
+//! Pubkeys are stored in base58 and amounts in native units. SQLite integers
+//! are signed 64 bit and can't hold every u64 amount, so amounts are stored as
+//! decimal text and the reports add them up in Rust.
+
+use {
+  rusqlite::{params, types::Type, Connection, OptionalExtension, Row},
+
+  std::{collections::BTreeMap, path::Path},
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS transactions (
+  signature TEXT PRIMARY KEY,
+  slot INTEGER NOT NULL,
+  block_time INTEGER,
+  succeeded INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS swaps (
+  signature TEXT NOT NULL REFERENCES transactions (signature),
+  instruction_index INTEGER NOT NULL,
+  block_time INTEGER,
+  user TEXT NOT NULL,
+  input_mint TEXT NOT NULL,
+  output_mint TEXT NOT NULL,
+  amount_in TEXT NOT NULL,
+  fee TEXT NOT NULL,
+  fee_vault TEXT,
+  amount_out TEXT NOT NULL,
+  PRIMARY KEY (signature, instruction_index)
+);
+
+CREATE TABLE IF NOT EXISTS swap_legs (
+  signature TEXT NOT NULL,
+  instruction_index INTEGER NOT NULL,
+  leg_index INTEGER NOT NULL,
+  venue TEXT NOT NULL,
+  amount_in TEXT NOT NULL,
+  amount_out TEXT NOT NULL,
+  PRIMARY KEY (signature, instruction_index, leg_index),
+  FOREIGN KEY (signature, instruction_index) REFERENCES swaps (signature, instruction_index)
+);
+
+CREATE TABLE IF NOT EXISTS errors (
+  signature TEXT PRIMARY KEY REFERENCES transactions (signature),
+  block_time INTEGER,
+  instruction_index INTEGER,
+  error TEXT NOT NULL,
+  failed_program TEXT,
+  error_code INTEGER,
+  aggregator_error TEXT
+);
+";
+
+/// Transaction of the aggregator program, as stored
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexedTransaction {
+  pub signature: String,
+  pub slot: u64,
+  pub block_time: Option<i64>,
+  pub swaps: Vec<SwapRecord>,
+  pub error: Option<ErrorRecord>,
+}
+
+/// Swap event of a top level aggregator instruction
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwapRecord {
+  pub instruction_index: u8,
+  pub user: String,
+  pub input_mint: String,
+  pub output_mint: String,
+  /// Amount taken from the source, fee included
+  pub amount_in: u64,
+  /// Aggregator fee, in the input mint
+  pub fee: u64,
+  pub fee_vault: Option<String>,
+  pub amount_out: u64,
+  pub legs: Vec<LegRecord>,
+}
+
+/// Leg of a swap, through one venue
+#[derive(Clone, Debug, PartialEq)]
+pub struct LegRecord {
+  pub venue: String,
+  pub amount_in: u64,
+  pub amount_out: u64,
+}
+
+/// Error of a failed transaction
+#[derive(Clone, Debug, PartialEq)]
+pub struct ErrorRecord {
+  /// Failed instruction, when the error belongs to one
+  pub instruction_index: Option<u8>,
+  pub error: String,
+  /// Program that raised the error, from the logs
+  pub failed_program: Option<String>,
+  /// Custom error code, of whichever program failed
+  pub error_code: Option<u32>,
+  /// Aggregator error of the custom error code, when the aggregator itself failed
+  pub aggregator_error: Option<String>,
+}
+
+/// Swapped volume of a pair over a UTC day
+#[derive(Clone, Debug, PartialEq)]
+pub struct DailyVolume {
+  pub day: String,
+  pub input_mint: String,
+  pub output_mint: String,
+  pub swaps: u64,
+  pub amount_in: u128,
+  pub amount_out: u128,
+}
+
+/// Fees collected in a mint
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeeRevenue {
+  pub mint: String,
+  pub swaps: u64,
+  pub fees: u128,
+}
+
+/// Volume routed through a venue over a UTC day, for the pair of the swaps
+#[derive(Clone, Debug, PartialEq)]
+pub struct VenueVolume {
+  pub day: String,
+  pub venue: String,
+  pub input_mint: String,
+  pub output_mint: String,
+  pub legs: u64,
+  pub amount_in: u128,
+  pub amount_out: u128,
+}
+
+/// Reads an amount stored as decimal text
+fn amount(row: &Row, index: usize) -> rusqlite::Result<u64> {
+  row.get::<_, String>(index)?
+    .parse()
+    .map_err(|error| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(error)))
+}
+
+pub struct Store {
+  connection: Connection,
+}
+
+impl Store {
+  /// Opens the database, creating the tables on first use
+  pub fn open(path: &Path) -> rusqlite::Result<Self> {
+    Self::with_connection(Connection::open(path)?)
+  }
+
+  fn with_connection(connection: Connection) -> rusqlite::Result<Self> {
+    connection.execute_batch(SCHEMA)?;
+    Ok(Self { connection })
+  }
+
+  /// Signature of the last indexed transaction, where the next sync resumes
+  pub fn newest_signature(&self) -> rusqlite::Result<Option<String>> {
+    self.connection
+      .query_row("SELECT signature FROM transactions ORDER BY slot DESC, rowid DESC LIMIT 1", [], |row| row.get(0))
+      .optional()
+  }
+
+  /// Stores a transaction with its swaps and error, a transaction already stored is left as is
+  pub fn insert(&mut self, transaction: &IndexedTransaction) -> rusqlite::Result<()> {
+    let db_transaction = self.connection.transaction()?;
+
+    let inserted = db_transaction.execute(
+      "INSERT OR IGNORE INTO transactions (signature, slot, block_time, succeeded) VALUES (?1, ?2, ?3, ?4)",
+      params![transaction.signature, transaction.slot, transaction.block_time, transaction.error.is_none()],
+    )?;
+    if inserted == 0 {
+      return Ok(());
+    }
+
+    for swap in &transaction.swaps {
+      db_transaction.execute(
+        "INSERT INTO swaps (signature, instruction_index, block_time, user, input_mint, output_mint, amount_in, fee, fee_vault, amount_out)
+          VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+          transaction.signature,
+          swap.instruction_index,
+          transaction.block_time,
+          swap.user,
+          swap.input_mint,
+          swap.output_mint,
+          swap.amount_in.to_string(),
+          swap.fee.to_string(),
+          swap.fee_vault,
+          swap.amount_out.to_string(),
+        ],
+      )?;
+      for (leg_index, leg) in swap.legs.iter().enumerate() {
+        db_transaction.execute(
+          "INSERT INTO swap_legs (signature, instruction_index, leg_index, venue, amount_in, amount_out)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+          params![
+            transaction.signature,
+            swap.instruction_index,
+            leg_index,
+            leg.venue,
+            leg.amount_in.to_string(),
+            leg.amount_out.to_string(),
+          ],
+        )?;
+      }
+    }
+
+    if let Some(error) = &transaction.error {
+      db_transaction.execute(
+        "INSERT INTO errors (signature, block_time, instruction_index, error, failed_program, error_code, aggregator_error)
+          VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+          transaction.signature,
+          transaction.block_time,
+          error.instruction_index,
+          error.error,
+          error.failed_program,
+          error.error_code,
+          error.aggregator_error,
+        ],
+      )?;
+    }
+
+    db_transaction.commit()
+  }
+
+  /// Volume per UTC day and direction of a pair
+  pub fn daily_volume(&self) -> rusqlite::Result<Vec<DailyVolume>> {
+    let mut statement = self.connection.prepare(
+      "SELECT date(block_time, 'unixepoch'), input_mint, output_mint, amount_in, amount_out
+        FROM swaps
+        WHERE block_time IS NOT NULL",
+    )?;
+    let mut rows = statement.query([])?;
+    let mut volumes = BTreeMap::new();
+    while let Some(row) = rows.next()? {
+      let key: (String, String, String) = (row.get(0)?, row.get(1)?, row.get(2)?);
+      let (swaps, amount_in, amount_out) = volumes.entry(key).or_insert((0u64, 0u128, 0u128));
+      *swaps += 1;
+      *amount_in += amount(row, 3)? as u128;
+      *amount_out += amount(row, 4)? as u128;
+    }
+
+    Ok(volumes
+      .into_iter()
+      .map(|((day, input_mint, output_mint), (swaps, amount_in, amount_out))| DailyVolume {
+        day,
+        input_mint,
+        output_mint,
+        swaps,
+        amount_in,
+        amount_out,
+      })
+      .collect())
+  }
+
+  /// Fees collected per input mint
+  pub fn fee_revenue(&self) -> rusqlite::Result<Vec<FeeRevenue>> {
+    let mut statement = self.connection.prepare("SELECT input_mint, fee FROM swaps")?;
+    let mut rows = statement.query([])?;
+    let mut revenues = BTreeMap::new();
+    while let Some(row) = rows.next()? {
+      let (swaps, fees) = revenues.entry(row.get::<_, String>(0)?).or_insert((0u64, 0u128));
+      *swaps += 1;
+      *fees += amount(row, 1)? as u128;
+    }
+
+    Ok(revenues
+      .into_iter()
+      .map(|(mint, (swaps, fees))| FeeRevenue { mint, swaps, fees })
+      .collect())
+  }
+
+  /// Legs per UTC day, venue and pair, the leg amounts are in the pair mints for single leg routes only
+  pub fn venue_volume(&self) -> rusqlite::Result<Vec<VenueVolume>> {
+    let mut statement = self.connection.prepare(
+      "SELECT date(swaps.block_time, 'unixepoch'), swap_legs.venue, swaps.input_mint, swaps.output_mint,
+          swap_legs.amount_in, swap_legs.amount_out
+        FROM swap_legs
+        JOIN swaps USING (signature, instruction_index)
+        WHERE swaps.block_time IS NOT NULL",
+    )?;
+    let mut rows = statement.query([])?;
+    let mut volumes = BTreeMap::new();
+    while let Some(row) = rows.next()? {
+      let key: (String, String, String, String) = (row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?);
+      let (legs, amount_in, amount_out) = volumes.entry(key).or_insert((0u64, 0u128, 0u128));
+      *legs += 1;
+      *amount_in += amount(row, 4)? as u128;
+      *amount_out += amount(row, 5)? as u128;
+    }
+
+    Ok(volumes
+      .into_iter()
+      .map(|((day, venue, input_mint, output_mint), (legs, amount_in, amount_out))| VenueVolume {
+        day,
+        venue,
+        input_mint,
+        output_mint,
+        legs,
+        amount_in,
+        amount_out,
+      })
+      .collect())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// 2023-11-14 and the next day, in seconds
+  const DAY_1: i64 = 1_700_000_000;
+  const DAY_2: i64 = DAY_1 + 24 * 60 * 60;
+
+  fn swap(instruction_index: u8, input_mint: &str, amount_in: u64, fee: u64, amount_out: u64) -> SwapRecord {
+    SwapRecord {
+      instruction_index,
+      user: "user".to_string(),
+      input_mint: input_mint.to_string(),
+      output_mint: "output".to_string(),
+      amount_in,
+      fee,
+      fee_vault: None,
+      amount_out,
+      legs: vec![LegRecord { venue: "Raydium".to_string(), amount_in: amount_in - fee, amount_out }],
+    }
+  }
+
+  fn transaction(signature: &str, slot: u64, block_time: i64, swaps: Vec<SwapRecord>) -> IndexedTransaction {
+    IndexedTransaction { signature: signature.to_string(), slot, block_time: Some(block_time), swaps, error: None }
+  }
+
+  fn store() -> Store {
+    Store::with_connection(Connection::open_in_memory().unwrap()).unwrap()
+  }
+
+  #[test]
+  fn reports_add_up_amounts_past_the_sqlite_integers() {
+    let mut store = store();
+    store.insert(&transaction("a", 1, DAY_1, vec![
+      swap(0, "input", u64::MAX, 10, u64::MAX - 20),
+      swap(1, "input", u64::MAX, 10, u64::MAX - 20),
+    ])).unwrap();
+    store.insert(&transaction("b", 2, DAY_2, vec![swap(0, "input", 100, 1, 90)])).unwrap();
+
+    let max = u64::MAX as u128;
+    assert_eq!(store.daily_volume().unwrap(), vec![
+      DailyVolume {
+        day: "2023-11-14".to_string(),
+        input_mint: "input".to_string(),
+        output_mint: "output".to_string(),
+        swaps: 2,
+        amount_in: 2 * max,
+        amount_out: 2 * (max - 20),
+      },
+      DailyVolume {
+        day: "2023-11-15".to_string(),
+        input_mint: "input".to_string(),
+        output_mint: "output".to_string(),
+        swaps: 1,
+        amount_in: 100,
+        amount_out: 90,
+      },
+    ]);
+    assert_eq!(store.fee_revenue().unwrap(), vec![FeeRevenue { mint: "input".to_string(), swaps: 3, fees: 21 }]);
+    assert_eq!(store.venue_volume().unwrap()[0], VenueVolume {
+      day: "2023-11-14".to_string(),
+      venue: "Raydium".to_string(),
+      input_mint: "input".to_string(),
+      output_mint: "output".to_string(),
+      legs: 2,
+      amount_in: 2 * (max - 10),
+      amount_out: 2 * (max - 20),
+    });
+  }
+
+  #[test]
+  fn insert_keeps_the_first_copy_and_resumes_after_the_newest() {
+    let mut store = store();
+    assert_eq!(store.newest_signature().unwrap(), None);
+
+    store.insert(&transaction("a", 1, DAY_1, vec![swap(0, "input", 100, 1, 90)])).unwrap();
+    store.insert(&transaction("b", 2, DAY_1, vec![])).unwrap();
+    store.insert(&transaction("a", 1, DAY_1, vec![swap(0, "input", 500, 5, 400)])).unwrap();
+
+    assert_eq!(store.newest_signature().unwrap(), Some("b".to_string()));
+    assert_eq!(store.fee_revenue().unwrap(), vec![FeeRevenue { mint: "input".to_string(), swaps: 1, fees: 1 }]);
+  }
+
+  #[test]
+  fn insert_stores_the_error() {
+    let mut store = store();
+    let mut failed = transaction("a", 1, DAY_1, vec![]);
+    failed.error = Some(ErrorRecord {
+      instruction_index: Some(0),
+      error: "custom program error".to_string(),
+      failed_program: Some("venue".to_string()),
+      error_code: Some(0x100),
+      aggregator_error: None,
+    });
+    store.insert(&failed).unwrap();
+
+    let (succeeded, failed_program, error_code): (bool, String, u32) = store.connection.query_row(
+      "SELECT succeeded, failed_program, error_code FROM transactions JOIN errors USING (signature)",
+      [],
+      |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    ).unwrap();
+    assert_eq!((succeeded, failed_program.as_str(), error_code), (false, "venue", 0x100));
+  }
+}
@@ -0,0 +1,243 @@
+//! Offline replay of aggregator instructions
+//!
+//! Loads dumped mainnet accounts and locally supplied program `.so` files
+//! into a `ProgramTest` bank, executes the instructions of a failed route and
+//! prints the logs, the consumed compute units and the balance changes of the
+//! writable accounts. Nothing is fetched from a cluster.
+//!
+//! The signers of the replayed instructions are substituted by local keypairs,
+//! unless their keypair file is given with `--signer`. The substitution also
+//! rewrites the owner, delegate and close authority of the snapshot token
+//! accounts, so the token program accepts the local signatures.
+
+mod snapshot;
+
+use {
+  clap::Parser,
+
+  solana_program_test::{processor, BanksClient, ProgramTest},
+  solana_sdk::{
+    account::Account,
+    bpf_loader,
+    compute_budget::ComputeBudgetInstruction,
+    instruction::Instruction,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{read_keypair_file, Keypair, Signer},
+    transaction::Transaction,
+  },
+
+  snapshot::{load_instructions, load_snapshot, SnapshotAccount},
+
+  std::{collections::HashMap, error::Error, fs, path::PathBuf, str::FromStr},
+};
+
+type ReplayResult = Result<(), Box<dyn Error>>;
+
+#[derive(Parser)]
+#[clap(name = "swap-aggregator-replay", version, about = "Offline replay of swap aggregator instructions")]
+struct Cli {
+  /// Swap aggregator program id
+  #[clap(long)]
+  program_id: Pubkey,
+
+  /// Built swap aggregator program, the native processor of this build by default
+  #[clap(long)]
+  aggregator_so: Option<PathBuf>,
+
+  /// Program deployed from a local file, as `<program id>=<path to .so>`
+  #[clap(long = "program", value_parser = parse_program)]
+  programs: Vec<(Pubkey, PathBuf)>,
+
+  /// Account files or directories of account files
+  #[clap(long = "accounts", required = true)]
+  accounts: Vec<PathBuf>,
+
+  /// Instructions to replay, in one transaction
+  #[clap(long)]
+  instruction: PathBuf,
+
+  /// Keypair file of a signer of the instructions, kept instead of substituted
+  #[clap(long = "signer")]
+  signers: Vec<PathBuf>,
+
+  /// Compute unit limit of the transaction
+  #[clap(long)]
+  compute_unit_limit: Option<u32>,
+}
+
+fn parse_program(value: &str) -> Result<(Pubkey, PathBuf), String> {
+  let (program_id, path) = value
+    .split_once('=')
+    .ok_or_else(|| format!("expected <program id>=<path>, got {}", value))?;
+  let program_id = Pubkey::from_str(program_id).map_err(|_| format!("invalid program id {}", program_id))?;
+  Ok((program_id, PathBuf::from(path)))
+}
+
+/// Executable account of a program deployed with the non upgradeable loader
+fn program_account(path: &PathBuf) -> Result<Account, Box<dyn Error>> {
+  let data = fs::read(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+  Ok(Account {
+    lamports: Rent::default().minimum_balance(data.len()).max(1),
+    data,
+    owner: bpf_loader::id(),
+    executable: true,
+    rent_epoch: 0,
+  })
+}
+
+fn substitute_key(key: &mut Pubkey, substitutes: &HashMap<Pubkey, Pubkey>) {
+  if let Some(substitute) = substitutes.get(key) {
+    *key = *substitute;
+  }
+}
+
+/// Moves the snapshot accounts and the token account authorities of the substituted signers
+fn substitute_accounts(accounts: &mut [SnapshotAccount], substitutes: &HashMap<Pubkey, Pubkey>) {
+  for snapshot_account in accounts.iter_mut() {
+    substitute_key(&mut snapshot_account.pubkey, substitutes);
+
+    let account = &mut snapshot_account.account;
+    if account.owner != spl_token::id() || account.data.len() != spl_token::state::Account::LEN {
+      continue;
+    }
+    let mut token = match spl_token::state::Account::unpack(&account.data) {
+      Ok(token) => token,
+      Err(_) => continue,
+    };
+    substitute_key(&mut token.owner, substitutes);
+    if let COption::Some(delegate) = &mut token.delegate {
+      substitute_key(delegate, substitutes);
+    }
+    if let COption::Some(close_authority) = &mut token.close_authority {
+      substitute_key(close_authority, substitutes);
+    }
+    spl_token::state::Account::pack(token, &mut account.data).unwrap();
+  }
+}
+
+/// Lamports and token amount of an account, `None` when the account does not exist
+async fn balance(banks_client: &mut BanksClient, pubkey: &Pubkey) -> Result<Option<(u64, Option<u64>)>, Box<dyn Error>> {
+  let account = banks_client.get_account(*pubkey).await?;
+  Ok(account.map(|account| {
+    let token_amount = (account.owner == spl_token::id())
+      .then(|| spl_token::state::Account::unpack(&account.data).ok())
+      .flatten()
+      .map(|token| token.amount);
+    (account.lamports, token_amount)
+  }))
+}
+
+fn signed_change(before: u64, after: u64) -> String {
+  if after >= before {
+    format!("+{}", after - before)
+  } else {
+    format!("-{}", before - after)
+  }
+}
+
+async fn replay(cli: &Cli) -> ReplayResult {
+  let mut instructions = load_instructions(&cli.instruction)?;
+  let mut accounts = load_snapshot(&cli.accounts)?;
+
+  // every signer is either kept with its keypair or substituted by a local one
+  let mut signers = cli.signers
+    .iter()
+    .map(|path| read_keypair_file(path).map_err(|error| format!("cannot read keypair {}: {}", path.display(), error)))
+    .collect::<Result<Vec<Keypair>, _>>()?;
+  let mut substitutes = HashMap::new();
+  for meta in instructions.iter().flat_map(|instruction| instruction.accounts.iter()) {
+    if !meta.is_signer || substitutes.contains_key(&meta.pubkey) || signers.iter().any(|signer| signer.pubkey() == meta.pubkey) {
+      continue;
+    }
+    let substitute = Keypair::new();
+    println!("Signer {} substituted by {}", meta.pubkey, substitute.pubkey());
+    substitutes.insert(meta.pubkey, substitute.pubkey());
+    signers.push(substitute);
+  }
+  for meta in instructions.iter_mut().flat_map(|instruction| instruction.accounts.iter_mut()) {
+    substitute_key(&mut meta.pubkey, &substitutes);
+  }
+  substitute_accounts(&mut accounts, &substitutes);
+
+  let mut program_test = ProgramTest::default();
+  program_test.prefer_bpf(false);
+  match &cli.aggregator_so {
+    Some(path) => program_test.add_account(cli.program_id, program_account(path)?),
+    None => program_test.add_program("swap_aggregator", cli.program_id, processor!(swap_aggregator::process_instruction)),
+  }
+  for (program_id, path) in &cli.programs {
+    program_test.add_account(*program_id, program_account(path)?);
+  }
+  for snapshot_account in &accounts {
+    program_test.add_account(snapshot_account.pubkey, snapshot_account.account.clone());
+  }
+  if let Some(compute_unit_limit) = cli.compute_unit_limit {
+    program_test.set_compute_max_units(compute_unit_limit as u64);
+    instructions.insert(0, ComputeBudgetInstruction::set_compute_unit_limit(compute_unit_limit));
+  }
+
+  let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+  let mut writable_accounts: Vec<Pubkey> = Vec::new();
+  for meta in instructions.iter().flat_map(|instruction| instruction.accounts.iter()) {
+    if meta.is_writable && !writable_accounts.contains(&meta.pubkey) {
+      writable_accounts.push(meta.pubkey);
+    }
+  }
+  let mut balances_before = Vec::with_capacity(writable_accounts.len());
+  for pubkey in &writable_accounts {
+    balances_before.push(balance(&mut banks_client, pubkey).await?);
+  }
+
+  let mut transaction_signers: Vec<&Keypair> = vec![&payer];
+  transaction_signers.extend(signers.iter().filter(|signer| {
+    instructions
+      .iter()
+      .flat_map(|instruction: &Instruction| instruction.accounts.iter())
+      .any(|meta| meta.is_signer && meta.pubkey == signer.pubkey())
+  }));
+  let transaction = Transaction::new_signed_with_payer(&instructions, Some(&payer.pubkey()), &transaction_signers, recent_blockhash);
+  let outcome = banks_client.process_transaction_with_metadata(transaction).await?;
+
+  println!("Logs:");
+  if let Some(metadata) = &outcome.metadata {
+    for log_message in &metadata.log_messages {
+      println!("  {}", log_message);
+    }
+    println!("Compute units: {}", metadata.compute_units_consumed);
+  }
+  match &outcome.result {
+    Ok(()) => println!("Result: success"),
+    Err(error) => println!("Result: {:?}", error),
+  }
+
+  println!("Balance changes:");
+  for (pubkey, before) in writable_accounts.iter().zip(balances_before) {
+    let after = balance(&mut banks_client, pubkey).await?;
+    let (lamports_before, token_before) = before.unwrap_or((0, None));
+    let (lamports_after, token_after) = after.unwrap_or((0, None));
+    if lamports_before != lamports_after {
+      println!("  {} lamports {}", pubkey, signed_change(lamports_before, lamports_after));
+    }
+    if token_before != token_after {
+      println!(
+        "  {} tokens {}",
+        pubkey,
+        signed_change(token_before.unwrap_or_default(), token_after.unwrap_or_default()),
+      );
+    }
+  }
+  Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+  let cli = Cli::parse();
+  if let Err(error) = replay(&cli).await {
+    eprintln!("Error: {}", error);
+    std::process::exit(1);
+  }
+}
@@ -0,0 +1,189 @@
+//! Account snapshot and instruction files
+//!
+//! An account file holds one account or an array of accounts, either as
+//!
+//! ```json
+//! { "pubkey": "...", "owner": "...", "lamports": 2039280, "data": "<base64>", "executable": false }
+//! ```
+//!
+//! or as printed by `solana account --output json`. A snapshot is a list of
+//! such files, or of directories whose `.json` files are all read.
+
+use {
+  base64::{engine::general_purpose::STANDARD, Engine},
+
+  serde::Deserialize,
+
+  solana_sdk::{
+    account::Account,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+  },
+
+  std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+  },
+};
+
+/// Account data, a base64 string or `[data, "base64"]`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AccountData {
+  Base64(String),
+  Encoded(String, String),
+}
+
+impl AccountData {
+  fn decode(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+    match self {
+      Self::Base64(data) => Ok(STANDARD.decode(data)?),
+      Self::Encoded(data, encoding) if encoding == "base64" => Ok(STANDARD.decode(data)?),
+      Self::Encoded(_, encoding) => Err(format!("unsupported account data encoding {}", encoding).into()),
+    }
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UiAccount {
+  owner: String,
+  lamports: u64,
+  data: AccountData,
+  #[serde(default)]
+  executable: bool,
+  #[serde(default)]
+  rent_epoch: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AccountEntry {
+  /// `solana account --output json`
+  Cli { pubkey: String, account: UiAccount },
+  Flat {
+    pubkey: String,
+    #[serde(flatten)]
+    account: UiAccount,
+  },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AccountFile {
+  Many(Vec<AccountEntry>),
+  One(AccountEntry),
+}
+
+/// Account of a snapshot
+pub struct SnapshotAccount {
+  pub pubkey: Pubkey,
+  pub account: Account,
+}
+
+fn parse_pubkey(value: &str) -> Result<Pubkey, Box<dyn Error>> {
+  Pubkey::from_str(value).map_err(|_| format!("invalid pubkey {}", value).into())
+}
+
+fn load_file(path: &Path) -> Result<Vec<SnapshotAccount>, Box<dyn Error>> {
+  let file: AccountFile = serde_json::from_str(&fs::read_to_string(path)?)
+    .map_err(|error| format!("{}: {}", path.display(), error))?;
+  let entries = match file {
+    AccountFile::Many(entries) => entries,
+    AccountFile::One(entry) => vec![entry],
+  };
+
+  entries
+    .into_iter()
+    .map(|entry| {
+      let (pubkey, account) = match entry {
+        AccountEntry::Cli { pubkey, account } | AccountEntry::Flat { pubkey, account } => (pubkey, account),
+      };
+      Ok(SnapshotAccount {
+        pubkey: parse_pubkey(&pubkey)?,
+        account: Account {
+          lamports: account.lamports,
+          data: account.data.decode()?,
+          owner: parse_pubkey(&account.owner)?,
+          executable: account.executable,
+          rent_epoch: account.rent_epoch,
+        },
+      })
+    })
+    .collect()
+}
+
+/// Reads the accounts of the snapshot files and directories
+pub fn load_snapshot(paths: &[PathBuf]) -> Result<Vec<SnapshotAccount>, Box<dyn Error>> {
+  let mut accounts = Vec::new();
+  for path in paths {
+    if path.is_dir() {
+      let mut files: Vec<PathBuf> = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+      files.retain(|file| file.extension().is_some_and(|extension| extension == "json"));
+      files.sort();
+      for file in files {
+        accounts.extend(load_file(&file)?);
+      }
+    } else {
+      accounts.extend(load_file(path)?);
+    }
+  }
+  Ok(accounts)
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UiAccountMeta {
+  pubkey: String,
+  is_signer: bool,
+  is_writable: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UiInstruction {
+  program_id: String,
+  accounts: Vec<UiAccountMeta>,
+  /// base64 instruction data
+  data: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum InstructionFile {
+  Many(Vec<UiInstruction>),
+  One(UiInstruction),
+}
+
+/// Reads the instructions to replay, one instruction or an array of them as
+/// `{ "programId": "...", "accounts": [{ "pubkey": "...", "isSigner": true, "isWritable": true }], "data": "<base64>" }`
+pub fn load_instructions(path: &Path) -> Result<Vec<Instruction>, Box<dyn Error>> {
+  let file: InstructionFile = serde_json::from_str(&fs::read_to_string(path)?)
+    .map_err(|error| format!("{}: {}", path.display(), error))?;
+  let instructions = match file {
+    InstructionFile::Many(instructions) => instructions,
+    InstructionFile::One(instruction) => vec![instruction],
+  };
+
+  instructions
+    .into_iter()
+    .map(|instruction| {
+      Ok(Instruction {
+        program_id: parse_pubkey(&instruction.program_id)?,
+        accounts: instruction.accounts
+          .iter()
+          .map(|meta| Ok(AccountMeta {
+            pubkey: parse_pubkey(&meta.pubkey)?,
+            is_signer: meta.is_signer,
+            is_writable: meta.is_writable,
+          }))
+          .collect::<Result<_, Box<dyn Error>>>()?,
+        data: STANDARD.decode(&instruction.data)?,
+      })
+    })
+    .collect()
+}
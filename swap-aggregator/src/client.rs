@@ -0,0 +1,665 @@
+//! Instruction builders for off-chain clients
+//!
+//! Each builder returns the aggregator instruction with its accounts in the order
+//! the processor expects. Admin builders take the signing admin key; in multisig
+//! mode the other admin signers can be added with [append_admin_signers].
+
+// this allows many arguments for the function parameter
+#![allow(clippy::too_many_arguments)]
+
+use {
+  crate::{
+    constant::*,
+    error::AggregatorError,
+    instruction::{AggregatorInstruction, AggregatorPath, RouteOptions},
+    processor::DiscountTier,
+  },
+
+  borsh::BorshSerialize,
+
+  solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+    sysvar,
+  },
+};
+
+/// Accounts of a Raydium AMM pool and its Serum market
+#[derive(Clone, Debug, PartialEq)]
+pub struct RaydiumPool {
+  pub amm_id: Pubkey,
+  pub amm_authority: Pubkey,
+  pub amm_open_orders: Pubkey,
+  pub amm_target_orders: Pubkey,
+  pub pool_coin_token_account: Pubkey,
+  pub pool_pc_token_account: Pubkey,
+  pub serum_program_id: Pubkey,
+  pub serum_market: Pubkey,
+  pub serum_bids: Pubkey,
+  pub serum_asks: Pubkey,
+  pub serum_event_queue: Pubkey,
+  pub serum_coin_vault_account: Pubkey,
+  pub serum_pc_vault_account: Pubkey,
+  pub serum_vault_signer: Pubkey,
+  pub program_id: Pubkey,
+}
+
+impl RaydiumPool {
+  /// the `RAYDIUM_SWAP_ACCOUNTS` accounts of a route leg
+  pub fn account_metas(&self) -> Vec<AccountMeta> {
+    vec![
+      // amm
+      AccountMeta::new(self.amm_id, false),
+      AccountMeta::new_readonly(self.amm_authority, false),
+      AccountMeta::new(self.amm_open_orders, false),
+      AccountMeta::new(self.amm_target_orders, false),
+      AccountMeta::new(self.pool_coin_token_account, false),
+      AccountMeta::new(self.pool_pc_token_account, false),
+      // serum
+      AccountMeta::new_readonly(self.serum_program_id, false),
+      AccountMeta::new(self.serum_market, false),
+      AccountMeta::new(self.serum_bids, false),
+      AccountMeta::new(self.serum_asks, false),
+      AccountMeta::new(self.serum_event_queue, false),
+      AccountMeta::new(self.serum_coin_vault_account, false),
+      AccountMeta::new(self.serum_pc_vault_account, false),
+      AccountMeta::new_readonly(self.serum_vault_signer, false),
+
+      AccountMeta::new_readonly(self.program_id, false),
+    ]
+  }
+}
+
+/// Accounts of a Saber stable swap pool
+#[derive(Clone, Debug, PartialEq)]
+pub struct SaberPool {
+  pub swap: Pubkey,
+  pub swap_authority: Pubkey,
+  pub token_a_mint: Pubkey,
+  pub token_a_reserve: Pubkey,
+  pub token_a_admin_fee_account: Pubkey,
+  pub token_b_mint: Pubkey,
+  pub token_b_reserve: Pubkey,
+  pub token_b_admin_fee_account: Pubkey,
+  pub program_id: Pubkey,
+}
+
+impl SaberPool {
+  /// the `SABER_SWAP_ACCOUNTS` accounts of a route leg, oriented from `source_mint`
+  pub fn account_metas(&self, source_mint: &Pubkey) -> Vec<AccountMeta> {
+    // the admin fee is taken in the destination token
+    let (source_reserve, destination_reserve, admin_fee_account) = if *source_mint == self.token_a_mint {
+      (self.token_a_reserve, self.token_b_reserve, self.token_b_admin_fee_account)
+    } else {
+      (self.token_b_reserve, self.token_a_reserve, self.token_a_admin_fee_account)
+    };
+
+    vec![
+      AccountMeta::new_readonly(self.swap, false),
+      AccountMeta::new_readonly(self.swap_authority, false),
+      AccountMeta::new(source_reserve, false),
+      AccountMeta::new(destination_reserve, false),
+      AccountMeta::new(admin_fee_account, false),
+      AccountMeta::new_readonly(sysvar::clock::id(), false),
+      AccountMeta::new_readonly(self.program_id, false),
+    ]
+  }
+}
+
+/// Accounts of a Mercurial stable swap pool with 4 tokens
+#[derive(Clone, Debug, PartialEq)]
+pub struct MercurialPool {
+  pub swap: Pubkey,
+  pub pool_authority: Pubkey,
+  pub token_accounts: [Pubkey; 4],
+  pub program_id: Pubkey,
+}
+
+impl MercurialPool {
+  /// the `MERCURIAL_SWAP_ACCOUNTS` accounts of a route leg
+  pub fn account_metas(&self) -> Vec<AccountMeta> {
+    let mut accounts = Vec::with_capacity(MERCURIAL_SWAP_ACCOUNTS);
+    accounts.push(AccountMeta::new_readonly(self.swap, false));
+    accounts.push(AccountMeta::new_readonly(self.pool_authority, false));
+    for token_account in self.token_accounts.iter() {
+      accounts.push(AccountMeta::new(*token_account, false));
+    }
+    accounts.push(AccountMeta::new_readonly(self.program_id, false));
+    accounts
+  }
+}
+
+/// Pool of a route leg
+#[derive(Clone, Debug, PartialEq)]
+pub enum RoutePool {
+  Raydium(RaydiumPool),
+  Saber(SaberPool),
+  Mercurial(MercurialPool),
+}
+
+impl RoutePool {
+  /// route of the pool
+  pub fn path(&self) -> AggregatorPath {
+    match self {
+      Self::Raydium(_) => AggregatorPath::Raydium,
+      Self::Saber(_) => AggregatorPath::Saber,
+      Self::Mercurial(_) => AggregatorPath::Mercurial,
+    }
+  }
+
+  /// pool address, the first account of a route leg checked against the pool allowlist
+  pub fn address(&self) -> Pubkey {
+    match self {
+      Self::Raydium(pool) => pool.amm_id,
+      Self::Saber(pool) => pool.swap,
+      Self::Mercurial(pool) => pool.swap,
+    }
+  }
+
+  /// accounts of a route leg swapping from `source_mint`
+  pub fn account_metas(&self, source_mint: &Pubkey) -> Vec<AccountMeta> {
+    match self {
+      Self::Raydium(pool) => pool.account_metas(),
+      Self::Saber(pool) => pool.account_metas(source_mint),
+      Self::Mercurial(pool) => pool.account_metas(),
+    }
+  }
+}
+
+/// address of the program state
+pub fn state_address(program_id: &Pubkey) -> Pubkey {
+  find_state_address(program_id).0
+}
+
+/// address of the program state and its bump seed
+pub fn find_state_address(program_id: &Pubkey) -> (Pubkey, u8) {
+  let seeds = [
+    SWAP_AGGREGATOR_SEED.as_bytes(),
+    program_id.as_ref(),
+  ];
+  Pubkey::find_program_address(&seeds, program_id)
+}
+
+/// address of the temporary wrapped SOL account of a user, for [route_swap_with_options]
+pub fn native_token_address(program_id: &Pubkey, user_transfer_authority: &Pubkey) -> Pubkey {
+  let seeds = [
+    NATIVE_TOKEN_SEED.as_bytes(),
+    user_transfer_authority.as_ref(),
+  ];
+  Pubkey::find_program_address(&seeds, program_id).0
+}
+
+/// address of the intermediate token account of a user and mint, for the two leg [route_swap_with_options]
+pub fn intermediate_token_address(program_id: &Pubkey, user_transfer_authority: &Pubkey, mint: &Pubkey) -> Pubkey {
+  let seeds = [
+    INTERMEDIATE_TOKEN_SEED.as_bytes(),
+    user_transfer_authority.as_ref(),
+    mint.as_ref(),
+  ];
+  Pubkey::find_program_address(&seeds, program_id).0
+}
+
+/// Adds the other admin signers of the multisig mode to an admin instruction
+pub fn append_admin_signers(instruction: &mut Instruction, admin_signers: &[Pubkey]) {
+  for admin_signer in admin_signers {
+    instruction.accounts.push(AccountMeta::new_readonly(*admin_signer, true));
+  }
+}
+
+fn aggregator_instruction(
+  program_id: &Pubkey,
+  accounts: Vec<AccountMeta>,
+  instruction: AggregatorInstruction,
+) -> Result<Instruction, ProgramError> {
+  Ok(Instruction {
+    program_id: *program_id,
+    accounts,
+    data: instruction.try_to_vec()?,
+  })
+}
+
+/// Creates an [UpdateState](AggregatorInstruction::UpdateState) instruction.
+/// The current owner pays for the state account on the first call, the only one setting the fee ratio.
+pub fn update_state(
+  program_id: &Pubkey,
+  cur_state_owner: &Pubkey,
+  new_state_owner: &Pubkey,
+  fee_numerator: u64,
+  fee_denominator: u64,
+) -> Result<Instruction, ProgramError> {
+  let accounts = vec![
+    AccountMeta::new(state_address(program_id), false),
+    AccountMeta::new(*cur_state_owner, true),
+    AccountMeta::new_readonly(*new_state_owner, false),
+    AccountMeta::new_readonly(system_program::id(), false),
+    AccountMeta::new_readonly(sysvar::rent::id(), false),
+  ];
+
+  aggregator_instruction(program_id, accounts, AggregatorInstruction::UpdateState {
+    fee_numerator,
+    fee_denominator,
+  })
+}
+
+/// Creates a [MigrateState](AggregatorInstruction::MigrateState) instruction.
+/// The admin pays the rent of the grown state account.
+pub fn migrate_state(
+  program_id: &Pubkey,
+  admin: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+  let accounts = vec![
+    AccountMeta::new(state_address(program_id), false),
+    AccountMeta::new(*admin, true),
+    AccountMeta::new_readonly(system_program::id(), false),
+    AccountMeta::new_readonly(sysvar::rent::id(), false),
+  ];
+
+  aggregator_instruction(program_id, accounts, AggregatorInstruction::MigrateState)
+}
+
+/// Accounts of a route, shared by the route builders. `legs` are the pools with the mint they swap from.
+fn route_accounts(
+  program_id: &Pubkey,
+  user_transfer_authority: &Pubkey,
+  source_token_account: &Pubkey,
+  destination_token_account: &Pubkey,
+  fee_vault: &Pubkey,
+  legs: &[(&RoutePool, &Pubkey)],
+  discount_token_account: Option<&Pubkey>,
+) -> Vec<AccountMeta> {
+  let mut accounts = vec![
+    AccountMeta::new_readonly(state_address(program_id), false),
+    AccountMeta::new_readonly(*user_transfer_authority, true),
+    AccountMeta::new(*source_token_account, false),
+    AccountMeta::new(*destination_token_account, false),
+    AccountMeta::new(*fee_vault, false),
+    AccountMeta::new_readonly(spl_token::id(), false),
+  ];
+  for (pool, source_mint) in legs {
+    accounts.extend(pool.account_metas(source_mint));
+  }
+
+  if let Some(discount_token_account) = discount_token_account {
+    accounts.push(AccountMeta::new_readonly(*discount_token_account, false));
+  }
+  accounts
+}
+
+/// Creates a single leg [RouteSwap](AggregatorInstruction::RouteSwap) instruction.
+pub fn route_swap(
+  program_id: &Pubkey,
+  user_transfer_authority: &Pubkey,
+  source_token_account: &Pubkey,
+  destination_token_account: &Pubkey,
+  fee_vault: &Pubkey,
+  source_mint: &Pubkey,
+  pool: &RoutePool,
+  discount_token_account: Option<&Pubkey>,
+  amount_in: u64,
+  amount_out: u64,
+) -> Result<Instruction, ProgramError> {
+  let accounts = route_accounts(
+    program_id,
+    user_transfer_authority,
+    source_token_account,
+    destination_token_account,
+    fee_vault,
+    &[(pool, source_mint)],
+    discount_token_account,
+  );
+
+  aggregator_instruction(program_id, accounts, AggregatorInstruction::RouteSwap {
+    route1: pool.path(),
+    route2: AggregatorPath::Skip,
+    amount_in,
+    amount_out,
+  })
+}
+
+/// Creates a single leg [SimulateRoute](AggregatorInstruction::SimulateRoute) instruction.
+pub fn simulate_route(
+  program_id: &Pubkey,
+  user_transfer_authority: &Pubkey,
+  source_token_account: &Pubkey,
+  destination_token_account: &Pubkey,
+  fee_vault: &Pubkey,
+  source_mint: &Pubkey,
+  pool: &RoutePool,
+  discount_token_account: Option<&Pubkey>,
+  amount_in: u64,
+) -> Result<Instruction, ProgramError> {
+  let accounts = route_accounts(
+    program_id,
+    user_transfer_authority,
+    source_token_account,
+    destination_token_account,
+    fee_vault,
+    &[(pool, source_mint)],
+    discount_token_account,
+  );
+
+  aggregator_instruction(program_id, accounts, AggregatorInstruction::SimulateRoute {
+    route1: pool.path(),
+    route2: AggregatorPath::Skip,
+    amount_in,
+  })
+}
+
+/// Creates a [RouteSwapWithOptions](AggregatorInstruction::RouteSwapWithOptions) instruction,
+/// swapping through `pool` alone or, with `second_leg`, through `pool` then the second leg pool.
+/// `second_leg` is that pool and the intermediate mint between the legs, held in the
+/// [intermediate_token_address] of the user during the swap.
+/// To wrap or unwrap native SOL, the [native_token_address] of the user is the source or destination
+/// token account, and `source_mint` is the native mint when wrapping. To create the destination token
+/// account, it is the associated token account of the user for `destination_mint`. Sweeping the
+/// intermediate residual needs `second_leg` and no other account.
+pub fn route_swap_with_options(
+  program_id: &Pubkey,
+  user_transfer_authority: &Pubkey,
+  source_token_account: &Pubkey,
+  destination_token_account: &Pubkey,
+  fee_vault: &Pubkey,
+  source_mint: &Pubkey,
+  destination_mint: &Pubkey,
+  pool: &RoutePool,
+  second_leg: Option<(&RoutePool, &Pubkey)>,
+  discount_token_account: Option<&Pubkey>,
+  amount_in: u64,
+  amount_out: u64,
+  options: RouteOptions,
+) -> Result<Instruction, ProgramError> {
+  let accounts = route_with_options_accounts(
+    program_id,
+    user_transfer_authority,
+    source_token_account,
+    destination_token_account,
+    fee_vault,
+    source_mint,
+    destination_mint,
+    pool,
+    second_leg,
+    discount_token_account,
+    &options,
+  );
+
+  aggregator_instruction(program_id, accounts, AggregatorInstruction::RouteSwapWithOptions {
+    route1: pool.path(),
+    route2: second_leg.map_or(AggregatorPath::Skip, |(second_pool, _)| second_pool.path()),
+    amount_in,
+    amount_out,
+    options,
+  })
+}
+
+/// Creates a [SimulateRouteWithOptions](AggregatorInstruction::SimulateRouteWithOptions) instruction,
+/// with the accounts of [route_swap_with_options].
+pub fn simulate_route_with_options(
+  program_id: &Pubkey,
+  user_transfer_authority: &Pubkey,
+  source_token_account: &Pubkey,
+  destination_token_account: &Pubkey,
+  fee_vault: &Pubkey,
+  source_mint: &Pubkey,
+  destination_mint: &Pubkey,
+  pool: &RoutePool,
+  second_leg: Option<(&RoutePool, &Pubkey)>,
+  discount_token_account: Option<&Pubkey>,
+  amount_in: u64,
+  options: RouteOptions,
+) -> Result<Instruction, ProgramError> {
+  let accounts = route_with_options_accounts(
+    program_id,
+    user_transfer_authority,
+    source_token_account,
+    destination_token_account,
+    fee_vault,
+    source_mint,
+    destination_mint,
+    pool,
+    second_leg,
+    discount_token_account,
+    &options,
+  );
+
+  aggregator_instruction(program_id, accounts, AggregatorInstruction::SimulateRouteWithOptions {
+    route1: pool.path(),
+    route2: second_leg.map_or(AggregatorPath::Skip, |(second_pool, _)| second_pool.path()),
+    amount_in,
+    options,
+  })
+}
+
+/// accounts of the options, then the `RouteSwap` accounts of the legs
+fn route_with_options_accounts(
+  program_id: &Pubkey,
+  user_transfer_authority: &Pubkey,
+  source_token_account: &Pubkey,
+  destination_token_account: &Pubkey,
+  fee_vault: &Pubkey,
+  source_mint: &Pubkey,
+  destination_mint: &Pubkey,
+  pool: &RoutePool,
+  second_leg: Option<(&RoutePool, &Pubkey)>,
+  discount_token_account: Option<&Pubkey>,
+  options: &RouteOptions,
+) -> Vec<AccountMeta> {
+  let mut accounts = vec![
+    AccountMeta::new_readonly(system_program::id(), false),
+    AccountMeta::new_readonly(sysvar::rent::id(), false),
+    AccountMeta::new_readonly(spl_token::native_mint::id(), false),
+  ];
+  if options.create_destination_account {
+    accounts.push(AccountMeta::new_readonly(spl_associated_token_account::id(), false));
+    accounts.push(AccountMeta::new_readonly(*destination_mint, false));
+  }
+
+  let mut legs = vec![(pool, source_mint)];
+  if let Some((second_pool, intermediate_mint)) = second_leg {
+    let intermediate_token_account = intermediate_token_address(program_id, user_transfer_authority, intermediate_mint);
+    accounts.push(AccountMeta::new(intermediate_token_account, false));
+    accounts.push(AccountMeta::new_readonly(*intermediate_mint, false));
+    legs.push((second_pool, intermediate_mint));
+  }
+
+  // the user pays the created accounts and receives the lamports back
+  let user_index = accounts.len() + 1;
+  accounts.extend(route_accounts(
+    program_id,
+    user_transfer_authority,
+    source_token_account,
+    destination_token_account,
+    fee_vault,
+    &legs,
+    discount_token_account,
+  ));
+  accounts[user_index].is_writable = true;
+  accounts
+}
+
+/// Creates an [UpdateFeeBeneficiaries](AggregatorInstruction::UpdateFeeBeneficiaries) instruction.
+pub fn update_fee_beneficiaries(
+  program_id: &Pubkey,
+  state_owner: &Pubkey,
+  beneficiaries: &[(Pubkey, u64)],
+) -> Result<Instruction, ProgramError> {
+  let mut accounts = vec![
+    AccountMeta::new(state_address(program_id), false),
+    AccountMeta::new_readonly(*state_owner, true),
+  ];
+  accounts.extend(beneficiaries.iter().map(|(owner, _)| AccountMeta::new_readonly(*owner, false)));
+
+  aggregator_instruction(program_id, accounts, AggregatorInstruction::UpdateFeeBeneficiaries {
+    weights: beneficiaries.iter().map(|(_, weight)| *weight).collect(),
+  })
+}
+
+/// Creates a [DistributeFees](AggregatorInstruction::DistributeFees) instruction.
+/// `beneficiary_token_accounts` follow the order of the beneficiaries in the state.
+pub fn distribute_fees(
+  program_id: &Pubkey,
+  fee_vault: &Pubkey,
+  beneficiary_token_accounts: &[Pubkey],
+) -> Result<Instruction, ProgramError> {
+  let mut accounts = vec![
+    AccountMeta::new_readonly(state_address(program_id), false),
+    AccountMeta::new(*fee_vault, false),
+    AccountMeta::new_readonly(spl_token::id(), false),
+  ];
+  accounts.extend(beneficiary_token_accounts.iter().map(|token_account| AccountMeta::new(*token_account, false)));
+
+  aggregator_instruction(program_id, accounts, AggregatorInstruction::DistributeFees)
+}
+
+/// Creates an [UpdateDiscountTiers](AggregatorInstruction::UpdateDiscountTiers) instruction.
+pub fn update_discount_tiers(
+  program_id: &Pubkey,
+  fee_admin: &Pubkey,
+  discount_mint: &Pubkey,
+  tiers: Vec<DiscountTier>,
+) -> Result<Instruction, ProgramError> {
+  let accounts = vec![
+    AccountMeta::new(state_address(program_id), false),
+    AccountMeta::new_readonly(*fee_admin, true),
+    AccountMeta::new_readonly(*discount_mint, false),
+  ];
+
+  aggregator_instruction(program_id, accounts, AggregatorInstruction::UpdateDiscountTiers { tiers })
+}
+
+/// Creates a [QueueFeeChange](AggregatorInstruction::QueueFeeChange) instruction.
+pub fn queue_fee_change(
+  program_id: &Pubkey,
+  fee_admin: &Pubkey,
+  fee_numerator: u64,
+  fee_denominator: u64,
+  fee_change_delay: i64,
+) -> Result<Instruction, ProgramError> {
+  let accounts = vec![
+    AccountMeta::new(state_address(program_id), false),
+    AccountMeta::new_readonly(*fee_admin, true),
+    AccountMeta::new_readonly(sysvar::clock::id(), false),
+  ];
+
+  aggregator_instruction(program_id, accounts, AggregatorInstruction::QueueFeeChange {
+    fee_numerator,
+    fee_denominator,
+    fee_change_delay,
+  })
+}
+
+/// Creates an [ExecuteFeeChange](AggregatorInstruction::ExecuteFeeChange) instruction.
+pub fn execute_fee_change(
+  program_id: &Pubkey,
+  fee_admin: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+  let accounts = vec![
+    AccountMeta::new(state_address(program_id), false),
+    AccountMeta::new_readonly(*fee_admin, true),
+    AccountMeta::new_readonly(sysvar::clock::id(), false),
+  ];
+
+  aggregator_instruction(program_id, accounts, AggregatorInstruction::ExecuteFeeChange)
+}
+
+/// Creates a [CancelFeeChange](AggregatorInstruction::CancelFeeChange) instruction.
+pub fn cancel_fee_change(
+  program_id: &Pubkey,
+  fee_admin: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+  let accounts = vec![
+    AccountMeta::new(state_address(program_id), false),
+    AccountMeta::new_readonly(*fee_admin, true),
+  ];
+
+  aggregator_instruction(program_id, accounts, AggregatorInstruction::CancelFeeChange)
+}
+
+/// Creates a [SetPause](AggregatorInstruction::SetPause) instruction.
+pub fn set_pause(
+  program_id: &Pubkey,
+  admin: &Pubkey,
+  is_paused: bool,
+) -> Result<Instruction, ProgramError> {
+  let accounts = vec![
+    AccountMeta::new(state_address(program_id), false),
+    AccountMeta::new_readonly(*admin, true),
+  ];
+
+  aggregator_instruction(program_id, accounts, AggregatorInstruction::SetPause { is_paused })
+}
+
+/// Creates a [SetAdminSigners](AggregatorInstruction::SetAdminSigners) instruction,
+/// failing with `InvalidAdminSigners` for more than [MAX_ADMIN_SIGNERS] admin signers.
+pub fn set_admin_signers(
+  program_id: &Pubkey,
+  state_owner: &Pubkey,
+  admin_signers: &[Pubkey],
+  threshold: u8,
+) -> Result<Instruction, ProgramError> {
+  // the signer count would not fit in the state, nor in its byte past 255 signers
+  if admin_signers.len() > MAX_ADMIN_SIGNERS {
+    return Err(AggregatorError::InvalidAdminSigners.into());
+  }
+
+  let mut accounts = vec![
+    AccountMeta::new(state_address(program_id), false),
+    AccountMeta::new_readonly(*state_owner, true),
+  ];
+  accounts.extend(admin_signers.iter().map(|admin_signer| AccountMeta::new_readonly(*admin_signer, false)));
+
+  aggregator_instruction(program_id, accounts, AggregatorInstruction::SetAdminSigners {
+    signer_count: admin_signers.len() as u8,
+    threshold,
+  })
+}
+
+/// Creates a [SetRoles](AggregatorInstruction::SetRoles) instruction.
+pub fn set_roles(
+  program_id: &Pubkey,
+  state_owner: &Pubkey,
+  fee_admin: &Pubkey,
+  route_admin: &Pubkey,
+  pause_guardian: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+  let accounts = vec![
+    AccountMeta::new(state_address(program_id), false),
+    AccountMeta::new_readonly(*state_owner, true),
+    AccountMeta::new_readonly(*fee_admin, false),
+    AccountMeta::new_readonly(*route_admin, false),
+    AccountMeta::new_readonly(*pause_guardian, false),
+  ];
+
+  aggregator_instruction(program_id, accounts, AggregatorInstruction::SetRoles)
+}
+
+/// Creates a [SetRouteEnabled](AggregatorInstruction::SetRouteEnabled) instruction.
+pub fn set_route_enabled(
+  program_id: &Pubkey,
+  route_admin: &Pubkey,
+  route: AggregatorPath,
+  is_enabled: bool,
+) -> Result<Instruction, ProgramError> {
+  let accounts = vec![
+    AccountMeta::new(state_address(program_id), false),
+    AccountMeta::new_readonly(*route_admin, true),
+  ];
+
+  aggregator_instruction(program_id, accounts, AggregatorInstruction::SetRouteEnabled { route, is_enabled })
+}
+
+/// Creates a [SetPoolAllowed](AggregatorInstruction::SetPoolAllowed) instruction.
+pub fn set_pool_allowed(
+  program_id: &Pubkey,
+  route_admin: &Pubkey,
+  pool: &Pubkey,
+  is_allowed: bool,
+) -> Result<Instruction, ProgramError> {
+  let accounts = vec![
+    AccountMeta::new(state_address(program_id), false),
+    AccountMeta::new_readonly(*route_admin, true),
+  ];
+
+  aggregator_instruction(program_id, accounts, AggregatorInstruction::SetPoolAllowed { pool: *pool, is_allowed })
+}
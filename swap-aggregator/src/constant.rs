@@ -1,24 +1,53 @@
-pub const RAYDIUM_SWAP_PROGRAM_ID:&str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+use solana_program::{pubkey, pubkey::Pubkey};
+
+pub const RAYDIUM_SWAP_PROGRAM_ID:Pubkey = pubkey!("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8");
 pub const RAYDIUM_SWAP_ACCOUNTS:usize = 15;
 
-pub const SERUM_PROGRAM_ID:&str = "9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin";
+pub const SERUM_PROGRAM_ID:Pubkey = pubkey!("9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin");
 
-pub const MERCURIAL_SWAP_PROGRAM_ID:&str = "MERLuDFBMmsHnsBPZw2sDQZHvXFMwp8EdjudcU2HKky";
+pub const MERCURIAL_SWAP_PROGRAM_ID:Pubkey = pubkey!("MERLuDFBMmsHnsBPZw2sDQZHvXFMwp8EdjudcU2HKky");
 pub const MERCURIAL_SWAP_ACCOUNTS:usize = 7;
 
-pub const SABER_SWAP_PROGRAM_ID:&str = "SSwpkEEcbUqx4vtoEByFjSkhKdCT862DNVb52nZg1UZ";
+pub const SABER_SWAP_PROGRAM_ID:Pubkey = pubkey!("SSwpkEEcbUqx4vtoEByFjSkhKdCT862DNVb52nZg1UZ");
 pub const SABER_SWAP_ACCOUNTS:usize = 7;
 
-pub const TOKEN_PROGRAM_ID:&str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+pub const TOKEN_PROGRAM_ID:Pubkey = pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
 
 ///rent sys bar program id
-pub const RENT_SYSVAR_ID:&str = "SysvarRent111111111111111111111111111111111";
+pub const RENT_SYSVAR_ID:Pubkey = pubkey!("SysvarRent111111111111111111111111111111111");
 
 /// system program id
-pub const SYSTEM_PROGRAM_ID:&str = "11111111111111111111111111111111";
+pub const SYSTEM_PROGRAM_ID:Pubkey = pubkey!("11111111111111111111111111111111");
 
 /// Seed for program state
 pub const SWAP_AGGREGATOR_SEED:&str = "Swap Aggregator state";
 
+/// Seed for the temporary wrapped SOL account of a user, closed by the swap creating it
+pub const NATIVE_TOKEN_SEED:&str = "Swap Aggregator native SOL";
+
+/// Seed for the intermediate token account of a user and mint, closed by the two leg swap creating it
+pub const INTERMEDIATE_TOKEN_SEED:&str = "Swap Aggregator intermediate";
+
+/// Maximum number of wallets sharing the collected fees
+pub const MAX_FEE_BENEFICIARIES: usize = 8;
+
+/// Maximum number of fee discount tiers
+pub const MAX_DISCOUNT_TIERS: usize = 4;
+
+/// Denominator of the fee discounts, in basis points
+pub const DISCOUNT_BPS_DENOMINATOR: u64 = 10000;
+
+/// Delay before a queued fee change can be executed, in seconds, until a fee change sets another
+pub const DEFAULT_FEE_CHANGE_DELAY: i64 = 2 * 24 * 60 * 60;
+
+/// Longest fee change delay, in seconds, so fees can't be locked for good
+pub const MAX_FEE_CHANGE_DELAY: i64 = 30 * 24 * 60 * 60;
+
+/// Maximum number of admin signers in multisig mode
+pub const MAX_ADMIN_SIGNERS: usize = 8;
+
+/// Maximum number of pools in the pool allowlist
+pub const MAX_ALLOWED_POOLS: usize = 16;
+
 /// address to change the program state at first
-pub const INITIAL_STATE_OWNER: &str = "2Pv5mjmKYAtXNpr3mcsXf7HjtS3fieJeFoWPATVT5rWa";
+pub const INITIAL_STATE_OWNER: Pubkey = pubkey!("2Pv5mjmKYAtXNpr3mcsXf7HjtS3fieJeFoWPATVT5rWa");
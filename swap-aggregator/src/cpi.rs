@@ -0,0 +1,229 @@
+//! Helpers for programs invoking the aggregator
+//!
+//! Each helper builds the aggregator instruction from the caller's account
+//! infos and invokes it with `invoke_signed`, so a PDA of the caller can sign
+//! as the user transfer authority. Swaps return the `SwapResult` the
+//! aggregator set as return data.
+
+use {
+  crate::{
+    error::AggregatorError,
+    instruction::{AggregatorInstruction, AggregatorPath, RouteOptions},
+    return_data::SwapResult,
+  },
+
+  borsh::{BorshDeserialize, BorshSerialize},
+
+  solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::{get_return_data, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+  },
+};
+
+/// Accounts of a [RouteSwap](AggregatorInstruction::RouteSwap), in the instruction order
+#[derive(Clone)]
+pub struct RouteSwapAccounts<'a> {
+  pub aggregator_program: AccountInfo<'a>,
+  pub program_state: AccountInfo<'a>,
+  pub user_transfer_authority: AccountInfo<'a>,
+  pub source_token_account: AccountInfo<'a>,
+  pub destination_token_account: AccountInfo<'a>,
+  pub fee_vault: AccountInfo<'a>,
+  pub token_program: AccountInfo<'a>,
+  /// Pool accounts of the route leg, as listed by the client pools
+  pub pool_accounts: Vec<AccountInfo<'a>>,
+  pub discount_token_account: Option<AccountInfo<'a>>,
+}
+
+/// Accounts of a [RouteSwapWithOptions](AggregatorInstruction::RouteSwapWithOptions), in the instruction order
+#[derive(Clone)]
+pub struct RouteSwapWithOptionsAccounts<'a> {
+  pub system_program: AccountInfo<'a>,
+  pub rent_sysvar: AccountInfo<'a>,
+  pub native_mint: AccountInfo<'a>,
+  /// Associated token program and destination mint, only to create the destination token account
+  pub destination_account: Option<(AccountInfo<'a>, AccountInfo<'a>)>,
+  /// Intermediate token account and intermediate mint, only for two leg routes
+  pub intermediate_account: Option<(AccountInfo<'a>, AccountInfo<'a>)>,
+  /// `RouteSwap` accounts, with the pool accounts of the second leg after those of the first.
+  /// The user transfer authority pays the created accounts, so it must be writable.
+  pub route_swap: RouteSwapAccounts<'a>,
+}
+
+/// Accounts of a [DistributeFees](AggregatorInstruction::DistributeFees), in the instruction order
+#[derive(Clone)]
+pub struct DistributeFeesAccounts<'a> {
+  pub aggregator_program: AccountInfo<'a>,
+  pub program_state: AccountInfo<'a>,
+  pub fee_vault: AccountInfo<'a>,
+  pub token_program: AccountInfo<'a>,
+  pub beneficiary_token_accounts: Vec<AccountInfo<'a>>,
+}
+
+/// Swaps through `route` and returns the amounts the aggregator reported
+pub fn route_swap(
+  accounts: RouteSwapAccounts<'_>,
+  route: AggregatorPath,
+  amount_in: u64,
+  minimum_amount_out: u64,
+  signer_seeds: &[&[&[u8]]],
+) -> Result<SwapResult, ProgramError> {
+  let mut account_infos = vec![
+    accounts.program_state,
+    accounts.user_transfer_authority,
+    accounts.source_token_account,
+    accounts.destination_token_account,
+    accounts.fee_vault,
+    accounts.token_program,
+  ];
+  account_infos.extend(accounts.pool_accounts);
+  account_infos.extend(accounts.discount_token_account);
+
+  let data = AggregatorInstruction::RouteSwap {
+    route1: route,
+    route2: AggregatorPath::Skip,
+    amount_in,
+    amount_out: minimum_amount_out,
+  }.try_to_vec()?;
+
+  // the user transfer authority follows the program state
+  invoke(&accounts.aggregator_program, account_infos, Some(1), data, signer_seeds)?;
+  swap_result(accounts.aggregator_program.key)
+}
+
+/// Swaps like [route_swap] for a user transfer authority that is a PDA of `caller_program_id`,
+/// signed with its `authority_seeds`, bump included. The seeds are checked before the CPI.
+pub fn route_swap_as_pda(
+  caller_program_id: &Pubkey,
+  accounts: RouteSwapAccounts<'_>,
+  route: AggregatorPath,
+  amount_in: u64,
+  minimum_amount_out: u64,
+  authority_seeds: &[&[u8]],
+) -> Result<SwapResult, ProgramError> {
+  check_authority_seeds(caller_program_id, accounts.user_transfer_authority.key, authority_seeds)?;
+  route_swap(accounts, route, amount_in, minimum_amount_out, &[authority_seeds])
+}
+
+/// Swaps through `route1`, then `route2` unless it is `Skip`, with the route options,
+/// and returns the amounts the aggregator reported
+pub fn route_swap_with_options(
+  accounts: RouteSwapWithOptionsAccounts<'_>,
+  route1: AggregatorPath,
+  route2: AggregatorPath,
+  amount_in: u64,
+  minimum_amount_out: u64,
+  options: RouteOptions,
+  signer_seeds: &[&[&[u8]]],
+) -> Result<SwapResult, ProgramError> {
+  let route_swap = accounts.route_swap;
+  let aggregator_program = route_swap.aggregator_program;
+  let mut account_infos = vec![accounts.system_program, accounts.rent_sysvar, accounts.native_mint];
+  if let Some((associated_token_program, destination_mint)) = accounts.destination_account {
+    account_infos.extend([associated_token_program, destination_mint]);
+  }
+  if let Some((intermediate_token_account, intermediate_mint)) = accounts.intermediate_account {
+    account_infos.extend([intermediate_token_account, intermediate_mint]);
+  }
+
+  // the user transfer authority follows the program state
+  let signer_index = account_infos.len() + 1;
+  account_infos.extend([
+    route_swap.program_state,
+    route_swap.user_transfer_authority,
+    route_swap.source_token_account,
+    route_swap.destination_token_account,
+    route_swap.fee_vault,
+    route_swap.token_program,
+  ]);
+  account_infos.extend(route_swap.pool_accounts);
+  account_infos.extend(route_swap.discount_token_account);
+
+  let data = AggregatorInstruction::RouteSwapWithOptions {
+    route1,
+    route2,
+    amount_in,
+    amount_out: minimum_amount_out,
+    options,
+  }.try_to_vec()?;
+
+  invoke(&aggregator_program, account_infos, Some(signer_index), data, signer_seeds)?;
+  swap_result(aggregator_program.key)
+}
+
+/// Swaps like [route_swap_with_options] for a user transfer authority that is a PDA of `caller_program_id`,
+/// signed with its `authority_seeds`, bump included. The seeds are checked before the CPI.
+#[allow(clippy::too_many_arguments)]
+pub fn route_swap_with_options_as_pda(
+  caller_program_id: &Pubkey,
+  accounts: RouteSwapWithOptionsAccounts<'_>,
+  route1: AggregatorPath,
+  route2: AggregatorPath,
+  amount_in: u64,
+  minimum_amount_out: u64,
+  options: RouteOptions,
+  authority_seeds: &[&[u8]],
+) -> Result<SwapResult, ProgramError> {
+  check_authority_seeds(caller_program_id, accounts.route_swap.user_transfer_authority.key, authority_seeds)?;
+  route_swap_with_options(accounts, route1, route2, amount_in, minimum_amount_out, options, &[authority_seeds])
+}
+
+/// Pays the fee vault out to the beneficiaries
+pub fn distribute_fees(accounts: DistributeFeesAccounts<'_>, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+  let mut account_infos = vec![accounts.program_state, accounts.fee_vault, accounts.token_program];
+  account_infos.extend(accounts.beneficiary_token_accounts);
+
+  let data = AggregatorInstruction::DistributeFees.try_to_vec()?;
+  invoke(&accounts.aggregator_program, account_infos, None, data, signer_seeds)
+}
+
+/// the user transfer authority must be the PDA of `authority_seeds`
+fn check_authority_seeds(caller_program_id: &Pubkey, authority: &Pubkey, authority_seeds: &[&[u8]]) -> ProgramResult {
+  let seeds_authority = Pubkey::create_program_address(authority_seeds, caller_program_id)
+    .map_err(|_| AggregatorError::InvalidUserAuthoritySeeds)?;
+  if seeds_authority != *authority {
+    return Err(AggregatorError::InvalidUserAuthoritySeeds.into());
+  }
+  Ok(())
+}
+
+/// `SwapResult` the aggregator set as return data
+fn swap_result(aggregator_program_id: &Pubkey) -> Result<SwapResult, ProgramError> {
+  match get_return_data() {
+    Some((program_id, data)) if program_id == *aggregator_program_id => {
+      SwapResult::try_from_slice(&data).map_err(|_| AggregatorError::InvalidReturnData.into())
+    },
+    _ => Err(AggregatorError::InvalidReturnData.into()),
+  }
+}
+
+/// Invokes the aggregator with `account_infos` as accounts, keeping their signer and writable flags.
+/// The account at `signer_index` signs in any case, a PDA of the caller through `signer_seeds`.
+fn invoke<'a>(
+  aggregator_program: &AccountInfo<'a>,
+  mut account_infos: Vec<AccountInfo<'a>>,
+  signer_index: Option<usize>,
+  data: Vec<u8>,
+  signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+  let instruction = Instruction {
+    program_id: *aggregator_program.key,
+    accounts: account_infos
+      .iter()
+      .enumerate()
+      .map(|(index, info)| AccountMeta {
+        pubkey: *info.key,
+        is_signer: info.is_signer || signer_index == Some(index),
+        is_writable: info.is_writable,
+      })
+      .collect(),
+    data,
+  };
+
+  account_infos.push(aggregator_program.clone());
+  invoke_signed(&instruction, &account_infos, signer_seeds)
+}
@@ -0,0 +1,363 @@
+//! Decoding of aggregator transactions
+//!
+//! Rebuilds the swaps of a confirmed transaction from its compiled
+//! instructions, the venue instructions the aggregator invoked and the events
+//! in its log messages. Account indices are resolved against the account keys
+//! of the transaction and named after the roles of the instruction docs.
+
+use {
+  crate::{
+    constant::*,
+    dex::{mercurial, raydium::AmmInstruction},
+    event::{AggregatorEvent, EVENT_LOG_PREFIX},
+    instruction::{AggregatorInstruction, AggregatorPath},
+  },
+
+  base64::{engine::general_purpose::STANDARD, Engine},
+  borsh::BorshDeserialize,
+
+  solana_program::{instruction::CompiledInstruction, pubkey::Pubkey},
+
+  stable_swap_client::instruction::SwapInstruction as SaberInstruction,
+};
+
+/// Log prefix of the native builds, which log events with `msg!`
+const NATIVE_LOG_PREFIX: &str = "Program log: ";
+
+/// Inner instructions of a top level instruction, as reported by the RPC
+#[derive(Clone, Debug, PartialEq)]
+pub struct InnerInstructions {
+  /// Index of the top level instruction
+  pub index: u8,
+  pub instructions: Vec<CompiledInstruction>,
+}
+
+/// Account of a decoded instruction
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountRole {
+  pub role: String,
+  /// `None` when the index is outside the given account keys
+  pub pubkey: Option<Pubkey>,
+}
+
+/// Instruction of a route venue
+#[derive(Clone, Debug, PartialEq)]
+pub enum VenueInstruction {
+  Raydium(AmmInstruction),
+  Saber(SaberInstruction),
+  Mercurial(mercurial::SwapInstruction),
+}
+
+/// Venue instruction invoked by the aggregator
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedVenueInstruction {
+  pub program_id: Pubkey,
+  pub instruction: VenueInstruction,
+  pub accounts: Vec<AccountRole>,
+}
+
+/// Top level aggregator instruction with its venue calls and events
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedInstruction {
+  /// Index of the instruction in the transaction
+  pub index: u8,
+  /// `None` when the data is not an aggregator instruction
+  pub instruction: Option<AggregatorInstruction>,
+  pub accounts: Vec<AccountRole>,
+  pub venue_instructions: Vec<DecodedVenueInstruction>,
+  pub events: Vec<AggregatorEvent>,
+}
+
+/// Decodes the top level aggregator instructions of a transaction
+///
+/// `account_keys` are the static keys of the message followed by the loaded
+/// writable and readonly addresses, the order compiled indices refer to.
+pub fn decode_transaction(
+  program_id: &Pubkey,
+  account_keys: &[Pubkey],
+  instructions: &[CompiledInstruction],
+  inner_instructions: &[InnerInstructions],
+  log_messages: &[String],
+) -> Vec<DecodedInstruction> {
+  let events = decode_events(program_id, log_messages);
+
+  instructions
+    .iter()
+    .enumerate()
+    .filter(|(_, instruction)| account_key(account_keys, instruction.program_id_index) == Some(*program_id))
+    .map(|(index, instruction)| {
+      let index = index as u8;
+      let aggregator_instruction = AggregatorInstruction::try_from_slice(&instruction.data).ok();
+      let roles = aggregator_account_roles(aggregator_instruction.as_ref(), instruction.accounts.len());
+
+      let venue_instructions = inner_instructions
+        .iter()
+        .filter(|inner| inner.index == index)
+        .flat_map(|inner| inner.instructions.iter())
+        .filter_map(|inner| decode_venue_instruction(account_keys, inner))
+        .collect();
+
+      DecodedInstruction {
+        index,
+        instruction: aggregator_instruction,
+        accounts: account_roles(account_keys, &instruction.accounts, roles),
+        venue_instructions,
+        events: events.get(index as usize).cloned().unwrap_or_default(),
+      }
+    })
+    .collect()
+}
+
+/// Decodes a venue instruction, `None` for other programs and unknown data
+pub fn decode_venue_instruction(account_keys: &[Pubkey], instruction: &CompiledInstruction) -> Option<DecodedVenueInstruction> {
+  let program_id = account_key(account_keys, instruction.program_id_index)?;
+
+  let (venue_instruction, roles) = if program_id == RAYDIUM_SWAP_PROGRAM_ID {
+    let amm_instruction = AmmInstruction::unpack(&instruction.data).ok()?;
+    let roles = match amm_instruction {
+      AmmInstruction::Swap(_) => raydium_swap_roles(),
+      _ => Vec::new(),
+    };
+    (VenueInstruction::Raydium(amm_instruction), roles)
+  } else if program_id == SABER_SWAP_PROGRAM_ID {
+    let saber_instruction = SaberInstruction::unpack(&instruction.data).ok()?;
+    let roles = match saber_instruction {
+      SaberInstruction::Swap(_) => saber_swap_roles(),
+      _ => Vec::new(),
+    };
+    (VenueInstruction::Saber(saber_instruction), roles)
+  } else if program_id == MERCURIAL_SWAP_PROGRAM_ID {
+    let mercurial_instruction = mercurial::SwapInstruction::unpack(&instruction.data).ok()?;
+    let roles = mercurial_exchange_roles(instruction.accounts.len());
+    (VenueInstruction::Mercurial(mercurial_instruction), roles)
+  } else {
+    return None;
+  };
+
+  Some(DecodedVenueInstruction {
+    program_id,
+    instruction: venue_instruction,
+    accounts: account_roles(account_keys, &instruction.accounts, roles),
+  })
+}
+
+/// Events of the aggregator per top level instruction, from the log messages
+///
+/// Follows the `invoke` and `success`/`failed` lines to keep only the data
+/// logged while the aggregator is the running program. Logs cut by the log
+/// limit leave the last instructions without their events.
+pub fn decode_events(program_id: &Pubkey, log_messages: &[String]) -> Vec<Vec<AggregatorEvent>> {
+  let program_id = program_id.to_string();
+  let mut events: Vec<Vec<AggregatorEvent>> = Vec::new();
+  let mut stack: Vec<&str> = Vec::new();
+
+  for log_message in log_messages {
+    if let Some((invoked, _)) = log_message.strip_prefix("Program ").and_then(|rest| rest.split_once(" invoke [")) {
+      // an invoke on an empty stack starts the next top level instruction, whatever depth is logged
+      if stack.is_empty() {
+        events.push(Vec::new());
+      }
+      stack.push(invoked);
+      continue;
+    }
+
+    let running = stack.last().copied();
+    if running.is_some_and(|running| {
+      log_message == &format!("Program {} success", running) ||
+        log_message.starts_with(&format!("Program {} failed", running))
+    }) {
+      stack.pop();
+      continue;
+    }
+
+    if running != Some(program_id.as_str()) {
+      continue;
+    }
+    let data = log_message.strip_prefix(NATIVE_LOG_PREFIX).unwrap_or(log_message);
+    let event = data
+      .strip_prefix(EVENT_LOG_PREFIX)
+      .and_then(|data| STANDARD.decode(data).ok())
+      .and_then(|data| AggregatorEvent::try_from_slice(&data).ok());
+    if let (Some(event), Some(instruction_events)) = (event, events.last_mut()) {
+      instruction_events.push(event);
+    }
+  }
+
+  events
+}
+
+fn account_key(account_keys: &[Pubkey], index: u8) -> Option<Pubkey> {
+  account_keys.get(index as usize).copied()
+}
+
+/// Names the accounts after `roles`, the accounts past the roles keep their position as name
+fn account_roles(account_keys: &[Pubkey], accounts: &[u8], roles: Vec<String>) -> Vec<AccountRole> {
+  let mut roles = roles.into_iter();
+  accounts
+    .iter()
+    .enumerate()
+    .map(|(position, index)| AccountRole {
+      role: roles.next().unwrap_or_else(|| format!("account {}", position)),
+      pubkey: account_key(account_keys, *index),
+    })
+    .collect()
+}
+
+fn roles(names: &[&str]) -> Vec<String> {
+  names.iter().map(|name| name.to_string()).collect()
+}
+
+/// Roles of the accounts of an aggregator instruction, see [AggregatorInstruction]
+fn aggregator_account_roles(instruction: Option<&AggregatorInstruction>, accounts_len: usize) -> Vec<String> {
+  let instruction = match instruction {
+    Some(instruction) => instruction,
+    None => return Vec::new(),
+  };
+
+  let (mut account_roles, trailing_role) = match instruction {
+    AggregatorInstruction::UpdateState { .. } => (
+      roles(&["program state", "state owner", "new state owner", "system program", "rent sysvar"]),
+      "admin signer",
+    ),
+    AggregatorInstruction::RouteSwap { route1, .. } | AggregatorInstruction::SimulateRoute { route1, .. } => {
+      (route_swap_roles(route1, &AggregatorPath::Skip), "unused account")
+    },
+    AggregatorInstruction::RouteSwapWithOptions { route1, route2, options, .. } |
+    AggregatorInstruction::SimulateRouteWithOptions { route1, route2, options, .. } => {
+      let mut account_roles = roles(&["system program", "rent sysvar", "native mint"]);
+      if options.create_destination_account {
+        account_roles.extend(roles(&["associated token program", "destination mint"]));
+      }
+      if *route2 != AggregatorPath::Skip {
+        account_roles.extend(roles(&["intermediate token account", "intermediate mint"]));
+      }
+      account_roles.extend(route_swap_roles(route1, route2));
+      (account_roles, "unused account")
+    },
+    AggregatorInstruction::UpdateFeeBeneficiaries { weights } => {
+      let mut account_roles = roles(&["program state", "state owner"]);
+      account_roles.extend((0..weights.len()).map(|index| format!("beneficiary {}", index)));
+      (account_roles, "admin signer")
+    },
+    AggregatorInstruction::DistributeFees => (
+      roles(&["program state", "fee vault", "token program"]),
+      "beneficiary token account",
+    ),
+    AggregatorInstruction::UpdateDiscountTiers { .. } => (
+      roles(&["program state", "fee admin", "discount mint"]),
+      "admin signer",
+    ),
+    AggregatorInstruction::QueueFeeChange { .. } | AggregatorInstruction::ExecuteFeeChange => (
+      roles(&["program state", "fee admin", "clock sysvar"]),
+      "admin signer",
+    ),
+    AggregatorInstruction::CancelFeeChange => (roles(&["program state", "fee admin"]), "admin signer"),
+    AggregatorInstruction::SetPause { .. } => (roles(&["program state", "pause authority"]), "admin signer"),
+    AggregatorInstruction::SetAdminSigners { signer_count, .. } => {
+      let mut account_roles = roles(&["program state", "state owner"]);
+      account_roles.extend((0..*signer_count).map(|index| format!("new admin signer {}", index)));
+      (account_roles, "admin signer")
+    },
+    AggregatorInstruction::SetRoles => (
+      roles(&["program state", "state owner", "fee admin", "route admin", "pause guardian"]),
+      "admin signer",
+    ),
+    AggregatorInstruction::SetRouteEnabled { .. } => (roles(&["program state", "route admin"]), "admin signer"),
+    AggregatorInstruction::MigrateState => (
+      roles(&["program state", "state owner", "system program", "rent sysvar"]),
+      "admin signer",
+    ),
+    AggregatorInstruction::SetPoolAllowed { .. } => (roles(&["program state", "route admin"]), "admin signer"),
+  };
+
+  while account_roles.len() < accounts_len {
+    account_roles.push(trailing_role.to_string());
+  }
+  account_roles
+}
+
+/// Roles of the `RouteSwap` accounts, with the pools of both legs
+fn route_swap_roles(route1: &AggregatorPath, route2: &AggregatorPath) -> Vec<String> {
+  let mut account_roles = roles(&[
+    "program state",
+    "user transfer authority",
+    "source token account",
+    "destination token account",
+    "fee vault",
+    "token program",
+  ]);
+  account_roles.extend(route_pool_roles(route1));
+  account_roles.extend(route_pool_roles(route2));
+  account_roles.push("discount token account".to_string());
+  account_roles
+}
+
+fn route_pool_roles(route: &AggregatorPath) -> Vec<String> {
+  match route {
+    AggregatorPath::Raydium => roles(&[
+      "amm",
+      "amm authority",
+      "amm open orders",
+      "amm target orders",
+      "pool coin token account",
+      "pool pc token account",
+      "serum program",
+      "serum market",
+      "serum bids",
+      "serum asks",
+      "serum event queue",
+      "serum coin vault",
+      "serum pc vault",
+      "serum vault signer",
+      "raydium program",
+    ]),
+    AggregatorPath::Saber => roles(&[
+      "swap",
+      "swap authority",
+      "source reserve",
+      "destination reserve",
+      "admin fee account",
+      "clock sysvar",
+      "saber program",
+    ]),
+    AggregatorPath::Mercurial => {
+      let mut pool_roles = roles(&["swap", "pool authority"]);
+      pool_roles.extend((0..MERCURIAL_SWAP_ACCOUNTS - 3).map(|index| format!("pool token account {}", index)));
+      pool_roles.push("mercurial program".to_string());
+      pool_roles
+    },
+    _ => Vec::new(),
+  }
+}
+
+/// Roles of the accounts of the Raydium `Swap` instruction
+fn raydium_swap_roles() -> Vec<String> {
+  let mut swap_roles = roles(&["token program"]);
+  swap_roles.extend(route_pool_roles(&AggregatorPath::Raydium).into_iter().take(RAYDIUM_SWAP_ACCOUNTS - 1));
+  swap_roles.extend(roles(&["user source token account", "user destination token account", "user owner"]));
+  swap_roles
+}
+
+/// Roles of the accounts of the Saber `Swap` instruction
+fn saber_swap_roles() -> Vec<String> {
+  roles(&[
+    "swap",
+    "swap authority",
+    "user authority",
+    "user source token account",
+    "source reserve",
+    "destination reserve",
+    "user destination token account",
+    "admin fee account",
+    "token program",
+  ])
+}
+
+/// Roles of the accounts of the Mercurial `Exchange` instruction, with its pool token accounts
+fn mercurial_exchange_roles(accounts_len: usize) -> Vec<String> {
+  let mut exchange_roles = roles(&["swap", "token program", "pool authority", "user transfer authority"]);
+  let pool_token_accounts = accounts_len.saturating_sub(exchange_roles.len() + 2);
+  exchange_roles.extend((0..pool_token_accounts).map(|index| format!("pool token account {}", index)));
+  exchange_roles.extend(roles(&["user source token account", "user destination token account"]));
+  exchange_roles
+}
@@ -5,6 +5,8 @@
 //! - converting a [SwapInstruction] into byte slices
 //! - providing functions for downstream users to easily build [SwapInstruction]s
 
+#![allow(clippy::too_many_arguments)]
+
 use solana_program::instruction::AccountMeta;
 use solana_program::instruction::Instruction;
 use solana_program::program_error::ProgramError;
@@ -56,7 +58,7 @@ impl SwapInstruction {
           minimum_out_amount
         }
       }
-      _ => return Err(ProgramError::InvalidAccountData.into()),
+      _ => return Err(ProgramError::InvalidAccountData),
     })
   }
 
@@ -86,21 +88,6 @@ impl SwapInstruction {
     Ok((amount, rest))
   }
 
-  fn unpack_u32(input: &[u8]) -> Result<(u32, &[u8]), ProgramError> {
-    if input.len() >= 4 {
-      let (amount, rest) = input.split_at(4);
-      let amount = amount
-          .get(..4)
-          .and_then(|slice| slice.try_into().ok())
-          .map(u32::from_le_bytes)
-          .ok_or(ProgramError::InvalidAccountData)?;
-
-      Ok((amount, rest))
-    } else {
-      return Err(ProgramError::InvalidAccountData);
-    }
-  }
-
   fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
     if input.len() >= 8 {
       let (amount, rest) = input.split_at(8);
@@ -139,8 +126,8 @@ pub fn exchange(
   for token_account_address in swap_token_accounts_addresses {
     accounts.push(AccountMeta::new(*token_account_address, false));
   }
-  accounts.push(AccountMeta::new_readonly(*source_token_account_address, false));
-  accounts.push(AccountMeta::new_readonly(*destination_token_account_address, false));
+  accounts.push(AccountMeta::new(*source_token_account_address, false));
+  accounts.push(AccountMeta::new(*destination_token_account_address, false));
 
   Ok(Instruction {
     program_id: *program_id,
@@ -7,7 +7,7 @@ use solana_program::{
   program_error::ProgramError,
   pubkey::Pubkey,
 };
-use std::mem::size_of;
+use std::{convert::TryInto, mem::size_of};
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
@@ -188,6 +188,7 @@ pub enum AmmInstruction {
     ///   3. `[]` (optional) the account to replace owner
     // SetParams(SetParamsInstruction),
 
+    ///
     ///   Withdraw Pnl from pool
     ///
     ///   0. `[]` Spl Token program id
@@ -247,7 +248,7 @@ impl AmmInstruction {
   pub fn pack(&self) -> Result<Vec<u8>, ProgramError> {
     let mut buf = Vec::with_capacity(size_of::<Self>());
 
-    match &*self {
+    match self {
       Self::Initialize(
         InitializeInstruction {nonce}
       ) => {
@@ -298,6 +299,74 @@ impl AmmInstruction {
     }
     Ok(buf)
   }
+
+  /// Unpacks a byte buffer into a [AmmInstruction](enum.AmmInstruction.html).
+  pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+    let (&tag, rest) = input.split_first().ok_or(ProgramError::InvalidAccountData)?;
+
+    Ok(match tag {
+      0 => {
+        let (&nonce, _) = rest.split_first().ok_or(ProgramError::InvalidAccountData)?;
+        Self::Initialize(InitializeInstruction { nonce })
+      }
+      1 => Self::Initialize2,
+      2 => {
+        let (plan_order_limit, rest) = Self::unpack_u16(rest)?;
+        let (place_order_limit, rest) = Self::unpack_u16(rest)?;
+        let (cancel_order_limit, _) = Self::unpack_u16(rest)?;
+        Self::MonitorStep(MonitorStepInstruction { plan_order_limit, place_order_limit, cancel_order_limit })
+      }
+      3 => {
+        let (max_coin_amount, rest) = Self::unpack_u64(rest)?;
+        let (max_pc_amount, rest) = Self::unpack_u64(rest)?;
+        let (base_side, _) = Self::unpack_u64(rest)?;
+        Self::Deposit(DepositInstruction { max_coin_amount, max_pc_amount, base_side })
+      }
+      4 => {
+        let (amount, _) = Self::unpack_u64(rest)?;
+        Self::Withdraw(WithdrawInstruction { amount })
+      }
+      5 => {
+        let (limit, _) = Self::unpack_u16(rest)?;
+        Self::WithdrawTransfer(WithdrawTransferInstruction { limit })
+      }
+      7 => Self::WithdrawPnl,
+      8 => {
+        let (amount, _) = Self::unpack_u64(rest)?;
+        Self::WithdrawSrm(WithdrawSrmInstruction { amount })
+      }
+      9 => {
+        let (amount_in, rest) = Self::unpack_u64(rest)?;
+        let (minimum_amount_out, _) = Self::unpack_u64(rest)?;
+        Self::Swap(SwapInstruction { amount_in, minimum_amount_out })
+      }
+      10 => {
+        let (&nonce, _) = rest.split_first().ok_or(ProgramError::InvalidAccountData)?;
+        Self::PreInitialize(InitializeInstruction { nonce })
+      }
+      _ => return Err(ProgramError::InvalidAccountData),
+    })
+  }
+
+  fn unpack_u16(input: &[u8]) -> Result<(u16, &[u8]), ProgramError> {
+    if input.len() < 2 {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    let (value, rest) = input.split_at(2);
+    Ok((u16::from_le_bytes([value[0], value[1]]), rest))
+  }
+
+  fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
+    if input.len() < 8 {
+      return Err(ProgramError::InvalidAccountData);
+    }
+    let (value, rest) = input.split_at(8);
+    let value = value
+      .try_into()
+      .map(u64::from_le_bytes)
+      .map_err(|_| ProgramError::InvalidAccountData)?;
+    Ok((value, rest))
+  }
 }
 
 /// Creates a 'swap' instruction.
@@ -10,6 +10,10 @@ use thiserror::Error;
 pub enum AggregatorError {
   #[error("Swap account already in use")]
   AlreadyInUse,
+
+  // The four program id errors below came from parsing the program ids at runtime,
+  // which are now compile time constants. They are no longer returned but stay
+  // reserved, so the codes of the following errors don't shift.
   #[error("Invalid Raydium program id")]
   InvalidRaydiumProgramId,
 
@@ -66,6 +70,93 @@ pub enum AggregatorError {
 
   #[error("Not expected mint")]
   NotExpectedMint,
+
+  #[error("Invalid fee beneficiaries")]
+  InvalidFeeBeneficiaries,
+
+  #[error("Invalid beneficiary token account")]
+  InvalidBeneficiaryAccount,
+
+  #[error("Invalid fee discount tiers")]
+  InvalidDiscountTiers,
+
+  #[error("Invalid discount token account")]
+  InvalidDiscountAccount,
+
+  #[error("Fee change is timelocked")]
+  FeeChangeTimelocked,
+
+  #[error("No pending fee change")]
+  NoPendingFeeChange,
+
+  #[error("Program is paused")]
+  ProgramPaused,
+
+  #[error("Invalid admin signers")]
+  InvalidAdminSigners,
+
+  #[error("Not enough admin signatures")]
+  NotEnoughAdminSignatures,
+
+  #[error("Invalid fee admin")]
+  InvalidFeeAdmin,
+
+  #[error("Invalid route admin")]
+  InvalidRouteAdmin,
+
+  #[error("Invalid pause guardian")]
+  InvalidPauseGuardian,
+
+  #[error("Route is disabled")]
+  RouteDisabled,
+
+  #[error("Route simulated, the result is in the return data")]
+  RouteSimulated,
+
+  #[error("Invalid return data")]
+  InvalidReturnData,
+
+  #[error("User transfer authority did not sign")]
+  MissingUserSignature,
+
+  #[error("Seeds do not derive the user transfer authority")]
+  InvalidUserAuthoritySeeds,
+
+  #[error("Invalid temporary wrapped SOL account")]
+  InvalidNativeAccount,
+
+  #[error("Invalid associated token program id")]
+  InvalidAssociatedTokenProgramId,
+
+  #[error("Route options can't be combined")]
+  ConflictingRouteOptions,
+
+  #[error("Destination is not the associated token account of the user")]
+  InvalidDestinationAccount,
+
+  #[error("Invalid intermediate token account")]
+  InvalidIntermediateAccount,
+
+  #[error("Program state has an older layout, run MigrateState")]
+  OutdatedStateLayout,
+
+  #[error("Program state already has the current layout")]
+  StateAlreadyMigrated,
+
+  #[error("Invalid fee change delay")]
+  InvalidFeeChangeDelay,
+
+  #[error("Two leg routes need the intermediate account of RouteSwapWithOptions or SimulateRouteWithOptions")]
+  UnsupportedSecondLeg,
+
+  #[error("Pool is not in the pool allowlist")]
+  PoolNotAllowed,
+
+  #[error("Pool allowlist is full")]
+  PoolAllowlistFull,
+
+  #[error("Amount out is below the minimum amount out")]
+  SlippageExceeded,
 }
 
 impl From<AggregatorError> for ProgramError {
@@ -0,0 +1,272 @@
+//! Events for indexers
+//! Each event is Borsh encoded and logged with `sol_log_data`, so it shows up
+//! as a `Program data: <base64>` log line of the aggregator program. Native
+//! builds log the same text with `msg!`, which shows up as
+//! `Program log: Program data: <base64>`; the decoder accepts both.
+
+use {
+  crate::{
+    instruction::AggregatorPath,
+    processor::{DiscountTier, FeeBeneficiary},
+  },
+
+  borsh::{BorshDeserialize, BorshSchema, BorshSerialize},
+
+  solana_program::{program_error::ProgramError, pubkey::Pubkey},
+};
+
+#[cfg(target_os = "solana")]
+use solana_program::log::sol_log_data;
+
+#[cfg(not(target_os = "solana"))]
+use {
+  base64::{engine::general_purpose::STANDARD, Engine},
+  solana_program::msg,
+};
+
+/// Prefix of the log line of an event, followed by the base64 Borsh encoding
+pub const EVENT_LOG_PREFIX: &str = "Program data: ";
+
+/// Events logged by the program, the Borsh enum tag tells them apart
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub enum AggregatorEvent {
+  /// A route was swapped
+  Swap(
+    #[allow(dead_code)]
+    SwapEvent
+  ),
+
+  /// `UpdateState` changed the program state
+  StateUpdated(
+    #[allow(dead_code)]
+    StateUpdatedEvent
+  ),
+
+  /// `MigrateState` grew the state account to the current layout
+  StateMigrated(
+    #[allow(dead_code)]
+    StateMigratedEvent
+  ),
+
+  /// `UpdateFeeBeneficiaries` replaced the beneficiaries
+  FeeBeneficiariesUpdated(
+    #[allow(dead_code)]
+    FeeBeneficiariesUpdatedEvent
+  ),
+
+  /// `UpdateDiscountTiers` replaced the discount mint and tiers
+  DiscountTiersUpdated(
+    #[allow(dead_code)]
+    DiscountTiersUpdatedEvent
+  ),
+
+  /// `QueueFeeChange` queued a fee change
+  FeeChangeQueued(
+    #[allow(dead_code)]
+    FeeChangeEvent
+  ),
+
+  /// `ExecuteFeeChange` applied the queued fee change
+  FeeChangeExecuted(
+    #[allow(dead_code)]
+    FeeChangeEvent
+  ),
+
+  /// `CancelFeeChange` dropped the queued fee change
+  FeeChangeCancelled(
+    #[allow(dead_code)]
+    FeeChangeEvent
+  ),
+
+  /// `SetPause` paused or resumed swaps
+  PauseUpdated(
+    #[allow(dead_code)]
+    PauseUpdatedEvent
+  ),
+
+  /// `SetAdminSigners` replaced the admin signers
+  AdminSignersUpdated(
+    #[allow(dead_code)]
+    AdminSignersUpdatedEvent
+  ),
+
+  /// `SetRoles` assigned the admin roles
+  RolesUpdated(
+    #[allow(dead_code)]
+    RolesUpdatedEvent
+  ),
+
+  /// `SetRouteEnabled` enabled or disabled a route
+  RouteUpdated(
+    #[allow(dead_code)]
+    RouteUpdatedEvent
+  ),
+
+  /// `SetPoolAllowed` added a pool to the pool allowlist or removed it
+  PoolAllowlistUpdated(
+    #[allow(dead_code)]
+    PoolAllowlistUpdatedEvent
+  ),
+}
+
+impl AggregatorEvent {
+  /// log the event as program data
+  pub fn emit(&self) -> Result<(), ProgramError> {
+    let data = self.try_to_vec()?;
+
+    #[cfg(target_os = "solana")]
+    sol_log_data(&[&data]);
+
+    // program-test does not record `sol_log_data` of native processors, so log the same line
+    #[cfg(not(target_os = "solana"))]
+    msg!("{}{}", EVENT_LOG_PREFIX, STANDARD.encode(&data));
+
+    Ok(())
+  }
+}
+
+/// Swap through a route
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct SwapEvent {
+  // User transfer authority
+  pub user: Pubkey,
+
+  // Mint of the source token account
+  pub input_mint: Pubkey,
+
+  // Mint of the destination token account
+  pub output_mint: Pubkey,
+
+  // Amount taken from the source, fee included
+  pub amount_in: u64,
+
+  // Aggregator fee paid into the fee vault
+  pub fee: u64,
+
+  // Legs of the route, in order
+  pub legs: Vec<SwapLegEvent>,
+
+  // Amount received on the destination
+  pub amount_out: u64,
+}
+
+/// Leg of a swapped route
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct SwapLegEvent {
+  // Venue of the leg
+  pub venue: AggregatorPath,
+
+  // Amount out of the leg
+  pub amount_out: u64,
+}
+
+/// Program state change of `UpdateState`
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct StateUpdatedEvent {
+  // The state was initialized by this update
+  pub initialized: bool,
+
+  // Owner before the update
+  pub previous_owner: Pubkey,
+
+  // Owner after the update
+  pub state_owner: Pubkey,
+
+  // Fee ratio after the update
+  pub fee_numerator: u64,
+
+  // Fee ratio after the update
+  pub fee_denominator: u64,
+}
+
+/// State account growth of `MigrateState`
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct StateMigratedEvent {
+  // Size of the state account before the migration
+  pub previous_len: u64,
+
+  // Size of the state account after the migration
+  pub len: u64,
+}
+
+/// Fee beneficiaries set by `UpdateFeeBeneficiaries`
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct FeeBeneficiariesUpdatedEvent {
+  // Beneficiaries in the state order
+  pub beneficiaries: Vec<FeeBeneficiary>,
+}
+
+/// Fee discount set by `UpdateDiscountTiers`
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct DiscountTiersUpdatedEvent {
+  // Mint whose holders get a fee discount
+  pub discount_mint: Pubkey,
+
+  // Discount tiers in the state order
+  pub tiers: Vec<DiscountTier>,
+}
+
+/// Fee change queued, executed or cancelled
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct FeeChangeEvent {
+  // Fee ratio of the change
+  pub fee_numerator: u64,
+
+  // Fee ratio of the change
+  pub fee_denominator: u64,
+
+  // Fee change delay set by the change, in seconds
+  pub fee_change_delay: i64,
+
+  // Unix timestamp from which the change can be executed
+  pub effective_timestamp: i64,
+}
+
+/// Pause switch of `SetPause`
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct PauseUpdatedEvent {
+  // Swaps are paused after the update
+  pub is_paused: bool,
+
+  // Signer of the instruction, the pause guardian or the owner authority
+  pub authority: Pubkey,
+}
+
+/// Admin signers set by `SetAdminSigners`
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct AdminSignersUpdatedEvent {
+  // Admin signers, empty for single key ownership
+  pub admin_signers: Vec<Pubkey>,
+
+  // Number of admin signers an admin instruction needs
+  pub threshold: u8,
+}
+
+/// Admin roles set by `SetRoles`, the default pubkey leaves a role to the owner authority
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct RolesUpdatedEvent {
+  pub fee_admin: Pubkey,
+  pub route_admin: Pubkey,
+  pub pause_guardian: Pubkey,
+}
+
+/// Route switch of `SetRouteEnabled`
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct RouteUpdatedEvent {
+  pub route: AggregatorPath,
+
+  // Swaps through the route are enabled after the update
+  pub is_enabled: bool,
+}
+
+/// Pool allowlist change of `SetPoolAllowed`
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct PoolAllowlistUpdatedEvent {
+  pub pool: Pubkey,
+
+  // The pool is in the allowlist after the update
+  pub is_allowed: bool,
+
+  // Pools in the allowlist after the update, empty allows every pool
+  pub allowed_pools: Vec<Pubkey>,
+}
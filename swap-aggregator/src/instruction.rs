@@ -6,12 +6,26 @@
 
 use {
   borsh::{BorshSerialize, BorshDeserialize, BorshSchema},
+  crate::processor::DiscountTier,
+  solana_program::pubkey::Pubkey,
 };
 
 /// Instructions supported by the Stability Pool program.
+///
+/// In multisig mode, admin instructions also need the signatures of enough
+/// admin signers, which can be appended to the accounts.
 #[repr(C)]
 #[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
 pub enum AggregatorInstruction {
+  /// Create and initialize the program state, or hand it to a new owner.
+  /// The fee ratio is only set at initialization, afterwards it must be the stored
+  /// ratio and fee changes go through `QueueFeeChange`.
+  ///
+  /// 0. `[writable]` Program state
+  /// 1. `[writable, signer]` State owner, or an admin signer in multisig mode, paying the state at creation
+  /// 2. `[]` New state owner
+  /// 3. `[]` System program
+  /// 4. `[]` Rent sysvar
   UpdateState {
     #[allow(dead_code)]
     fee_numerator: u64,
@@ -20,6 +34,18 @@ pub enum AggregatorInstruction {
     fee_denominator: u64
   },
 
+  /// Swap through the route, the aggregator fee is taken from the source first.
+  /// The `SwapResult` is set as return data for CPI callers. Only single leg
+  /// routes, `route2` must be `Skip`; two leg routes go through `RouteSwapWithOptions`.
+  ///
+  /// 0. `[]` Program state
+  /// 1. `[signer]` User transfer authority, a PDA of a calling program signs with `invoke_signed`
+  /// 2. `[writable]` Source token account
+  /// 3. `[writable]` Destination token account
+  /// 4. `[writable]` Fee vault of the source mint, owned by the program state
+  /// 5. `[]` Token program
+  /// 6. `[writable]` Pool accounts of the route
+  /// 7. `[]` (optional) Discount mint token account of the user, for a fee discount
   RouteSwap {
     #[allow(dead_code)]
     route1: AggregatorPath,
@@ -32,7 +58,229 @@ pub enum AggregatorInstruction {
 
     #[allow(dead_code)]
     amount_out: u64,
-  }
+  },
+
+  /// Replace the fee beneficiaries table
+  ///
+  /// 0. `[writable]` Program state
+  /// 1. `[signer]` State owner, or an admin signer in multisig mode
+  /// 2. `[]` Beneficiary wallets, one per weight in the same order
+  UpdateFeeBeneficiaries {
+    #[allow(dead_code)]
+    weights: Vec<u64>,
+  },
+
+  /// Pay the fee vault balance out to the beneficiaries, pro rata to their weight
+  ///
+  /// 0. `[]` Program state
+  /// 1. `[writable]` Fee vault, a token account owned by the program state
+  /// 2. `[]` Token program
+  /// 3. `[writable]` Beneficiary token accounts of the vault mint, one per beneficiary in the state order
+  DistributeFees,
+
+  /// Set the discount mint and replace the fee discount tiers
+  ///
+  /// 0. `[writable]` Program state
+  /// 1. `[signer]` Fee admin or state owner, an admin signer in multisig mode
+  /// 2. `[]` Discount mint
+  UpdateDiscountTiers {
+    #[allow(dead_code)]
+    tiers: Vec<DiscountTier>,
+  },
+
+  /// Queue a fee change, executable once the current fee change delay has passed.
+  /// `fee_change_delay` is the delay of the changes queued after this one, in seconds.
+  ///
+  /// 0. `[writable]` Program state
+  /// 1. `[signer]` Fee admin or state owner, an admin signer in multisig mode
+  /// 2. `[]` Clock sysvar
+  QueueFeeChange {
+    #[allow(dead_code)]
+    fee_numerator: u64,
+
+    #[allow(dead_code)]
+    fee_denominator: u64,
+
+    #[allow(dead_code)]
+    fee_change_delay: i64
+  },
+
+  /// Apply the queued fee ratio and delay after the timelock
+  ///
+  /// 0. `[writable]` Program state
+  /// 1. `[signer]` Fee admin or state owner, an admin signer in multisig mode
+  /// 2. `[]` Clock sysvar
+  ExecuteFeeChange,
+
+  /// Drop the queued fee change
+  ///
+  /// 0. `[writable]` Program state
+  /// 1. `[signer]` Fee admin or state owner, an admin signer in multisig mode
+  CancelFeeChange,
+
+  /// Pause or resume swaps, effective immediately
+  ///
+  /// 0. `[writable]` Program state
+  /// 1. `[signer]` Pause guardian to pause, or the owner authority
+  SetPause {
+    #[allow(dead_code)]
+    is_paused: bool
+  },
+
+  /// Replace the admin signers, a zero threshold with no signers restores single key ownership
+  ///
+  /// 0. `[writable]` Program state
+  /// 1. `[signer]` State owner, or an admin signer in multisig mode
+  /// 2. `[]` New admin signers, `signer_count` of them
+  SetAdminSigners {
+    #[allow(dead_code)]
+    signer_count: u8,
+
+    #[allow(dead_code)]
+    threshold: u8
+  },
+
+  /// Assign the admin roles, the default pubkey leaves a role to the owner authority
+  ///
+  /// 0. `[writable]` Program state
+  /// 1. `[signer]` State owner, or an admin signer in multisig mode
+  /// 2. `[]` Fee admin
+  /// 3. `[]` Route admin
+  /// 4. `[]` Pause guardian
+  SetRoles,
+
+  /// Enable or disable swaps through a route
+  ///
+  /// 0. `[writable]` Program state
+  /// 1. `[signer]` Route admin, or the owner authority
+  SetRouteEnabled {
+    #[allow(dead_code)]
+    route: AggregatorPath,
+
+    #[allow(dead_code)]
+    is_enabled: bool
+  },
+
+  /// Execute the route like `RouteSwap`, without a minimum amount out, then fail with
+  /// `RouteSimulated` so nothing commits. The `SimulationResult` is set as return data,
+  /// for `simulateTransaction` callers to read an execution exact quote.
+  /// Only single leg routes, like `RouteSwap`; two leg routes are simulated by `SimulateRouteWithOptions`.
+  ///
+  /// Same accounts as `RouteSwap`
+  SimulateRoute {
+    #[allow(dead_code)]
+    route1: AggregatorPath,
+
+    #[allow(dead_code)]
+    route2: AggregatorPath,
+
+    #[allow(dead_code)]
+    amount_in: u64,
+  },
+
+  /// Swap like `RouteSwap`, with the options of the route.
+  ///
+  /// To wrap native SOL, `amount_in` lamports of the user transfer authority are moved into its
+  /// temporary wrapped SOL account, which is the source token account. To unwrap, the temporary
+  /// account is the destination token account. Either way it is created, then closed back to the
+  /// user transfer authority, in the instruction.
+  ///
+  /// To create the destination token account, it is the associated token account of the user transfer
+  /// authority, created by the associated token program unless it exists already, paid by the user.
+  ///
+  /// A `route2` other than `Skip` swaps the output of `route1` again. The intermediate token account
+  /// between the legs is a PDA of the user and intermediate mint owning itself, created and closed back
+  /// to the user in the instruction. The second leg swaps all it holds, so no dust or rent is left
+  /// behind. The minimum amount out applies to the second leg. A venue can fill the second leg only
+  /// in part; with `sweep_intermediate_residual` the rest is swapped again through `route2` into the
+  /// destination before the close, and the minimum amount out applies to both swaps together.
+  /// Without it, any residual, dust included, fails the close and aborts the whole swap. The residual
+  /// is never sent back to the source token account, which holds another mint.
+  ///
+  /// 0. `[]` System program
+  /// 1. `[]` Rent sysvar
+  /// 2. `[]` Native mint
+  /// 3. `[]` Associated token program, only to create the destination token account
+  /// 4. `[]` Destination mint, only to create the destination token account
+  /// 5. `[writable]` Intermediate token account, only for two leg routes
+  /// 6. `[]` Intermediate mint, only for two leg routes
+  /// 7. The `RouteSwap` accounts, with the pool accounts of `route2` after those of `route1`.
+  ///    The user transfer authority is `[writable]` to pay and receive lamports
+  RouteSwapWithOptions {
+    #[allow(dead_code)]
+    route1: AggregatorPath,
+
+    #[allow(dead_code)]
+    route2: AggregatorPath,
+
+    #[allow(dead_code)]
+    amount_in: u64,
+
+    #[allow(dead_code)]
+    amount_out: u64,
+
+    #[allow(dead_code)]
+    options: RouteOptions,
+  },
+
+  /// Grow the state account of an older program version to the current layout.
+  /// A state with a single fee owner gets it as the only fee beneficiary, the other new fields start unset.
+  ///
+  /// 0. `[writable]` Program state
+  /// 1. `[writable, signer]` State owner, or an admin signer in multisig mode, paying the extra rent
+  /// 2. `[]` System program
+  /// 3. `[]` Rent sysvar
+  MigrateState,
+
+  /// Add a pool to the pool allowlist or remove it. While the allowlist is empty every pool is allowed,
+  /// otherwise swap legs only go through its pools, identified by the first pool account of the leg.
+  ///
+  /// 0. `[writable]` Program state
+  /// 1. `[signer]` Route admin, or the owner authority
+  SetPoolAllowed {
+    #[allow(dead_code)]
+    pool: Pubkey,
+
+    #[allow(dead_code)]
+    is_allowed: bool
+  },
+
+  /// Execute the route like `RouteSwapWithOptions`, without a minimum amount out, then fail with
+  /// `RouteSimulated` and the `SimulationResult` as return data, like `SimulateRoute`. Two leg routes
+  /// go through the intermediate token account, so each leg amount out comes from its venue.
+  ///
+  /// Same accounts as `RouteSwapWithOptions`
+  SimulateRouteWithOptions {
+    #[allow(dead_code)]
+    route1: AggregatorPath,
+
+    #[allow(dead_code)]
+    route2: AggregatorPath,
+
+    #[allow(dead_code)]
+    amount_in: u64,
+
+    #[allow(dead_code)]
+    options: RouteOptions,
+  },
+}
+
+/// Options of a [RouteSwapWithOptions](AggregatorInstruction::RouteSwapWithOptions)
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct RouteOptions {
+  /// the input is native SOL, wrapped in the temporary account
+  pub wrap_native_input: bool,
+
+  /// the output is unwrapped from the temporary account to native SOL
+  pub unwrap_native_output: bool,
+
+  /// the destination associated token account is created when missing
+  pub create_destination_account: bool,
+
+  /// a two leg route swaps what the second leg left in the intermediate account into the destination,
+  /// without it a residual aborts the swap
+  pub sweep_intermediate_residual: bool,
 }
 
 #[repr(C)]
@@ -1,8 +1,7 @@
-/// Main Entrypoint and declaration file
+//! Main Entrypoint and declaration file
 
 use solana_program::{
   account_info::{ AccountInfo },
-  entrypoint,
   entrypoint::ProgramResult,
   program_error::PrintProgramError,
   pubkey::Pubkey,
@@ -10,6 +9,9 @@ use solana_program::{
 
 use error::AggregatorError;
 
+#[cfg(not(feature = "no-entrypoint"))]
+use solana_program::entrypoint;
+
 /// module declaration
 pub mod dex;
 /// instruction module
@@ -21,7 +23,42 @@ pub mod error;
 
 pub mod constant;
 
+/// events logged for indexers
+pub mod event;
+
+/// return data of the swap instructions
+pub mod return_data;
+
+/// instruction builders for off-chain clients
+#[cfg(feature = "no-entrypoint")]
+pub mod client;
+
+/// helpers for programs invoking the aggregator
+#[cfg(feature = "no-entrypoint")]
+pub mod cpi;
+
+/// off-chain quotes for the route venues
+#[cfg(feature = "no-entrypoint")]
+pub mod quote;
+
+/// route finding over the pools of the route venues
+#[cfg(feature = "no-entrypoint")]
+pub mod router;
+
+/// split of a trade across parallel pools
+#[cfg(feature = "no-entrypoint")]
+pub mod split;
+
+/// pool registry file format and loader
+#[cfg(feature = "no-entrypoint")]
+pub mod registry;
+
+/// decoding of aggregator transactions for indexers
+#[cfg(feature = "no-entrypoint")]
+pub mod decoder;
+
 // Declare and export the program's entrypoint
+#[cfg(not(feature = "no-entrypoint"))]
 entrypoint!(process_instruction);
 
 // Program entrypoint's implementation
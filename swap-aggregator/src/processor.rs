@@ -1,24 +1,30 @@
 //! Program state processor
 //! In here, All instructions are processed by Processor
+
+// this allows many arguments for the function parameter
+#![allow(clippy::too_many_arguments)]
+
 use std::convert::TryInto;
 
 use {
-  std::{
-    str::FromStr,
-    convert::{identity},
-  },
+  std::cell::Ref,
 
   crate::{
-    instruction::{AggregatorInstruction, AggregatorPath},
+    instruction::{AggregatorInstruction, AggregatorPath, RouteOptions},
     error::{AggregatorError},
     constant::*,
+    event::{
+      AdminSignersUpdatedEvent, AggregatorEvent, DiscountTiersUpdatedEvent, FeeBeneficiariesUpdatedEvent, FeeChangeEvent,
+      PauseUpdatedEvent, PoolAllowlistUpdatedEvent, RolesUpdatedEvent, RouteUpdatedEvent, StateMigratedEvent, StateUpdatedEvent, SwapEvent, SwapLegEvent,
+    },
+    return_data::{SimulationResult, SwapResult},
     dex::*,
   },
 
-  bytemuck::{cast_slice},
-
   borsh::{BorshDeserialize, BorshSchema, BorshSerialize},
 
+  bytemuck::{Pod, Zeroable},
+
   solana_program::{
     account_info::{
       next_account_info,
@@ -31,23 +37,24 @@ use {
     program::{
       invoke,
       invoke_signed,
+      set_return_data,
     },
     program_error::ProgramError,
     pubkey::Pubkey,
     program_pack::Pack,
     system_instruction,
-    sysvar::{rent::Rent, Sysvar},
+    sysvar::{clock::Clock, rent::Rent, Sysvar},
   },
 
   anchor_spl::token::{self, Transfer},
-  anchor_lang::CpiContext,
+  anchor_lang::context::CpiContext,
 };
 
 use stable_swap_client::{self};
 
 /// Program State
 #[repr(C)]
-#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+#[derive(Clone, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
 pub struct ProgramState {
   // Initialized state
   pub is_initialized:bool,
@@ -55,6 +62,102 @@ pub struct ProgramState {
   // Owner address to update the program state
   pub state_owner: Pubkey,
 
+  // Fee ratio to redistribute
+  pub fee_numerator: u64,
+
+  // Fee ratio to redistribute
+  pub fee_denominator: u64,
+
+  // Wallets sharing the collected fees, unused slots have zero weight
+  pub beneficiaries: [FeeBeneficiary; MAX_FEE_BENEFICIARIES],
+
+  // Mint whose holders get a fee discount
+  pub discount_mint: Pubkey,
+
+  // Fee discount tiers, unused slots have a zero minimum amount
+  pub discount_tiers: [DiscountTier; MAX_DISCOUNT_TIERS],
+
+  // Swaps are rejected while paused
+  pub is_paused: bool,
+
+  // Fee change waiting for its timelock
+  pub pending_fee_change: PendingFeeChange,
+
+  // Admin keys of the multisig mode
+  pub admin_signers: [Pubkey; MAX_ADMIN_SIGNERS],
+
+  // Number of used admin signer slots
+  pub admin_signer_count: u8,
+
+  // Admin signatures required, zero when `state_owner` alone controls the state
+  pub admin_threshold: u8,
+
+  // Key allowed to change fees, unset leaves it to the owner
+  pub fee_admin: Pubkey,
+
+  // Key allowed to enable and disable routes, unset leaves it to the owner
+  pub route_admin: Pubkey,
+
+  // Key allowed to pause swaps, unset leaves it to the owner
+  pub pause_guardian: Pubkey,
+
+  // Bit set of disabled routes, indexed by `AggregatorPath`
+  pub disabled_routes: u8,
+
+  // Bump seed of the state address
+  pub bump: u8,
+
+  // Delay before a queued fee change can be executed, in seconds
+  pub fee_change_delay: i64,
+
+  // Delay set by the queued fee change, kept out of `pending_fee_change` so older layouts only grow
+  pub pending_fee_change_delay: i64,
+
+  // Pools swap legs may go through, empty allows every pool
+  pub allowed_pools: [Pubkey; MAX_ALLOWED_POOLS],
+
+  // Number of used allowed pool slots
+  pub allowed_pool_count: u8,
+}
+
+impl ProgramState {
+  /// Serialized size of the program state
+  pub const LEN: usize = 1 + 32 + 8 + 8 +
+    FeeBeneficiary::LEN * MAX_FEE_BENEFICIARIES +
+    32 + DiscountTier::LEN * MAX_DISCOUNT_TIERS +
+    1 + PendingFeeChange::LEN +
+    32 * MAX_ADMIN_SIGNERS + 1 + 1 +
+    32 + 32 + 32 + 1 + 1 +
+    8 + 8 +
+    32 * MAX_ALLOWED_POOLS + 1;
+
+  /// best fee discount, in basis points, for a balance of the discount mint
+  pub fn discount_bps(&self, balance: u64) -> u64 {
+    best_discount_bps(&self.discount_tiers, balance)
+  }
+
+  /// aggregator fee a swap of `amount_in` pays, for the balance of the discount account of the user if any
+  pub fn fee_amount(&self, amount_in: u64, discount_balance: Option<u64>) -> u64 {
+    let discount_bps = discount_balance.map_or(0, |balance| self.discount_bps(balance));
+    aggregator_fee(self.fee_numerator, self.fee_denominator, discount_bps, amount_in)
+  }
+
+  /// check if swaps through the route are enabled
+  pub fn is_route_enabled(&self, route: &AggregatorPath) -> bool {
+    self.disabled_routes & (1 << route.clone() as u8) == 0
+  }
+}
+
+/// Program state of the deployments before the fee vaults, upgraded by `MigrateState`
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct LegacyProgramState {
+  // Initialized state
+  pub is_initialized: bool,
+
+  // Owner address to update the program state
+  pub state_owner: Pubkey,
+
   // Fee owner address to redistribute
   pub fee_owner: Pubkey,
 
@@ -62,7 +165,145 @@ pub struct ProgramState {
   pub fee_numerator: u64,
 
   // Fee ratio to redistribute
-  pub fee_denominator: u64
+  pub fee_denominator: u64,
+}
+
+impl LegacyProgramState {
+  /// Serialized size of the legacy program state
+  pub const LEN: usize = 1 + 32 + 32 + 8 + 8;
+}
+
+/// Zero-copy view of a serialized [ProgramState], read in place by the swap instructions.
+/// Booleans are bytes, and the pending fee change is left as raw bytes.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct ProgramStateView {
+  pub is_initialized: u8,
+  pub state_owner: Pubkey,
+  pub fee_numerator: u64,
+  pub fee_denominator: u64,
+  pub beneficiaries: [FeeBeneficiary; MAX_FEE_BENEFICIARIES],
+  pub discount_mint: Pubkey,
+  pub discount_tiers: [DiscountTier; MAX_DISCOUNT_TIERS],
+  pub is_paused: u8,
+  pub pending_fee_change: [u8; PendingFeeChange::LEN],
+  pub admin_signers: [Pubkey; MAX_ADMIN_SIGNERS],
+  pub admin_signer_count: u8,
+  pub admin_threshold: u8,
+  pub fee_admin: Pubkey,
+  pub route_admin: Pubkey,
+  pub pause_guardian: Pubkey,
+  pub disabled_routes: u8,
+  pub bump: u8,
+  pub fee_change_delay: i64,
+  pub pending_fee_change_delay: i64,
+  pub allowed_pools: [Pubkey; MAX_ALLOWED_POOLS],
+  pub allowed_pool_count: u8,
+}
+
+// the view must keep the borsh layout of the state
+const _: () = assert!(std::mem::size_of::<ProgramStateView>() == ProgramState::LEN);
+
+impl ProgramStateView {
+  /// best fee discount, in basis points, for a balance of the discount mint
+  pub fn discount_bps(&self, balance: u64) -> u64 {
+    best_discount_bps(&{ self.discount_tiers }, balance)
+  }
+
+  /// check if swaps through the route are enabled
+  pub fn is_route_enabled(&self, route: &AggregatorPath) -> bool {
+    self.disabled_routes & (1 << route.clone() as u8) == 0
+  }
+
+  /// check if swaps may go through the pool, every pool is allowed while the allowlist is empty
+  pub fn is_pool_allowed(&self, pool: &Pubkey) -> bool {
+    let allowed_pools = self.allowed_pools;
+    let allowed_pool_count = self.allowed_pool_count as usize;
+    allowed_pool_count == 0 || allowed_pools[..allowed_pool_count].contains(pool)
+  }
+}
+
+/// fee ratio of `amount_in` reduced by `discount_bps`, at least one token unit is always charged
+fn aggregator_fee(fee_numerator: u64, fee_denominator: u64, discount_bps: u64, amount_in: u64) -> u64 {
+  let amount_fee = amount_in as u128 * fee_numerator as u128 / fee_denominator as u128;
+  let amount_fee = amount_fee * (DISCOUNT_BPS_DENOMINATOR - discount_bps) as u128 / DISCOUNT_BPS_DENOMINATOR as u128;
+  (amount_fee as u64).max(1)
+}
+
+fn best_discount_bps(discount_tiers: &[DiscountTier], balance: u64) -> u64 {
+  discount_tiers
+    .iter()
+    .filter(|tier| tier.min_amount > 0 && balance >= tier.min_amount)
+    .map(|tier| tier.discount_bps)
+    .max()
+    .unwrap_or(0)
+}
+
+/// Admin roles, each allowed its own instructions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AdminRole {
+  /// State owner, or the admin signers in multisig mode
+  Owner,
+  /// Changes fees and fee discounts, together with the admin signers in multisig mode
+  FeeAdmin,
+  /// Enables and disables routes
+  RouteAdmin,
+  /// Pauses swaps
+  PauseGuardian,
+}
+
+/// Fee beneficiary
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema, Pod, Zeroable)]
+pub struct FeeBeneficiary {
+  // Wallet which owns the token accounts receiving the fee share
+  pub owner: Pubkey,
+
+  // Share of the fees, relative to the sum of all weights
+  pub weight: u64,
+}
+
+impl FeeBeneficiary {
+  /// Serialized size of a beneficiary entry
+  pub const LEN: usize = 32 + 8;
+}
+
+/// Fee discount tier
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema, Pod, Zeroable)]
+pub struct DiscountTier {
+  // Minimum balance of the discount mint to reach this tier
+  pub min_amount: u64,
+
+  // Fee reduction in basis points
+  pub discount_bps: u64,
+}
+
+impl DiscountTier {
+  /// Serialized size of a discount tier
+  pub const LEN: usize = 8 + 8;
+}
+
+/// Queued fee change
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct PendingFeeChange {
+  // A fee change is queued
+  pub is_pending: bool,
+
+  // Queued fee ratio
+  pub fee_numerator: u64,
+
+  // Queued fee ratio
+  pub fee_denominator: u64,
+
+  // Unix timestamp from which the change can be executed
+  pub effective_timestamp: i64,
+}
+
+impl PendingFeeChange {
+  /// Serialized size of a pending fee change
+  pub const LEN: usize = 1 + 8 + 8 + 8;
 }
 
 /// Program state handler.
@@ -88,18 +329,97 @@ impl Processor {
       } => {
         Self::process_route_swap(program_id, accounts, route1, route2, amount_in, amount_out)
       }
+      AggregatorInstruction::SimulateRoute {
+        route1,
+        route2,
+        amount_in
+      } => {
+        Self::process_simulate_route(program_id, accounts, route1, route2, amount_in)
+      }
       AggregatorInstruction::UpdateState {
         fee_numerator,
         fee_denominator
       } => {
         Self::process_update_state(program_id, accounts, fee_numerator, fee_denominator)
       }
+      AggregatorInstruction::UpdateFeeBeneficiaries {
+        weights
+      } => {
+        Self::process_update_fee_beneficiaries(program_id, accounts, weights)
+      }
+      AggregatorInstruction::DistributeFees => {
+        Self::process_distribute_fees(program_id, accounts)
+      }
+      AggregatorInstruction::UpdateDiscountTiers {
+        tiers
+      } => {
+        Self::process_update_discount_tiers(program_id, accounts, tiers)
+      }
+      AggregatorInstruction::QueueFeeChange {
+        fee_numerator,
+        fee_denominator,
+        fee_change_delay
+      } => {
+        Self::process_queue_fee_change(program_id, accounts, fee_numerator, fee_denominator, fee_change_delay)
+      }
+      AggregatorInstruction::ExecuteFeeChange => {
+        Self::process_execute_fee_change(program_id, accounts)
+      }
+      AggregatorInstruction::CancelFeeChange => {
+        Self::process_cancel_fee_change(program_id, accounts)
+      }
+      AggregatorInstruction::SetPause {
+        is_paused
+      } => {
+        Self::process_set_pause(program_id, accounts, is_paused)
+      }
+      AggregatorInstruction::SetAdminSigners {
+        signer_count,
+        threshold
+      } => {
+        Self::process_set_admin_signers(program_id, accounts, signer_count, threshold)
+      }
+      AggregatorInstruction::SetRoles => {
+        Self::process_set_roles(program_id, accounts)
+      }
+      AggregatorInstruction::SetRouteEnabled {
+        route,
+        is_enabled
+      } => {
+        Self::process_set_route_enabled(program_id, accounts, route, is_enabled)
+      }
+      AggregatorInstruction::RouteSwapWithOptions {
+        route1,
+        route2,
+        amount_in,
+        amount_out,
+        options
+      } => {
+        Self::process_route_swap_with_options(program_id, accounts, route1, route2, amount_in, amount_out, options)
+      }
+      AggregatorInstruction::MigrateState => {
+        Self::process_migrate_state(program_id, accounts)
+      }
+      AggregatorInstruction::SetPoolAllowed {
+        pool,
+        is_allowed
+      } => {
+        Self::process_set_pool_allowed(program_id, accounts, pool, is_allowed)
+      }
+      AggregatorInstruction::SimulateRouteWithOptions {
+        route1,
+        route2,
+        amount_in,
+        options
+      } => {
+        Self::process_simulate_route_with_options(program_id, accounts, route1, route2, amount_in, options)
+      }
     }
   }
 
   /// create ab account for global state
   pub fn create_or_allocate_account_raw<'a>(
-    program_id: Pubkey,
+    program_id: &Pubkey,
     new_account_info: &AccountInfo<'a>,
     rent_sysvar_info: &AccountInfo<'a>,
     system_program_info: &AccountInfo<'a>,
@@ -116,7 +436,7 @@ impl Processor {
     if required_lamports > 0 {
       msg!("Transfer {} lamports to the new account", required_lamports);
       invoke(
-        &system_instruction::transfer(&payer_info.key, new_account_info.key, required_lamports),
+        &system_instruction::transfer(payer_info.key, new_account_info.key, required_lamports),
         &[
           payer_info.clone(),
           new_account_info.clone(),
@@ -129,37 +449,64 @@ impl Processor {
     invoke_signed(
       &system_instruction::allocate(new_account_info.key, size.try_into().map_err(|_| AggregatorError::InvalidAllocateSpaceForAccount)?),
       &[new_account_info.clone(), system_program_info.clone()],
-      &[&signer_seeds],
+      &[signer_seeds],
     )?;
 
     msg!("Assign the account to the owning program");
     invoke_signed(
-      &system_instruction::assign(new_account_info.key, &program_id),
+      &system_instruction::assign(new_account_info.key, program_id),
       &[new_account_info.clone(), system_program_info.clone()],
-      &[&signer_seeds],
+      &[signer_seeds],
     )?;
     msg!("Completed assignation!");
 
     Ok(())
   }
 
-  /// check if the program account address is valid
-  pub fn check_state_account(program_id: &Pubkey, key: &Pubkey) -> Result<(), ProgramError> {
+  /// check if the program account address is valid, from the bump stored in the state
+  pub fn check_state_account(program_id: &Pubkey, state_info: &AccountInfo, bump: u8) -> Result<(), ProgramError> {
+    if state_info.owner != program_id {
+      return Err(AggregatorError::InvalidStateAddress.into());
+    }
+
     let seeds = [
       SWAP_AGGREGATOR_SEED.as_bytes(),
       program_id.as_ref(),
+      &[bump],
     ];
 
-    let (program_data_key, _bump) = Pubkey::find_program_address(&seeds, program_id);
-    if program_data_key != *key {
-      return Err(AggregatorError::InvalidStateAddress.into());
-    } else {
-      Ok(())
+    match Pubkey::create_program_address(&seeds, program_id) {
+      Ok(program_data_key) if program_data_key == *state_info.key => Ok(()),
+      _ => Err(AggregatorError::InvalidStateAddress.into()),
+    }
+  }
+
+  /// borrow the program state in place, for the instructions which only read it
+  pub fn load_state_view<'a>(program_id: &Pubkey, state_info: &'a AccountInfo) -> Result<Ref<'a, ProgramStateView>, ProgramError> {
+    Self::check_state_layout(program_id, state_info)?;
+
+    let data = state_info.data.borrow();
+    let program_state = Ref::map(data, |data| bytemuck::from_bytes::<ProgramStateView>(data));
+    Self::check_state_account(program_id, state_info, program_state.bump)?;
+
+    if program_state.is_initialized == 0 {
+      return Err(AggregatorError::NotInitializedState.into());
     }
+
+    Ok(program_state)
   }
 
-  fn u64_to_pubkey(arr:[u64;4]) -> Pubkey {
-    return Pubkey::new(cast_slice(&identity(arr) as &[_]));
+  /// check that the state account has the current layout, older layouts are upgraded by `MigrateState`
+  pub fn check_state_layout(program_id: &Pubkey, state_info: &AccountInfo) -> ProgramResult {
+    if state_info.owner != program_id {
+      return Err(AggregatorError::InvalidStateAddress.into());
+    }
+
+    match state_info.data_len() {
+      ProgramState::LEN => Ok(()),
+      len if len < ProgramState::LEN => Err(AggregatorError::OutdatedStateLayout.into()),
+      _ => Err(AggregatorError::InvalidStateAddress.into()),
+    }
   }
 
   fn next_account_infos<'a, 'b: 'a>(
@@ -172,11 +519,137 @@ impl Processor {
     }
 
     let (accounts, remaining) = accounts.split_at(count);
-    *iter = remaining.into_iter();
+    *iter = remaining.iter();
 
     Ok(accounts)
   }
 
+  /// check the admin authority of an instruction.
+  /// Either `state_owner` signs, or in multisig mode enough admin signers sign anywhere in the accounts.
+  pub fn check_admin(
+    program_state: &ProgramState,
+    state_owner_info: &AccountInfo,
+    accounts: &[AccountInfo],
+  ) -> ProgramResult {
+    if program_state.admin_threshold == 0 {
+      if !state_owner_info.is_signer {
+        return Err(AggregatorError::InvalidStateSigner.into());
+      }
+
+      if program_state.state_owner != *state_owner_info.key {
+        return Err(AggregatorError::InvalidStateOwner.into());
+      }
+
+      return Ok(());
+    }
+
+    let admin_signers = &program_state.admin_signers[..program_state.admin_signer_count as usize];
+    let signatures = admin_signers
+      .iter()
+      .filter(|admin_signer| accounts.iter().any(|info| info.is_signer && info.key == *admin_signer))
+      .count();
+
+    if signatures < program_state.admin_threshold as usize {
+      return Err(AggregatorError::NotEnoughAdminSignatures.into());
+    }
+
+    Ok(())
+  }
+
+  /// check that an admin instruction is signed by the holder of its role.
+  /// The owner authority can act for every role.
+  /// In multisig mode fee changes need the admin signers threshold, the fee admin key alone is not enough.
+  pub fn check_role(
+    program_state: &ProgramState,
+    role: AdminRole,
+    signer_info: &AccountInfo,
+    accounts: &[AccountInfo],
+  ) -> ProgramResult {
+    let (role_key, role_error) = match role {
+      AdminRole::Owner => return Self::check_admin(program_state, signer_info, accounts),
+      AdminRole::FeeAdmin if program_state.admin_threshold > 0 => {
+        return Self::check_admin(program_state, signer_info, accounts)
+      },
+      AdminRole::FeeAdmin => (program_state.fee_admin, AggregatorError::InvalidFeeAdmin),
+      AdminRole::RouteAdmin => (program_state.route_admin, AggregatorError::InvalidRouteAdmin),
+      AdminRole::PauseGuardian => (program_state.pause_guardian, AggregatorError::InvalidPauseGuardian),
+    };
+
+    if role_key != Pubkey::default() && signer_info.is_signer && *signer_info.key == role_key {
+      return Ok(());
+    }
+
+    Self::check_admin(program_state, signer_info, accounts).map_err(|_| role_error.into())
+  }
+
+  /// load an initialized program state for an instruction signed by the holder of `role`
+  fn load_admin_state(
+    program_id: &Pubkey,
+    state_info: &AccountInfo,
+    signer_info: &AccountInfo,
+    accounts: &[AccountInfo],
+    role: AdminRole,
+  ) -> Result<ProgramState, ProgramError> {
+    Self::check_state_layout(program_id, state_info)?;
+    let program_state = ProgramState::try_from_slice(&state_info.data.borrow())?;
+
+    Self::check_state_account(program_id, state_info, program_state.bump)?;
+
+    if !program_state.is_initialized {
+      return Err(AggregatorError::NotInitializedState.into());
+    }
+
+    Self::check_role(&program_state, role, signer_info, accounts)?;
+
+    Ok(program_state)
+  }
+
+  /// number of pool accounts a route leg expects
+  fn route_accounts_len(route: &AggregatorPath) -> usize {
+    match route {
+      AggregatorPath::Raydium => RAYDIUM_SWAP_ACCOUNTS,
+      AggregatorPath::Saber => SABER_SWAP_ACCOUNTS,
+      AggregatorPath::Mercurial => MERCURIAL_SWAP_ACCOUNTS,
+      _ => 0,
+    }
+  }
+
+  /// check that the pool of a route leg, the first of its `pool_accounts`, is in the pool allowlist
+  fn check_pool_allowed(program_state: &ProgramStateView, route: &AggregatorPath, pool_accounts: &[AccountInfo]) -> ProgramResult {
+    // routes without pool accounts swap nothing
+    if Self::route_accounts_len(route) == 0 {
+      return Ok(());
+    }
+    match pool_accounts.first() {
+      Some(pool_info) if program_state.is_pool_allowed(pool_info.key) => Ok(()),
+      Some(_) => Err(AggregatorError::PoolNotAllowed.into()),
+      None => Err(ProgramError::NotEnoughAccountKeys),
+    }
+  }
+
+  /// calculate the aggregator fee, reduced by the discount tier of the user's discount token account
+  pub fn calculate_fee(
+    program_state: &ProgramStateView,
+    amount_in: u64,
+    discount_token_info: Option<&AccountInfo>,
+    user_transfer_authority_info: &AccountInfo,
+    token_program_info: &AccountInfo,
+  ) -> Result<u64, ProgramError> {
+    let mut discount_bps = 0;
+
+    if let Some(discount_token_info) = discount_token_info {
+      let discount_token = Self::unpack_token_account(discount_token_info, token_program_info.key)?;
+      if discount_token.owner != *user_transfer_authority_info.key ||
+        discount_token.mint != program_state.discount_mint {
+        return Err(AggregatorError::InvalidDiscountAccount.into());
+      }
+
+      discount_bps = program_state.discount_bps(discount_token.amount);
+    }
+
+    Ok(aggregator_fee(program_state.fee_numerator, program_state.fee_denominator, discount_bps, amount_in))
+  }
+
   pub fn unpack_token_account(
     account_info: &AccountInfo,
     token_program_id: &Pubkey,
@@ -197,6 +670,7 @@ impl Processor {
     destination_info: &'a AccountInfo<'b>,
     user_transfer_authority_info: &'a AccountInfo<'b>,
     token_program_info: &'a AccountInfo<'b>,
+    signer_seeds: &[&[&[u8]]],
   ) -> Result<u64, ProgramError> {
     let account_info_iter = &mut accounts.iter();
 
@@ -208,10 +682,8 @@ impl Processor {
     let swap_token_info_4 = next_account_info(account_info_iter)?;
     let swap_program_info = next_account_info(account_info_iter)?;
 
-    let program_id = Pubkey::from_str(MERCURIAL_SWAP_PROGRAM_ID).map_err(|_| AggregatorError::InvalidMercurialProgramId)?;
-
     let ix = mercurial::exchange(
-      &program_id,
+      &MERCURIAL_SWAP_PROGRAM_ID,
       swap_account_info.key,
       token_program_info.key,
       pool_authority_info.key,
@@ -231,7 +703,7 @@ impl Processor {
     let mut dest_token = Self::unpack_token_account(destination_info, token_program_info.key)?;
     let ori_balance = dest_token.amount;
 
-    invoke(
+    invoke_signed(
       &ix,
       &[
         swap_account_info.clone(),
@@ -245,7 +717,8 @@ impl Processor {
         source_info.clone(),
         destination_info.clone(),
         swap_program_info.clone(),
-      ]
+      ],
+      signer_seeds,
     )?;
 
     dest_token = Self::unpack_token_account(destination_info, token_program_info.key)?;
@@ -261,6 +734,7 @@ impl Processor {
     destination_info: &'a AccountInfo<'b>,
     user_transfer_authority_info: &'a AccountInfo<'b>,
     token_program_info: &'a AccountInfo<'b>,
+    signer_seeds: &[&[&[u8]]],
   ) -> Result<u64, ProgramError> {
     let account_info_iter = &mut accounts.iter();
 
@@ -289,7 +763,7 @@ impl Processor {
     let mut dest_token = Self::unpack_token_account(destination_info, token_program_info.key)?;
     let ori_balance = dest_token.amount;
 
-    invoke(
+    invoke_signed(
       &ix,
       &[
         stable_pool_info.clone(),
@@ -303,7 +777,8 @@ impl Processor {
         token_program_info.clone(),
         clock_program_info.clone(),
         stable_program_info.clone(),
-      ]
+      ],
+      signer_seeds,
     )?;
 
     dest_token = Self::unpack_token_account(destination_info, token_program_info.key)?;
@@ -319,6 +794,7 @@ impl Processor {
     destination_info: &'a AccountInfo<'b>,
     user_transfer_authority_info: &'a AccountInfo<'b>,
     token_program_info: &'a AccountInfo<'b>,
+    signer_seeds: &[&[&[u8]]],
   ) -> Result<u64, ProgramError> {
     let account_info_iter = &mut accounts.iter();
 
@@ -340,11 +816,9 @@ impl Processor {
 
     let ray_program_info = next_account_info(account_info_iter)?;
 
-    let ray_program_id = Pubkey::from_str(RAYDIUM_SWAP_PROGRAM_ID).map_err(|_| AggregatorError::InvalidRaydiumProgramId)?;
-
     // call raydium swap
     let ix = raydium::swap(
-      &ray_program_id,
+      &RAYDIUM_SWAP_PROGRAM_ID,
       ray_pool_info.key,
       ray_authority_info.key,
       ray_open_orders_info.key,
@@ -369,7 +843,7 @@ impl Processor {
     let mut dest_token = Self::unpack_token_account(destination_info, token_program_info.key)?;
     let ori_balance = dest_token.amount;
 
-    invoke(
+    invoke_signed(
       &ix,
       &[
         // spl token
@@ -395,7 +869,8 @@ impl Processor {
         destination_info.clone(),
         user_transfer_authority_info.clone(),
         ray_program_info.clone(),
-      ]
+      ],
+      signer_seeds,
     )?;
 
     dest_token = Self::unpack_token_account(destination_info, token_program_info.key)?;
@@ -403,6 +878,59 @@ impl Processor {
     Ok(delta_balance)
   }
 
+  /// swap through the pool of a route leg, the next pool accounts of `accounts`, and return the amount received
+  fn swap_leg<'a, 'b>(
+    route: &AggregatorPath,
+    accounts: &mut std::slice::Iter<'a, AccountInfo<'b>>,
+    amount_in: u64,
+    amount_out: u64,
+    source_info: &'a AccountInfo<'b>,
+    destination_info: &'a AccountInfo<'b>,
+    authority_info: &'a AccountInfo<'b>,
+    token_program_info: &'a AccountInfo<'b>,
+    signer_seeds: &[&[&[u8]]],
+  ) -> Result<u64, ProgramError> {
+    match route {
+      AggregatorPath::Raydium => {
+        Self::swap_raydium(
+          Self::next_account_infos(accounts, RAYDIUM_SWAP_ACCOUNTS)?,
+          amount_in,
+          amount_out,
+          source_info,
+          destination_info,
+          authority_info,
+          token_program_info,
+          signer_seeds,
+        )
+      }
+      AggregatorPath::Saber => {
+        Self::swap_saber(
+          Self::next_account_infos(accounts, SABER_SWAP_ACCOUNTS)?,
+          amount_in,
+          amount_out,
+          source_info,
+          destination_info,
+          authority_info,
+          token_program_info,
+          signer_seeds,
+        )
+      }
+      AggregatorPath::Mercurial => {
+        Self::swap_mercurial_4(
+          Self::next_account_infos(accounts, MERCURIAL_SWAP_ACCOUNTS)?,
+          amount_in,
+          amount_out,
+          source_info,
+          destination_info,
+          authority_info,
+          token_program_info,
+          signer_seeds,
+        )
+      }
+      _ => Ok(0)
+    }
+  }
+
   /// Processes an [Update](enum.Instruction.html).
   pub fn process_update_state(
     program_id: &Pubkey,
@@ -417,22 +945,18 @@ impl Processor {
     let cur_state_owner_info = next_account_info(account_info_iter)?;
     let new_state_owner_info = next_account_info(account_info_iter)?;
 
-    let fee_owner_info = next_account_info(account_info_iter)?;
-
     let system_info = next_account_info(account_info_iter)?;
     let rent_info = next_account_info(account_info_iter)?;
 
-    Self::check_state_account(program_id, state_info.key)?;
-
     if !cur_state_owner_info.is_signer {
       return Err(AggregatorError::InvalidStateSigner.into());
     }
 
-    if *system_info.key != Pubkey::from_str(SYSTEM_PROGRAM_ID).map_err(|_| AggregatorError::InvalidSystemProgramId)? {
+    if *system_info.key != SYSTEM_PROGRAM_ID {
       return Err(AggregatorError::InvalidSystemProgramId.into());
     }
 
-    if *rent_info.key != Pubkey::from_str(RENT_SYSVAR_ID).map_err(|_| AggregatorError::InvalidRentSysvarId)? {
+    if *rent_info.key != RENT_SYSVAR_ID {
       return Err(AggregatorError::InvalidRentSysvarId.into());
     }
 
@@ -441,13 +965,17 @@ impl Processor {
       program_id.as_ref(),
     ];
 
-    let (_pda_key, bump) = Pubkey::find_program_address(&seeds, program_id);
+    // the only address derivation, the bump is stored for the other instructions
+    let (pda_key, bump) = Pubkey::find_program_address(&seeds, program_id);
+    if pda_key != *state_info.key {
+      return Err(AggregatorError::InvalidStateAddress.into());
+    }
 
     if state_info.data_is_empty() {
-      let size = 81; // ProgramState::get_packed_len();
+      let size = ProgramState::LEN;
 
       Self::create_or_allocate_account_raw(
-        *program_id,
+        program_id,
         state_info,
         rent_info,
         system_info,
@@ -461,81 +989,997 @@ impl Processor {
       )?;
     }
 
+    Self::check_state_layout(program_id, state_info)?;
     let mut program_state = ProgramState::try_from_slice(&state_info.data.borrow())?;
+    let was_initialized = program_state.is_initialized;
+    let previous_owner = program_state.state_owner;
 
-    if program_state.is_initialized == false {
-      program_state.state_owner = Pubkey::from_str(INITIAL_STATE_OWNER).map_err(|_| AggregatorError::InvalidStateOwner)?;
+    if !program_state.is_initialized {
+      program_state.state_owner = INITIAL_STATE_OWNER;
       program_state.is_initialized = true;
     }
 
-    if program_state.state_owner != *cur_state_owner_info.key {
-      return Err(AggregatorError::InvalidStateOwner.into());
-    }
+    Self::check_admin(&program_state, cur_state_owner_info, accounts)?;
 
-    if fee_numerator > fee_denominator ||
-      fee_denominator <= 0 ||
-      fee_numerator < 0 {
+    // the fee ratio is only set at initialization, then it changes through the timelocked queue
+    if !was_initialized {
+      if fee_numerator > fee_denominator ||
+        fee_denominator == 0 {
+        return Err(AggregatorError::InvalidFeeParameters.into());
+      }
+
+      program_state.fee_numerator = fee_numerator;
+      program_state.fee_denominator = fee_denominator;
+      program_state.fee_change_delay = DEFAULT_FEE_CHANGE_DELAY;
+    } else if (fee_numerator, fee_denominator) != (program_state.fee_numerator, program_state.fee_denominator) {
       return Err(AggregatorError::InvalidFeeParameters.into());
     }
 
     // Save the program state
     program_state.state_owner = *new_state_owner_info.key;
-    program_state.fee_owner = *fee_owner_info.key;
-    program_state.fee_numerator = fee_numerator;
-    program_state.fee_denominator = fee_denominator;
+    program_state.bump = bump;
 
     program_state.serialize(&mut &mut state_info.data.borrow_mut()[..])?;
-    Ok(())
+
+    AggregatorEvent::StateUpdated(StateUpdatedEvent {
+      initialized: !was_initialized,
+      previous_owner,
+      state_owner: program_state.state_owner,
+      fee_numerator: program_state.fee_numerator,
+      fee_denominator: program_state.fee_denominator,
+    }).emit()
   }
 
-  pub fn process_route_swap(
+  /// Processes a [MigrateState](enum.Instruction.html).
+  /// Grows a state account of an older layout to the current one, the new fields start unset.
+  pub fn process_migrate_state(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    route1: AggregatorPath,
-    route2: AggregatorPath,
-    amount_in: u64,
-    amount_out: u64
   ) -> ProgramResult {
     // load account info
     let account_info_iter = &mut accounts.iter();
-
     let state_info = next_account_info(account_info_iter)?;
+    let admin_info = next_account_info(account_info_iter)?;
 
-    let user_transfer_authority_info = next_account_info(account_info_iter)?;
-    let source_info = next_account_info(account_info_iter)?;
-    let mid_token_info = next_account_info(account_info_iter)?;
-    let fixed_fee_account_info = next_account_info(account_info_iter)?;
+    let system_info = next_account_info(account_info_iter)?;
+    let rent_info = next_account_info(account_info_iter)?;
 
-    let token_program_info = next_account_info(account_info_iter)?;
+    if *system_info.key != SYSTEM_PROGRAM_ID {
+      return Err(AggregatorError::InvalidSystemProgramId.into());
+    }
 
-    if *token_program_info.key != Pubkey::from_str(TOKEN_PROGRAM_ID).map_err(|_| AggregatorError::InvalidTokenProgramId)?{
-        return Err(AggregatorError::InvalidTokenProgramId.into());
+    if *rent_info.key != RENT_SYSVAR_ID {
+      return Err(AggregatorError::InvalidRentSysvarId.into());
     }
 
-    Self::check_state_account(program_id, state_info.key)?;
-    let program_state = ProgramState::try_from_slice(&state_info.data.borrow())?;
+    let seeds = [
+      SWAP_AGGREGATOR_SEED.as_bytes(),
+      program_id.as_ref(),
+    ];
 
-    if program_state.is_initialized == false
-    {
-        return Err(AggregatorError::NotInitializedState.into());
+    // older layouts may not store the bump
+    let (pda_key, bump) = Pubkey::find_program_address(&seeds, program_id);
+    if pda_key != *state_info.key || state_info.owner != program_id {
+      return Err(AggregatorError::InvalidStateAddress.into());
     }
 
-    // let source_token = Self::unpack_token_account(source_info, token_program_info.key)?;
-    let fee_token = Self::unpack_token_account(fixed_fee_account_info, token_program_info.key)?;
-    if fee_token.owner != program_state.fee_owner{
-        return Err(AggregatorError::InvalidFeeOwner.into());
+    let data_len = state_info.data_len();
+    if data_len >= ProgramState::LEN {
+      return Err(AggregatorError::StateAlreadyMigrated.into());
     }
 
-    let mut amount_fee = amount_in * program_state.fee_numerator / program_state.fee_denominator;
-    
-    if amount_fee <= 0 {
-        amount_fee = 1;
+    let mut program_state = if data_len == LegacyProgramState::LEN {
+      // the single fee owner becomes the only beneficiary of the fee vaults
+      let legacy_state = LegacyProgramState::try_from_slice(&state_info.data.borrow())?;
+      let mut program_state = ProgramState {
+        is_initialized: legacy_state.is_initialized,
+        state_owner: legacy_state.state_owner,
+        fee_numerator: legacy_state.fee_numerator,
+        fee_denominator: legacy_state.fee_denominator,
+        ..ProgramState::default()
+      };
+      if legacy_state.fee_owner != Pubkey::default() {
+        program_state.beneficiaries[0] = FeeBeneficiary { owner: legacy_state.fee_owner, weight: 1 };
+      }
+      program_state
+    } else {
+      // the later layouts only append fields, which read as unset from zeros
+      let mut data = state_info.data.borrow().to_vec();
+      data.resize(ProgramState::LEN, 0);
+      ProgramState::try_from_slice(&data)?
+    };
+    program_state.bump = bump;
+
+    // layouts before the configurable delay used the default one
+    if program_state.fee_change_delay == 0 {
+      program_state.fee_change_delay = DEFAULT_FEE_CHANGE_DELAY;
+    }
+    if program_state.pending_fee_change.is_pending && program_state.pending_fee_change_delay == 0 {
+      program_state.pending_fee_change_delay = program_state.fee_change_delay;
+    }
+
+    if !program_state.is_initialized {
+      return Err(AggregatorError::NotInitializedState.into());
+    }
+
+    Self::check_admin(&program_state, admin_info, accounts)?;
+
+    let rent = &Rent::from_account_info(rent_info)?;
+    let required_lamports = rent
+      .minimum_balance(ProgramState::LEN)
+      .saturating_sub(state_info.lamports());
+
+    if required_lamports > 0 {
+      invoke(
+        &system_instruction::transfer(admin_info.key, state_info.key, required_lamports),
+        &[
+          admin_info.clone(),
+          state_info.clone(),
+          system_info.clone(),
+        ],
+      )?;
+    }
+
+    msg!("Migrate the state from {} to {} bytes", data_len, ProgramState::LEN);
+    state_info.realloc(ProgramState::LEN, true)?;
+
+    program_state.serialize(&mut &mut state_info.data.borrow_mut()[..])?;
+
+    AggregatorEvent::StateMigrated(StateMigratedEvent {
+      previous_len: data_len as u64,
+      len: ProgramState::LEN as u64,
+    }).emit()
+  }
+
+  /// Processes an [UpdateFeeBeneficiaries](enum.Instruction.html).
+  pub fn process_update_fee_beneficiaries(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    weights: Vec<u64>,
+  ) -> ProgramResult {
+    // load account info
+    let account_info_iter = &mut accounts.iter();
+    let state_info = next_account_info(account_info_iter)?;
+    let state_owner_info = next_account_info(account_info_iter)?;
+
+    let mut program_state = Self::load_admin_state(program_id, state_info, state_owner_info, accounts, AdminRole::Owner)?;
+
+    if weights.is_empty() ||
+      weights.len() > MAX_FEE_BENEFICIARIES ||
+      weights.contains(&0) ||
+      weights.iter().try_fold(0u64, |sum, weight| sum.checked_add(*weight)).is_none() {
+      return Err(AggregatorError::InvalidFeeBeneficiaries.into());
+    }
+
+    // one wallet account per weight, in the same order
+    let beneficiary_infos = Self::next_account_infos(account_info_iter, weights.len())?;
+
+    let mut beneficiaries = [FeeBeneficiary::default(); MAX_FEE_BENEFICIARIES];
+    for (beneficiary, (owner_info, weight)) in beneficiaries.iter_mut().zip(beneficiary_infos.iter().zip(weights)) {
+      beneficiary.owner = *owner_info.key;
+      beneficiary.weight = weight;
+    }
+
+    // Save the program state
+    program_state.beneficiaries = beneficiaries;
+
+    program_state.serialize(&mut &mut state_info.data.borrow_mut()[..])?;
+
+    AggregatorEvent::FeeBeneficiariesUpdated(FeeBeneficiariesUpdatedEvent {
+      beneficiaries: beneficiaries[..beneficiary_infos.len()].to_vec(),
+    }).emit()
+  }
+
+  /// Processes a [DistributeFees](enum.Instruction.html).
+  /// Anyone can call it, the fee vault balance goes to the beneficiaries pro rata to their weight.
+  pub fn process_distribute_fees(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+  ) -> ProgramResult {
+    // load account info
+    let account_info_iter = &mut accounts.iter();
+    let state_info = next_account_info(account_info_iter)?;
+    let fee_vault_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    if *token_program_info.key != TOKEN_PROGRAM_ID {
+      return Err(AggregatorError::InvalidTokenProgramId.into());
+    }
+
+    let (state_beneficiaries, bump) = {
+      let program_state = Self::load_state_view(program_id, state_info)?;
+      (program_state.beneficiaries, program_state.bump)
+    };
+
+    let fee_vault = Self::unpack_token_account(fee_vault_info, token_program_info.key)?;
+    if fee_vault.owner != *state_info.key {
+      return Err(AggregatorError::InvalidFeeOwner.into());
+    }
+
+    let beneficiaries: Vec<&FeeBeneficiary> = state_beneficiaries
+      .iter()
+      .filter(|beneficiary| beneficiary.weight > 0)
+      .collect();
+
+    if beneficiaries.is_empty() {
+      return Err(AggregatorError::InvalidFeeBeneficiaries.into());
+    }
+
+    let total_weight: u128 = beneficiaries.iter().map(|beneficiary| beneficiary.weight as u128).sum();
+
+    // one token account per beneficiary, in the state order
+    let beneficiary_token_infos = Self::next_account_infos(account_info_iter, beneficiaries.len())?;
+
+    for (beneficiary, beneficiary_token_info) in beneficiaries.iter().zip(beneficiary_token_infos) {
+      let beneficiary_token = Self::unpack_token_account(beneficiary_token_info, token_program_info.key)?;
+      if beneficiary_token.owner != beneficiary.owner {
+        return Err(AggregatorError::InvalidBeneficiaryAccount.into());
+      }
+
+      if beneficiary_token.mint != fee_vault.mint {
+        return Err(AggregatorError::NotExpectedMint.into());
+      }
+
+      // rounding dust stays in the vault for the next distribution
+      let amount = (fee_vault.amount as u128 * beneficiary.weight as u128 / total_weight) as u64;
+      if amount == 0 {
+        continue;
+      }
+
+      msg!("Distribute {} to {}", amount, beneficiary.owner);
+
+      let cpi_accounts = Transfer {
+        from: fee_vault_info.clone(),
+        to: beneficiary_token_info.clone(),
+        authority: state_info.clone(),
+      };
+      let signer_seeds: &[&[u8]] = &[
+        SWAP_AGGREGATOR_SEED.as_bytes(),
+        program_id.as_ref(),
+        &[bump],
+      ];
+      let cpi_ctx = CpiContext::new_with_signer(token_program_info.clone(), cpi_accounts, std::slice::from_ref(&signer_seeds));
+
+      token::transfer(cpi_ctx, amount)?;
+    }
+
+    Ok(())
+  }
+
+  /// Processes an [UpdateDiscountTiers](enum.Instruction.html).
+  pub fn process_update_discount_tiers(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    tiers: Vec<DiscountTier>,
+  ) -> ProgramResult {
+    // load account info
+    let account_info_iter = &mut accounts.iter();
+    let state_info = next_account_info(account_info_iter)?;
+    let fee_admin_info = next_account_info(account_info_iter)?;
+    let discount_mint_info = next_account_info(account_info_iter)?;
+
+    let mut program_state = Self::load_admin_state(program_id, state_info, fee_admin_info, accounts, AdminRole::FeeAdmin)?;
+
+    if *discount_mint_info.owner != TOKEN_PROGRAM_ID {
+      return Err(AggregatorError::NotExpectedMint.into());
+    }
+
+    if tiers.len() > MAX_DISCOUNT_TIERS ||
+      tiers.iter().any(|tier| tier.min_amount == 0 || tier.discount_bps > DISCOUNT_BPS_DENOMINATOR) {
+      return Err(AggregatorError::InvalidDiscountTiers.into());
+    }
+
+    let mut discount_tiers = [DiscountTier::default(); MAX_DISCOUNT_TIERS];
+    discount_tiers[..tiers.len()].copy_from_slice(&tiers);
+
+    // Save the program state
+    program_state.discount_mint = *discount_mint_info.key;
+    program_state.discount_tiers = discount_tiers;
+
+    program_state.serialize(&mut &mut state_info.data.borrow_mut()[..])?;
+
+    AggregatorEvent::DiscountTiersUpdated(DiscountTiersUpdatedEvent {
+      discount_mint: program_state.discount_mint,
+      tiers,
+    }).emit()
+  }
+
+  /// Processes a [QueueFeeChange](enum.Instruction.html).
+  pub fn process_queue_fee_change(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    fee_numerator: u64,
+    fee_denominator: u64,
+    fee_change_delay: i64,
+  ) -> ProgramResult {
+    // load account info
+    let account_info_iter = &mut accounts.iter();
+    let state_info = next_account_info(account_info_iter)?;
+    let fee_admin_info = next_account_info(account_info_iter)?;
+    let clock_info = next_account_info(account_info_iter)?;
+
+    let mut program_state = Self::load_admin_state(program_id, state_info, fee_admin_info, accounts, AdminRole::FeeAdmin)?;
+
+    if fee_numerator > fee_denominator ||
+      fee_denominator == 0 {
+      return Err(AggregatorError::InvalidFeeParameters.into());
+    }
+
+    if fee_change_delay <= 0 || fee_change_delay > MAX_FEE_CHANGE_DELAY {
+      return Err(AggregatorError::InvalidFeeChangeDelay.into());
+    }
+
+    let clock = Clock::from_account_info(clock_info)?;
+
+    // Save the program state, the new delay applies from the next queued change
+    program_state.pending_fee_change = PendingFeeChange {
+      is_pending: true,
+      fee_numerator,
+      fee_denominator,
+      effective_timestamp: clock.unix_timestamp + program_state.fee_change_delay,
+    };
+    program_state.pending_fee_change_delay = fee_change_delay;
+    msg!("Fee change queued until {}", program_state.pending_fee_change.effective_timestamp);
+
+    program_state.serialize(&mut &mut state_info.data.borrow_mut()[..])?;
+
+    AggregatorEvent::FeeChangeQueued(Self::fee_change_event(&program_state)).emit()
+  }
+
+  /// Processes an [ExecuteFeeChange](enum.Instruction.html).
+  pub fn process_execute_fee_change(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+  ) -> ProgramResult {
+    // load account info
+    let account_info_iter = &mut accounts.iter();
+    let state_info = next_account_info(account_info_iter)?;
+    let fee_admin_info = next_account_info(account_info_iter)?;
+    let clock_info = next_account_info(account_info_iter)?;
+
+    let mut program_state = Self::load_admin_state(program_id, state_info, fee_admin_info, accounts, AdminRole::FeeAdmin)?;
+
+    let pending_fee_change = program_state.pending_fee_change;
+    if !pending_fee_change.is_pending {
+      return Err(AggregatorError::NoPendingFeeChange.into());
+    }
+
+    let clock = Clock::from_account_info(clock_info)?;
+    if clock.unix_timestamp < pending_fee_change.effective_timestamp {
+      return Err(AggregatorError::FeeChangeTimelocked.into());
+    }
+
+    let event = Self::fee_change_event(&program_state);
+
+    // Save the program state
+    program_state.fee_numerator = pending_fee_change.fee_numerator;
+    program_state.fee_denominator = pending_fee_change.fee_denominator;
+    program_state.fee_change_delay = program_state.pending_fee_change_delay;
+    program_state.pending_fee_change = PendingFeeChange::default();
+    program_state.pending_fee_change_delay = 0;
+
+    program_state.serialize(&mut &mut state_info.data.borrow_mut()[..])?;
+
+    AggregatorEvent::FeeChangeExecuted(event).emit()
+  }
+
+  /// Processes a [CancelFeeChange](enum.Instruction.html).
+  pub fn process_cancel_fee_change(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+  ) -> ProgramResult {
+    // load account info
+    let account_info_iter = &mut accounts.iter();
+    let state_info = next_account_info(account_info_iter)?;
+    let fee_admin_info = next_account_info(account_info_iter)?;
+
+    let mut program_state = Self::load_admin_state(program_id, state_info, fee_admin_info, accounts, AdminRole::FeeAdmin)?;
+
+    if !program_state.pending_fee_change.is_pending {
+      return Err(AggregatorError::NoPendingFeeChange.into());
+    }
+
+    let event = Self::fee_change_event(&program_state);
+
+    // Save the program state
+    program_state.pending_fee_change = PendingFeeChange::default();
+    program_state.pending_fee_change_delay = 0;
+
+    program_state.serialize(&mut &mut state_info.data.borrow_mut()[..])?;
+
+    AggregatorEvent::FeeChangeCancelled(event).emit()
+  }
+
+  /// event of the queued fee change
+  fn fee_change_event(program_state: &ProgramState) -> FeeChangeEvent {
+    FeeChangeEvent {
+      fee_numerator: program_state.pending_fee_change.fee_numerator,
+      fee_denominator: program_state.pending_fee_change.fee_denominator,
+      fee_change_delay: program_state.pending_fee_change_delay,
+      effective_timestamp: program_state.pending_fee_change.effective_timestamp,
+    }
+  }
+
+  /// Processes a [SetPause](enum.Instruction.html).
+  /// Not timelocked, so the owner can stop swaps in an emergency.
+  pub fn process_set_pause(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    is_paused: bool,
+  ) -> ProgramResult {
+    // load account info
+    let account_info_iter = &mut accounts.iter();
+    let state_info = next_account_info(account_info_iter)?;
+    let admin_info = next_account_info(account_info_iter)?;
+
+    // the guardian can pause, resuming is up to the owner
+    let role = if is_paused { AdminRole::PauseGuardian } else { AdminRole::Owner };
+    let mut program_state = Self::load_admin_state(program_id, state_info, admin_info, accounts, role)?;
+
+    // Save the program state
+    program_state.is_paused = is_paused;
+
+    program_state.serialize(&mut &mut state_info.data.borrow_mut()[..])?;
+
+    AggregatorEvent::PauseUpdated(PauseUpdatedEvent {
+      is_paused,
+      authority: *admin_info.key,
+    }).emit()
+  }
+
+  /// Processes a [SetAdminSigners](enum.Instruction.html).
+  pub fn process_set_admin_signers(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    signer_count: u8,
+    threshold: u8,
+  ) -> ProgramResult {
+    // load account info
+    let account_info_iter = &mut accounts.iter();
+    let state_info = next_account_info(account_info_iter)?;
+    let state_owner_info = next_account_info(account_info_iter)?;
+
+    let mut program_state = Self::load_admin_state(program_id, state_info, state_owner_info, accounts, AdminRole::Owner)?;
+
+    if signer_count as usize > MAX_ADMIN_SIGNERS ||
+      threshold > signer_count ||
+      (threshold == 0 && signer_count > 0) {
+      return Err(AggregatorError::InvalidAdminSigners.into());
+    }
+
+    let admin_signer_infos = Self::next_account_infos(account_info_iter, signer_count as usize)?;
+
+    let mut admin_signers = [Pubkey::default(); MAX_ADMIN_SIGNERS];
+    for (i, admin_signer_info) in admin_signer_infos.iter().enumerate() {
+      if admin_signers[..i].contains(admin_signer_info.key) {
+        return Err(AggregatorError::InvalidAdminSigners.into());
+      }
+      admin_signers[i] = *admin_signer_info.key;
+    }
+
+    // Save the program state
+    program_state.admin_signers = admin_signers;
+    program_state.admin_signer_count = signer_count;
+    program_state.admin_threshold = threshold;
+
+    program_state.serialize(&mut &mut state_info.data.borrow_mut()[..])?;
+
+    AggregatorEvent::AdminSignersUpdated(AdminSignersUpdatedEvent {
+      admin_signers: admin_signers[..signer_count as usize].to_vec(),
+      threshold,
+    }).emit()
+  }
+
+  /// Processes a [SetRoles](enum.Instruction.html).
+  pub fn process_set_roles(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+  ) -> ProgramResult {
+    // load account info
+    let account_info_iter = &mut accounts.iter();
+    let state_info = next_account_info(account_info_iter)?;
+    let state_owner_info = next_account_info(account_info_iter)?;
+    let fee_admin_info = next_account_info(account_info_iter)?;
+    let route_admin_info = next_account_info(account_info_iter)?;
+    let pause_guardian_info = next_account_info(account_info_iter)?;
+
+    let mut program_state = Self::load_admin_state(program_id, state_info, state_owner_info, accounts, AdminRole::Owner)?;
+
+    // Save the program state
+    program_state.fee_admin = *fee_admin_info.key;
+    program_state.route_admin = *route_admin_info.key;
+    program_state.pause_guardian = *pause_guardian_info.key;
+
+    program_state.serialize(&mut &mut state_info.data.borrow_mut()[..])?;
+
+    AggregatorEvent::RolesUpdated(RolesUpdatedEvent {
+      fee_admin: program_state.fee_admin,
+      route_admin: program_state.route_admin,
+      pause_guardian: program_state.pause_guardian,
+    }).emit()
+  }
+
+  /// Processes a [SetRouteEnabled](enum.Instruction.html).
+  pub fn process_set_route_enabled(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    route: AggregatorPath,
+    is_enabled: bool,
+  ) -> ProgramResult {
+    // load account info
+    let account_info_iter = &mut accounts.iter();
+    let state_info = next_account_info(account_info_iter)?;
+    let route_admin_info = next_account_info(account_info_iter)?;
+
+    let mut program_state = Self::load_admin_state(program_id, state_info, route_admin_info, accounts, AdminRole::RouteAdmin)?;
+
+    // Save the program state
+    let route_bit = 1 << route.clone() as u8;
+    if is_enabled {
+      program_state.disabled_routes &= !route_bit;
+    } else {
+      program_state.disabled_routes |= route_bit;
+    }
+
+    program_state.serialize(&mut &mut state_info.data.borrow_mut()[..])?;
+
+    AggregatorEvent::RouteUpdated(RouteUpdatedEvent {
+      route,
+      is_enabled,
+    }).emit()
+  }
+
+  /// Processes a [SetPoolAllowed](enum.Instruction.html).
+  pub fn process_set_pool_allowed(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    pool: Pubkey,
+    is_allowed: bool,
+  ) -> ProgramResult {
+    // load account info
+    let account_info_iter = &mut accounts.iter();
+    let state_info = next_account_info(account_info_iter)?;
+    let route_admin_info = next_account_info(account_info_iter)?;
+
+    let mut program_state = Self::load_admin_state(program_id, state_info, route_admin_info, accounts, AdminRole::RouteAdmin)?;
+
+    // Save the program state, the used slots stay at the front
+    let mut allowed_pools = program_state.allowed_pools[..program_state.allowed_pool_count as usize].to_vec();
+    if is_allowed {
+      if !allowed_pools.contains(&pool) {
+        if allowed_pools.len() == MAX_ALLOWED_POOLS {
+          return Err(AggregatorError::PoolAllowlistFull.into());
+        }
+        allowed_pools.push(pool);
+      }
+    } else {
+      allowed_pools.retain(|allowed_pool| *allowed_pool != pool);
+    }
+
+    program_state.allowed_pools = [Pubkey::default(); MAX_ALLOWED_POOLS];
+    program_state.allowed_pools[..allowed_pools.len()].copy_from_slice(&allowed_pools);
+    program_state.allowed_pool_count = allowed_pools.len() as u8;
+
+    program_state.serialize(&mut &mut state_info.data.borrow_mut()[..])?;
+
+    AggregatorEvent::PoolAllowlistUpdated(PoolAllowlistUpdatedEvent {
+      pool,
+      is_allowed,
+      allowed_pools,
+    }).emit()
+  }
+
+  /// Processes a [RouteSwap](enum.Instruction.html), single leg routes only.
+  pub fn process_route_swap(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    route1: AggregatorPath,
+    route2: AggregatorPath,
+    amount_in: u64,
+    amount_out: u64
+  ) -> ProgramResult {
+    // without the intermediate account of the options, the second leg has nowhere to swap from
+    if route2 != AggregatorPath::Skip {
+      return Err(AggregatorError::UnsupportedSecondLeg.into());
+    }
+
+    let swap = Self::execute_route(program_id, accounts, route1, AggregatorPath::Skip, amount_in, amount_out, None, false)?;
+    Self::report_swap(swap)
+  }
+
+  /// Processes a [RouteSwapWithOptions](enum.Instruction.html).
+  pub fn process_route_swap_with_options(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    route1: AggregatorPath,
+    route2: AggregatorPath,
+    amount_in: u64,
+    amount_out: u64,
+    options: RouteOptions,
+  ) -> ProgramResult {
+    let swap = Self::execute_route_with_options(program_id, accounts, route1, route2, amount_in, amount_out, options)?;
+    Self::report_swap(swap)
+  }
+
+  /// Processes a [SimulateRouteWithOptions](enum.Instruction.html).
+  /// The result is set as return data, then the returned error rolls the route back.
+  pub fn process_simulate_route_with_options(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    route1: AggregatorPath,
+    route2: AggregatorPath,
+    amount_in: u64,
+    options: RouteOptions,
+  ) -> ProgramResult {
+    let swap = Self::execute_route_with_options(program_id, accounts, route1, route2, amount_in, 0, options)?;
+    Self::report_simulation(swap)
+  }
+
+  /// execute the route with its options, the accounts of the options are created and closed around it
+  fn execute_route_with_options(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    route1: AggregatorPath,
+    route2: AggregatorPath,
+    amount_in: u64,
+    amount_out: u64,
+    options: RouteOptions,
+  ) -> Result<SwapEvent, ProgramError> {
+    let account_info_iter = &mut accounts.iter();
+
+    let system_program_info = next_account_info(account_info_iter)?;
+    let rent_sysvar_info = next_account_info(account_info_iter)?;
+    let native_mint_info = next_account_info(account_info_iter)?;
+
+    let destination_account_infos = if options.create_destination_account {
+      Some((next_account_info(account_info_iter)?, next_account_info(account_info_iter)?))
+    } else {
+      None
+    };
+
+    let intermediate_account_infos = if route2 != AggregatorPath::Skip {
+      Some((next_account_info(account_info_iter)?, next_account_info(account_info_iter)?))
+    } else {
+      None
+    };
+
+    // a single leg route has no intermediate account to sweep
+    if options.sweep_intermediate_residual && intermediate_account_infos.is_none() {
+      return Err(AggregatorError::ConflictingRouteOptions.into());
+    }
+
+    // the RouteSwap accounts follow the accounts of the options
+    let route_accounts = account_info_iter.as_slice();
+    let _state_info = next_account_info(account_info_iter)?;
+    let user_transfer_authority_info = next_account_info(account_info_iter)?;
+    let source_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let _fixed_fee_account_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    if *system_program_info.key != SYSTEM_PROGRAM_ID {
+      return Err(AggregatorError::InvalidSystemProgramId.into());
+    }
+
+    if *rent_sysvar_info.key != RENT_SYSVAR_ID {
+      return Err(AggregatorError::InvalidRentSysvarId.into());
+    }
+
+    if *token_program_info.key != TOKEN_PROGRAM_ID {
+      return Err(AggregatorError::InvalidTokenProgramId.into());
+    }
+
+    let native_info = match (options.wrap_native_input, options.unwrap_native_output) {
+      (false, false) => None,
+      (true, false) => Some(source_info),
+      (false, true) => Some(destination_info),
+      // a single temporary account can't be both sides of the swap
+      (true, true) => return Err(AggregatorError::InvalidNativeAccount.into()),
+    };
+
+    if let Some((associated_token_program_info, destination_mint_info)) = destination_account_infos {
+      if options.unwrap_native_output {
+        return Err(AggregatorError::ConflictingRouteOptions.into());
+      }
+
+      if *associated_token_program_info.key != spl_associated_token_account::id() {
+        return Err(AggregatorError::InvalidAssociatedTokenProgramId.into());
+      }
+
+      let destination_key = spl_associated_token_account::get_associated_token_address(
+        user_transfer_authority_info.key,
+        destination_mint_info.key,
+      );
+      if destination_key != *destination_info.key {
+        return Err(AggregatorError::InvalidDestinationAccount.into());
+      }
+
+      // an existing account is left as is
+      invoke(
+        &spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+          user_transfer_authority_info.key,
+          user_transfer_authority_info.key,
+          destination_mint_info.key,
+          token_program_info.key,
+        ),
+        &[
+          user_transfer_authority_info.clone(),
+          destination_info.clone(),
+          destination_mint_info.clone(),
+          system_program_info.clone(),
+          token_program_info.clone(),
+          associated_token_program_info.clone(),
+        ],
+      )?;
+    }
+
+    if let Some(native_info) = native_info {
+      let seeds = [
+        NATIVE_TOKEN_SEED.as_bytes(),
+        user_transfer_authority_info.key.as_ref(),
+      ];
+      let (native_key, bump) = Pubkey::find_program_address(&seeds, program_id);
+      if native_key != *native_info.key || *native_mint_info.key != spl_token::native_mint::id() {
+        return Err(AggregatorError::InvalidNativeAccount.into());
+      }
+
+      Self::create_token_account(
+        native_info,
+        native_mint_info,
+        user_transfer_authority_info.key,
+        user_transfer_authority_info,
+        token_program_info,
+        system_program_info,
+        rent_sysvar_info,
+        &[NATIVE_TOKEN_SEED.as_bytes(), user_transfer_authority_info.key.as_ref(), &[bump]],
+      )?;
+    }
+
+    // the intermediate account of a two leg route owns itself, the program signs the second leg for it
+    let intermediate_bump = match intermediate_account_infos {
+      Some((intermediate_info, intermediate_mint_info)) => {
+        let seeds = [
+          INTERMEDIATE_TOKEN_SEED.as_bytes(),
+          user_transfer_authority_info.key.as_ref(),
+          intermediate_mint_info.key.as_ref(),
+        ];
+        let (intermediate_key, bump) = Pubkey::find_program_address(&seeds, program_id);
+        if intermediate_key != *intermediate_info.key {
+          return Err(AggregatorError::InvalidIntermediateAccount.into());
+        }
+
+        Self::create_token_account(
+          intermediate_info,
+          intermediate_mint_info,
+          intermediate_info.key,
+          user_transfer_authority_info,
+          token_program_info,
+          system_program_info,
+          rent_sysvar_info,
+          &[
+            INTERMEDIATE_TOKEN_SEED.as_bytes(),
+            user_transfer_authority_info.key.as_ref(),
+            intermediate_mint_info.key.as_ref(),
+            &[bump],
+          ],
+        )?;
+        [bump]
+      }
+      None => [0],
+    };
+    let intermediate_signer = intermediate_account_infos.map(|(intermediate_info, intermediate_mint_info)| (intermediate_info, [
+      INTERMEDIATE_TOKEN_SEED.as_bytes(),
+      user_transfer_authority_info.key.as_ref(),
+      intermediate_mint_info.key.as_ref(),
+      &intermediate_bump[..],
+    ]));
+
+    if options.wrap_native_input {
+      invoke(
+        &system_instruction::transfer(user_transfer_authority_info.key, source_info.key, amount_in),
+        &[user_transfer_authority_info.clone(), source_info.clone(), system_program_info.clone()],
+      )?;
+      invoke(
+        &spl_token::instruction::sync_native(token_program_info.key, source_info.key)?,
+        &[source_info.clone(), token_program_info.clone()],
+      )?;
     }
-    let amount_1 = amount_in - amount_fee;
 
-    if amount_1 <= 0 {
+    let swap = Self::execute_route(
+      program_id,
+      route_accounts,
+      route1,
+      route2,
+      amount_in,
+      amount_out,
+      intermediate_signer.as_ref().map(|(intermediate_info, seeds)| (*intermediate_info, &seeds[..])),
+      options.sweep_intermediate_residual,
+    )?;
+
+    // the second leg, and the sweep, emptied the intermediate account, its rent goes back to the user
+    if let Some((intermediate_info, seeds)) = &intermediate_signer {
+      invoke_signed(
+        &spl_token::instruction::close_account(
+          token_program_info.key,
+          intermediate_info.key,
+          user_transfer_authority_info.key,
+          intermediate_info.key,
+          &[],
+        )?,
+        &[(*intermediate_info).clone(), user_transfer_authority_info.clone(), token_program_info.clone()],
+        &[&seeds[..]],
+      )?;
+    }
+
+    // the rent, and the unwrapped output, go back to the user
+    if let Some(native_info) = native_info {
+      invoke(
+        &spl_token::instruction::close_account(
+          token_program_info.key,
+          native_info.key,
+          user_transfer_authority_info.key,
+          user_transfer_authority_info.key,
+          &[],
+        )?,
+        &[native_info.clone(), user_transfer_authority_info.clone(), token_program_info.clone()],
+      )?;
+    }
+
+    Ok(swap)
+  }
+
+  /// create a temporary token account at the address of `signer_seeds`, paid by `payer_info`
+  fn create_token_account<'a>(
+    token_account_info: &AccountInfo<'a>,
+    mint_info: &AccountInfo<'a>,
+    owner: &Pubkey,
+    payer_info: &AccountInfo<'a>,
+    token_program_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    rent_sysvar_info: &AccountInfo<'a>,
+    signer_seeds: &[&[u8]],
+  ) -> ProgramResult {
+    Self::create_or_allocate_account_raw(
+      &TOKEN_PROGRAM_ID,
+      token_account_info,
+      rent_sysvar_info,
+      system_program_info,
+      payer_info,
+      spl_token::state::Account::LEN,
+      signer_seeds,
+    )?;
+
+    invoke(
+      &spl_token::instruction::initialize_account3(token_program_info.key, token_account_info.key, mint_info.key, owner)?,
+      &[token_account_info.clone(), mint_info.clone(), token_program_info.clone()],
+    )
+  }
+
+  /// log the swap event and set the swap result as return data for CPI callers
+  fn report_swap(swap: SwapEvent) -> ProgramResult {
+    let result = SwapResult {
+      amount_in: swap.amount_in,
+      fee: swap.fee,
+      amount_out: swap.amount_out,
+    };
+    AggregatorEvent::Swap(swap).emit()?;
+
+    // set last, the CPIs of the route reset the return data
+    set_return_data(&result.try_to_vec()?);
+    Ok(())
+  }
+
+  /// Processes a [SimulateRoute](enum.Instruction.html).
+  /// The result is set as return data, then the returned error rolls the route back.
+  pub fn process_simulate_route(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    route1: AggregatorPath,
+    route2: AggregatorPath,
+    amount_in: u64,
+  ) -> ProgramResult {
+    if route2 != AggregatorPath::Skip {
+      return Err(AggregatorError::UnsupportedSecondLeg.into());
+    }
+
+    let swap = Self::execute_route(program_id, accounts, route1, AggregatorPath::Skip, amount_in, 0, None, false)?;
+    Self::report_simulation(swap)
+  }
+
+  /// set the simulated swap as return data, and fail for the route to roll back
+  fn report_simulation(swap: SwapEvent) -> ProgramResult {
+    let result = SimulationResult {
+      amount_in: swap.amount_in,
+      fee: swap.fee,
+      legs_amount_out: swap.legs.iter().map(|leg| leg.amount_out).collect(),
+      amount_out: swap.amount_out,
+    };
+    set_return_data(&result.try_to_vec()?);
+
+    Err(AggregatorError::RouteSimulated.into())
+  }
+
+  /// swap through the route after taking the aggregator fee, the swap is returned for the caller to report.
+  /// A two leg route swaps into the `intermediate` account, which signs the second leg with its seeds.
+  /// With `sweep_residual`, what the second leg left in it is swapped again into the destination.
+  #[allow(clippy::too_many_arguments)]
+  fn execute_route<'a>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo<'a>],
+    route1: AggregatorPath,
+    route2: AggregatorPath,
+    amount_in: u64,
+    amount_out: u64,
+    intermediate: Option<(&AccountInfo<'a>, &[&[u8]])>,
+    sweep_residual: bool,
+  ) -> Result<SwapEvent, ProgramError> {
+    // load account info
+    let account_info_iter = &mut accounts.iter();
+
+    let state_info = next_account_info(account_info_iter)?;
+
+    let user_transfer_authority_info = next_account_info(account_info_iter)?;
+    let source_info = next_account_info(account_info_iter)?;
+    let mid_token_info = next_account_info(account_info_iter)?;
+    let fixed_fee_account_info = next_account_info(account_info_iter)?;
+
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    if *token_program_info.key != TOKEN_PROGRAM_ID{
+        return Err(AggregatorError::InvalidTokenProgramId.into());
+    }
+
+    // a PDA of a calling program signs through its invoke_signed, the venue CPIs pass the privilege on
+    if !user_transfer_authority_info.is_signer {
+        return Err(AggregatorError::MissingUserSignature.into());
+    }
+
+    let program_state = Self::load_state_view(program_id, state_info)?;
+
+    if program_state.is_paused != 0 {
+        return Err(AggregatorError::ProgramPaused.into());
+    }
+
+    if !program_state.is_route_enabled(&route1) {
+        return Err(AggregatorError::RouteDisabled.into());
+    }
+
+    if route2 != AggregatorPath::Skip {
+        if !program_state.is_route_enabled(&route2) {
+            return Err(AggregatorError::RouteDisabled.into());
+        }
+        if intermediate.is_none() {
+            return Err(AggregatorError::InvalidIntermediateAccount.into());
+        }
+    }
+
+    let pool_accounts = account_info_iter.as_slice();
+    Self::check_pool_allowed(&program_state, &route1, pool_accounts)?;
+    Self::check_pool_allowed(&program_state, &route2, pool_accounts.get(Self::route_accounts_len(&route1)..).unwrap_or(&[]))?;
+
+    // fees are collected into a vault owned by the state account
+    let fee_token = Self::unpack_token_account(fixed_fee_account_info, token_program_info.key)?;
+    if fee_token.owner != *state_info.key {
+        return Err(AggregatorError::InvalidFeeOwner.into());
+    }
+
+    // the optional discount token account follows the pool accounts
+    let pool_accounts_len = Self::route_accounts_len(&route1) + Self::route_accounts_len(&route2);
+    let discount_token_info = account_info_iter.as_slice().get(pool_accounts_len);
+
+    let amount_fee = Self::calculate_fee(
+      &program_state,
+      amount_in,
+      discount_token_info,
+      user_transfer_authority_info,
+      token_program_info,
+    )?;
+    drop(program_state);
+
+    if amount_in <= amount_fee {
         return Err(AggregatorError::InsufficientSourceToken.into());
     }
+    let amount_1 = amount_in - amount_fee;
 
     let cpi_accounts = Transfer{
         from: source_info.clone(),
@@ -545,46 +1989,76 @@ impl Processor {
     let cpi_ctx = CpiContext::new(token_program_info.clone(), cpi_accounts);
 
     token::transfer(cpi_ctx, amount_fee)?;
-    // }
-    let mut amount_2 = 0;
-    match route1 {
-      AggregatorPath::Raydium => {
-        amount_2 = Self::swap_raydium(
-            Self::next_account_infos(account_info_iter, RAYDIUM_SWAP_ACCOUNTS)?,
-            amount_1,
-            amount_out,
-            source_info,
-            mid_token_info,
-            user_transfer_authority_info,
-            token_program_info,
-        )?;
-      }
-      AggregatorPath::Saber => {
-        amount_2 = Self::swap_saber(
-          Self::next_account_infos(account_info_iter, SABER_SWAP_ACCOUNTS)?,
-          amount_1,
-          amount_out,
-          source_info,
+
+    // the minimum amount out applies to the last leg
+    let (leg_destination_info, leg_amount_out) = match intermediate {
+      Some((intermediate_info, _)) => (intermediate_info, 0),
+      None => (mid_token_info, amount_out),
+    };
+    let leg_amount = Self::swap_leg(
+      &route1,
+      account_info_iter,
+      amount_1,
+      leg_amount_out,
+      source_info,
+      leg_destination_info,
+      user_transfer_authority_info,
+      token_program_info,
+      &[],
+    )?;
+    let mut legs = vec![SwapLegEvent { venue: route1, amount_out: leg_amount }];
+
+    let mut amount_2 = leg_amount;
+    if let Some((intermediate_info, intermediate_seeds)) = intermediate {
+      // the sweep goes through the pool accounts of the second leg again, and adds to its amount out,
+      // so the minimum amount out is checked on the total after the sweep instead of by the venue
+      let route2_accounts = &mut account_info_iter.clone();
+      let venue_amount_out = if sweep_residual { 0 } else { amount_out };
+      amount_2 = Self::swap_leg(
+        &route2,
+        account_info_iter,
+        leg_amount,
+        venue_amount_out,
+        intermediate_info,
+        mid_token_info,
+        intermediate_info,
+        token_program_info,
+        &[intermediate_seeds],
+      )?;
+
+      let residual = Self::unpack_token_account(intermediate_info, token_program_info.key)?.amount;
+      if sweep_residual && residual > 0 {
+        msg!("Sweep {} left in the intermediate account", residual);
+        amount_2 = amount_2.checked_add(Self::swap_leg(
+          &route2,
+          route2_accounts,
+          residual,
+          0,
+          intermediate_info,
           mid_token_info,
-          user_transfer_authority_info,
+          intermediate_info,
           token_program_info,
-        )?;
+          &[intermediate_seeds],
+        )?).ok_or(ProgramError::ArithmeticOverflow)?;
       }
-      AggregatorPath::Mercurial => {
-        amount_2 = Self::swap_mercurial_4(
-          Self::next_account_infos(account_info_iter, MERCURIAL_SWAP_ACCOUNTS)?,
-          amount_1,
-          amount_out,
-          source_info,
-          mid_token_info,
-          user_transfer_authority_info,
-          token_program_info,
-        )?;
+      if sweep_residual && amount_2 < amount_out {
+        return Err(AggregatorError::SlippageExceeded.into());
       }
-      _ => {}
-    };
+      legs.push(SwapLegEvent { venue: route2, amount_out: amount_2 });
+    }
 
-    Ok(())
+    msg!("Swap amount out {}", amount_2);
+
+    let destination_token = Self::unpack_token_account(mid_token_info, token_program_info.key)?;
+    Ok(SwapEvent {
+      user: *user_transfer_authority_info.key,
+      input_mint: fee_token.mint,
+      output_mint: destination_token.mint,
+      amount_in,
+      fee: amount_fee,
+      legs,
+      amount_out: amount_2,
+    })
   }
 
   /// process `Route to Raydium` instruction.
@@ -604,37 +2078,51 @@ impl Processor {
 
     let token_program_info = next_account_info(account_info_iter)?;
 
-    if *token_program_info.key != Pubkey::from_str(TOKEN_PROGRAM_ID).map_err(|_| AggregatorError::InvalidTokenProgramId)? {
+    if *token_program_info.key != TOKEN_PROGRAM_ID {
       return Err(AggregatorError::InvalidTokenProgramId.into());
     }
 
-    let mut amount_new_in = amount_in;
+    if !user_transfer_authority_info.is_signer {
+      return Err(AggregatorError::MissingUserSignature.into());
+    }
+
     msg!("Swap step {}", step);
 
-    if step == 0 {
+    let (amount_new_in, amount_fee) = if step == 0 {
       let state_info = next_account_info(account_info_iter)?;
       let fixed_fee_account_info = next_account_info(account_info_iter)?;
 
-      Self::check_state_account(program_id, state_info.key)?;
-      let program_state = ProgramState::try_from_slice(&state_info.data.borrow())?;
+      let program_state = Self::load_state_view(program_id, state_info)?;
+
+      if program_state.is_paused != 0 {
+        return Err(AggregatorError::ProgramPaused.into());
+      }
 
-      if program_state.is_initialized == false {
-        return Err(AggregatorError::NotInitializedState.into());
+      if !program_state.is_route_enabled(&route) {
+        return Err(AggregatorError::RouteDisabled.into());
       }
 
+      Self::check_pool_allowed(&program_state, &route, account_info_iter.as_slice())?;
+
+      // fees are collected into a vault owned by the state account
       let fee_token = Self::unpack_token_account(fixed_fee_account_info, token_program_info.key)?;
-      if fee_token.owner != program_state.fee_owner {
+      if fee_token.owner != *state_info.key {
         return Err(AggregatorError::InvalidFeeOwner.into());
       }
 
-      let mut amount_fee = amount_in * program_state.fee_numerator / program_state.fee_denominator;
+      // the optional discount token account follows the pool accounts
+      let discount_token_info = account_info_iter.as_slice().get(Self::route_accounts_len(&route));
 
-      if amount_fee <= 0 {
-        amount_fee = 1;
-      }
-      amount_new_in = amount_in - amount_fee;
+      let amount_fee = Self::calculate_fee(
+        &program_state,
+        amount_in,
+        discount_token_info,
+        user_transfer_authority_info,
+        token_program_info,
+      )?;
+      drop(program_state);
 
-      if amount_new_in <= 0 {
+      if amount_in <= amount_fee {
         return Err(AggregatorError::InsufficientSourceToken.into());
       }
 
@@ -647,51 +2135,38 @@ impl Processor {
       let cpi_ctx = CpiContext::new(token_program_info.clone(), cpi_accounts);
 
       token::transfer(cpi_ctx, amount_fee)?;
+
+      (amount_in - amount_fee, amount_fee)
     } else {
       let source_token = Self::unpack_token_account(source_info, token_program_info.key)?;
-      amount_new_in = source_token.amount - amount_in;
-    }
+      (source_token.amount - amount_in, 0)
+    };
 
     msg!("Swap amount {}", amount_new_in);
 
-    match route {
-      AggregatorPath::Raydium => {
-        Self::swap_raydium(
-          Self::next_account_infos(account_info_iter, RAYDIUM_SWAP_ACCOUNTS)?,
-          amount_new_in,
-          amount_out,
-          source_info,
-          destination_info,
-          user_transfer_authority_info,
-          token_program_info,
-        )?;
-      }
-      AggregatorPath::Saber => {
-        Self::swap_saber(
-          Self::next_account_infos(account_info_iter, SABER_SWAP_ACCOUNTS)?,
-          amount_new_in,
-          amount_out,
-          source_info,
-          destination_info,
-          user_transfer_authority_info,
-          token_program_info,
-        )?;
-      }
-      AggregatorPath::Mercurial => {
-        Self::swap_mercurial_4(
-          Self::next_account_infos(account_info_iter, MERCURIAL_SWAP_ACCOUNTS)?,
-          amount_new_in,
-          amount_out,
-          source_info,
-          destination_info,
-          user_transfer_authority_info,
-          token_program_info,
-        )?;
-      }
-      _ => {}
-    }
+    let amount_leg_out = Self::swap_leg(
+      &route,
+      account_info_iter,
+      amount_new_in,
+      amount_out,
+      source_info,
+      destination_info,
+      user_transfer_authority_info,
+      token_program_info,
+      &[],
+    )?;
 
-    Ok(())
+    let source_token = Self::unpack_token_account(source_info, token_program_info.key)?;
+    let destination_token = Self::unpack_token_account(destination_info, token_program_info.key)?;
+    Self::report_swap(SwapEvent {
+      user: *user_transfer_authority_info.key,
+      input_mint: source_token.mint,
+      output_mint: destination_token.mint,
+      amount_in: amount_new_in + amount_fee,
+      fee: amount_fee,
+      legs: vec![SwapLegEvent { venue: route, amount_out: amount_leg_out }],
+      amount_out: amount_leg_out,
+    })
   }
 
 }
\ No newline at end of file
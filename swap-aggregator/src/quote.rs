@@ -0,0 +1,808 @@
+//! Off-chain quotes for the route legs
+//!
+//! Each venue is priced from its decoded pool data with the same curve and
+//! fees the venue applies on-chain. The `amount_out` of a quote, less the
+//! slippage a client accepts, is what goes into the `amount_out` argument
+//! of `RouteSwap`.
+
+use {
+  solana_program::{
+    program_error::ProgramError,
+    program_pack::Pack,
+  },
+
+  arrayref::array_ref,
+
+  stable_swap_client::state::SwapInfo,
+  stable_swap_math::curve::StableSwap,
+
+  std::convert::TryFrom,
+};
+
+/// Result of a quote
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quote {
+  /// Amount received
+  pub amount_out: u64,
+
+  /// Fee kept by the venue, in the token the venue charges it in
+  pub fee_paid: u64,
+
+  /// Loss against the spot price, 0.01 is 1%
+  pub price_impact: f64,
+}
+
+/// Offset of the swap fee numerator in a Raydium `AmmInfo`
+const RAYDIUM_SWAP_FEE_NUMERATOR_OFFSET: usize = 176;
+/// Offset of the pnl the AMM still has to take, coin then pc, in a Raydium `AmmInfo`
+const RAYDIUM_NEED_TAKE_PNL_OFFSET: usize = 192;
+/// Offset of `native_coin_total`, followed by `native_pc_free` and `native_pc_total`, in a Serum `OpenOrders`
+const SERUM_OPEN_ORDERS_NATIVE_COIN_TOTAL_OFFSET: usize = 85;
+
+/// Reserves and swap fee of a Raydium AMM
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RaydiumPoolState {
+  pub coin_amount: u64,
+  pub pc_amount: u64,
+  pub swap_fee_numerator: u64,
+  pub swap_fee_denominator: u64,
+}
+
+impl RaydiumPoolState {
+  /// Decodes the pool from the amm account, its Serum open orders and its two token vaults.
+  /// The reserves include the funds sitting in open orders, less the pnl the AMM still has to take.
+  pub fn from_account_data(
+    amm_data: &[u8],
+    open_orders_data: &[u8],
+    pool_coin_token_data: &[u8],
+    pool_pc_token_data: &[u8],
+  ) -> Result<Self, ProgramError> {
+    if amm_data.len() < RAYDIUM_NEED_TAKE_PNL_OFFSET + 16 ||
+      open_orders_data.len() < SERUM_OPEN_ORDERS_NATIVE_COIN_TOTAL_OFFSET + 24 {
+      return Err(ProgramError::InvalidAccountData);
+    }
+
+    let read_u64 = |data: &[u8], offset: usize| u64::from_le_bytes(*array_ref![data, offset, 8]);
+
+    let swap_fee_numerator = read_u64(amm_data, RAYDIUM_SWAP_FEE_NUMERATOR_OFFSET);
+    let swap_fee_denominator = read_u64(amm_data, RAYDIUM_SWAP_FEE_NUMERATOR_OFFSET + 8);
+    let need_take_pnl_coin = read_u64(amm_data, RAYDIUM_NEED_TAKE_PNL_OFFSET);
+    let need_take_pnl_pc = read_u64(amm_data, RAYDIUM_NEED_TAKE_PNL_OFFSET + 8);
+
+    let open_orders_coin_total = read_u64(open_orders_data, SERUM_OPEN_ORDERS_NATIVE_COIN_TOTAL_OFFSET);
+    let open_orders_pc_total = read_u64(open_orders_data, SERUM_OPEN_ORDERS_NATIVE_COIN_TOTAL_OFFSET + 16);
+
+    if swap_fee_denominator == 0 || swap_fee_numerator > swap_fee_denominator {
+      return Err(ProgramError::InvalidAccountData);
+    }
+
+    let coin_vault = spl_token::state::Account::unpack(pool_coin_token_data)?;
+    let pc_vault = spl_token::state::Account::unpack(pool_pc_token_data)?;
+
+    let coin_total = coin_vault.amount.checked_add(open_orders_coin_total).ok_or(ProgramError::InvalidAccountData)?;
+    let pc_total = pc_vault.amount.checked_add(open_orders_pc_total).ok_or(ProgramError::InvalidAccountData)?;
+
+    Ok(Self {
+      coin_amount: coin_total.saturating_sub(need_take_pnl_coin),
+      pc_amount: pc_total.saturating_sub(need_take_pnl_pc),
+      swap_fee_numerator,
+      swap_fee_denominator,
+    })
+  }
+}
+
+/// Quotes a constant product swap, the fee is taken from the input like the Raydium AMM does.
+pub fn quote_constant_product(
+  amount_in: u64,
+  reserve_in: u64,
+  reserve_out: u64,
+  fee_numerator: u64,
+  fee_denominator: u64,
+) -> Option<Quote> {
+  if reserve_in == 0 || reserve_out == 0 || fee_denominator == 0 {
+    return None;
+  }
+
+  // the fee is rounded up
+  let fee_paid = (amount_in as u128 * fee_numerator as u128).div_ceil(fee_denominator as u128);
+  let amount_in_after_fee = (amount_in as u128).checked_sub(fee_paid)?;
+
+  let amount_out = reserve_out as u128 * amount_in_after_fee / (reserve_in as u128 + amount_in_after_fee);
+
+  Some(Quote {
+    amount_out: amount_out as u64,
+    fee_paid: fee_paid as u64,
+    price_impact: amount_in_after_fee as f64 / (reserve_in as f64 + amount_in_after_fee as f64),
+  })
+}
+
+/// Quotes a Raydium AMM swap from coin to pc, or from pc to coin.
+/// Only the AMM curve is priced, not the fills its Serum orders may add.
+pub fn quote_raydium(pool: &RaydiumPoolState, amount_in: u64, coin_to_pc: bool) -> Option<Quote> {
+  let (reserve_in, reserve_out) = if coin_to_pc {
+    (pool.coin_amount, pool.pc_amount)
+  } else {
+    (pool.pc_amount, pool.coin_amount)
+  };
+
+  quote_constant_product(amount_in, reserve_in, reserve_out, pool.swap_fee_numerator, pool.swap_fee_denominator)
+}
+
+/// Quotes a Saber swap with the stable swap math of the pool.
+/// The fee is charged in the destination token.
+pub fn quote_saber(
+  swap_info: &SwapInfo,
+  amount_in: u64,
+  source_reserve: u64,
+  destination_reserve: u64,
+  current_ts: i64,
+) -> Option<Quote> {
+  if amount_in == 0 {
+    return Some(Quote { amount_out: 0, fee_paid: 0, price_impact: 0.0 });
+  }
+
+  let stable_swap = StableSwap::new_from_swap_info(swap_info, current_ts);
+  let result = stable_swap.swap_to(amount_in, source_reserve, destination_reserve, &swap_info.fees)?;
+
+  // spot price from a small swap, the fee ratio is the same for both
+  let probe_amount = (source_reserve / 1_000_000).max(1);
+  let probe = stable_swap.swap_to(probe_amount, source_reserve, destination_reserve, &swap_info.fees)?;
+
+  let spot_price = (probe.amount_swapped + probe.fee) as f64 / probe_amount as f64;
+  let price = (result.amount_swapped + result.fee) as f64 / amount_in as f64;
+
+  Some(Quote {
+    amount_out: result.amount_swapped,
+    fee_paid: result.fee,
+    price_impact: price_impact(price, spot_price),
+  })
+}
+
+/// Quotes a Saber swap from the swap account data
+pub fn quote_saber_from_account_data(
+  swap_data: &[u8],
+  amount_in: u64,
+  source_reserve: u64,
+  destination_reserve: u64,
+  current_ts: i64,
+) -> Result<Option<Quote>, ProgramError> {
+  let swap_info = SwapInfo::unpack(swap_data)?;
+  Ok(quote_saber(&swap_info, amount_in, source_reserve, destination_reserve, current_ts))
+}
+
+/// Decoded Mercurial stable swap pool
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MercurialPoolState {
+  /// Amplification coefficient
+  pub amp: u64,
+
+  /// Trade fee ratio, charged on the output
+  pub fee_numerator: u64,
+  pub fee_denominator: u64,
+
+  /// Reserves of the pool tokens, in pool order
+  pub balances: Vec<u64>,
+
+  /// Multipliers bringing each token to the same decimals
+  pub precision_multipliers: Vec<u64>,
+}
+
+/// Offset of the amplification coefficient, followed by the fee and admin fee numerators, in a Mercurial `SwapInfo`
+const MERCURIAL_AMP_OFFSET: usize = 3;
+/// Offset of the number of pool tokens, a u32 followed by the precision factor, in a Mercurial `SwapInfo`
+const MERCURIAL_TOKEN_ACCOUNTS_LENGTH_OFFSET: usize = 27;
+/// Offset of the precision multipliers in a Mercurial `SwapInfo`
+const MERCURIAL_PRECISION_MULTIPLIERS_OFFSET: usize = 39;
+/// Denominator of the Mercurial fees
+const MERCURIAL_FEE_DENOMINATOR: u64 = 10_000_000_000;
+
+impl MercurialPoolState {
+  /// Decodes the pool from the swap account and the balances of its token accounts, in pool order
+  pub fn from_account_data(swap_data: &[u8], balances: &[u64]) -> Result<Self, ProgramError> {
+    if swap_data.len() < MERCURIAL_PRECISION_MULTIPLIERS_OFFSET + 8 * 4 {
+      return Err(ProgramError::InvalidAccountData);
+    }
+
+    let read_u64 = |offset: usize| u64::from_le_bytes(*array_ref![swap_data, offset, 8]);
+
+    let token_count = u32::from_le_bytes(*array_ref![swap_data, MERCURIAL_TOKEN_ACCOUNTS_LENGTH_OFFSET, 4]) as usize;
+    if !(2..=4).contains(&token_count) || balances.len() != token_count {
+      return Err(ProgramError::InvalidAccountData);
+    }
+
+    let precision_multipliers: Vec<u64> = (0..token_count)
+      .map(|index| read_u64(MERCURIAL_PRECISION_MULTIPLIERS_OFFSET + 8 * index))
+      .collect();
+    if precision_multipliers.contains(&0) {
+      return Err(ProgramError::InvalidAccountData);
+    }
+
+    Ok(Self {
+      amp: read_u64(MERCURIAL_AMP_OFFSET),
+      fee_numerator: read_u64(MERCURIAL_AMP_OFFSET + 8),
+      fee_denominator: MERCURIAL_FEE_DENOMINATOR,
+      balances: balances.to_vec(),
+      precision_multipliers,
+    })
+  }
+}
+
+/// StableSwap invariant of balances brought to the same decimals
+fn compute_d(amp: u64, xp: &[u128]) -> Option<u128> {
+  let n = xp.len() as u128;
+  let sum = xp.iter().try_fold(0u128, |sum, x| sum.checked_add(*x))?;
+  if sum == 0 {
+    return Some(0);
+  }
+
+  let ann = amp as u128 * n;
+  let mut d = sum;
+  for _ in 0..256 {
+    let mut d_p = d;
+    for x in xp {
+      d_p = d_p.checked_mul(d)? / (x.checked_mul(n)?).max(1);
+    }
+
+    let d_prev = d;
+    // d = (ann * sum + d_p * n) * d / ((ann - 1) * d + (n + 1) * d_p)
+    let numerator = ann.checked_mul(sum)?.checked_add(d_p.checked_mul(n)?)?.checked_mul(d)?;
+    let denominator = (ann - 1).checked_mul(d)?.checked_add((n + 1).checked_mul(d_p)?)?;
+    d = numerator / denominator;
+
+    if d.abs_diff(d_prev) <= 1 {
+      return Some(d);
+    }
+  }
+
+  None
+}
+
+/// New balance of token `j` keeping the invariant `d`, once token `i` has the balance `x`
+fn compute_y(amp: u64, i: usize, j: usize, x: u128, xp: &[u128], d: u128) -> Option<u128> {
+  let n = xp.len() as u128;
+  let ann = amp as u128 * n;
+
+  let mut c = d;
+  let mut sum = 0u128;
+  for (k, balance) in xp.iter().enumerate() {
+    let balance = if k == i {
+      x
+    } else if k == j {
+      continue;
+    } else {
+      *balance
+    };
+    sum = sum.checked_add(balance)?;
+    c = c.checked_mul(d)? / balance.checked_mul(n)?.max(1);
+  }
+  c = c.checked_mul(d)? / ann.checked_mul(n)?;
+  let b = sum.checked_add(d / ann)?;
+
+  let mut y = d;
+  for _ in 0..256 {
+    let y_prev = y;
+    // y = (y^2 + c) / (2y + b - d)
+    let denominator = y.checked_mul(2)?.checked_add(b)?.checked_sub(d)?;
+    if denominator == 0 {
+      return None;
+    }
+    y = y.checked_mul(y)?.checked_add(c)? / denominator;
+
+    if y.abs_diff(y_prev) <= 1 {
+      return Some(y);
+    }
+  }
+
+  None
+}
+
+/// Quotes a Mercurial swap from token `i` to token `j` of the pool.
+/// The fee is charged in the destination token. Malformed pools, like a zero precision multiplier, have no quote.
+pub fn quote_mercurial(pool: &MercurialPoolState, i: usize, j: usize, amount_in: u64) -> Option<Quote> {
+  let n = pool.balances.len();
+  if i == j || i >= n || j >= n ||
+    pool.precision_multipliers.len() != n || pool.precision_multipliers.contains(&0) ||
+    pool.amp == 0 || pool.fee_denominator == 0 || pool.fee_numerator > pool.fee_denominator {
+    return None;
+  }
+
+  if amount_in == 0 {
+    return Some(Quote { amount_out: 0, fee_paid: 0, price_impact: 0.0 });
+  }
+
+  let xp: Vec<u128> = pool.balances
+    .iter()
+    .zip(pool.precision_multipliers.iter())
+    .map(|(balance, multiplier)| *balance as u128 * *multiplier as u128)
+    .collect();
+
+  let d = compute_d(pool.amp, &xp)?;
+  let dy_for = |dx: u64| -> Option<u128> {
+    let x = xp[i].checked_add(dx as u128 * pool.precision_multipliers[i] as u128)?;
+    let y = compute_y(pool.amp, i, j, x, &xp, d)?;
+    // rounded down, back in the destination decimals
+    Some(xp[j].checked_sub(y)?.saturating_sub(1) / pool.precision_multipliers[j] as u128)
+  };
+
+  let dy = dy_for(amount_in)?;
+  let fee_paid = dy * pool.fee_numerator as u128 / pool.fee_denominator as u128;
+
+  let probe_amount = (pool.balances[i] / 1_000_000).max(1);
+  let spot_price = dy_for(probe_amount)? as f64 / probe_amount as f64;
+  let price = dy as f64 / amount_in as f64;
+
+  Some(Quote {
+    amount_out: u64::try_from(dy - fee_paid).ok()?,
+    fee_paid: u64::try_from(fee_paid).ok()?,
+    price_impact: price_impact(price, spot_price),
+  })
+}
+
+/// Price level of a decoded Serum order book
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OrderbookLevel {
+  /// Native quote amount per native base amount
+  pub price: f64,
+
+  /// Native base amount available at this price
+  pub quantity: u64,
+}
+
+/// Offset of the base then quote lot sizes in a Serum `MarketState`, after the 5 bytes of account padding
+const SERUM_MARKET_LOT_SIZES_OFFSET: usize = 349;
+/// Offset of the nodes in a Serum bids or asks slab, after the account padding, flags and slab header
+const SERUM_SLAB_NODES_OFFSET: usize = 45;
+/// Size of a Serum slab node
+const SERUM_SLAB_NODE_SIZE: usize = 72;
+/// Tag of a Serum slab leaf node, an order
+const SERUM_SLAB_LEAF_TAG: u32 = 2;
+
+/// Decodes the base and quote lot sizes of a Serum market account
+pub fn serum_market_lot_sizes(market_data: &[u8]) -> Result<(u64, u64), ProgramError> {
+  if market_data.len() < SERUM_MARKET_LOT_SIZES_OFFSET + 16 {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  let base_lot_size = u64::from_le_bytes(*array_ref![market_data, SERUM_MARKET_LOT_SIZES_OFFSET, 8]);
+  let quote_lot_size = u64::from_le_bytes(*array_ref![market_data, SERUM_MARKET_LOT_SIZES_OFFSET + 8, 8]);
+  if base_lot_size == 0 || quote_lot_size == 0 {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  Ok((base_lot_size, quote_lot_size))
+}
+
+/// Decodes the bids or asks slab of a Serum market into price levels, best first.
+/// Orders at the same price are merged, prices and quantities are brought to native amounts
+/// with the lot sizes of [serum_market_lot_sizes].
+pub fn serum_orderbook_levels(
+  slab_data: &[u8],
+  is_bids: bool,
+  base_lot_size: u64,
+  quote_lot_size: u64,
+) -> Result<Vec<OrderbookLevel>, ProgramError> {
+  if slab_data.len() < SERUM_SLAB_NODES_OFFSET || base_lot_size == 0 || quote_lot_size == 0 {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  // nodes past the bump index were never used
+  let bump_index = u64::from_le_bytes(*array_ref![slab_data, 13, 8]) as usize;
+  let node_count = (slab_data.len() - SERUM_SLAB_NODES_OFFSET) / SERUM_SLAB_NODE_SIZE;
+  if bump_index > node_count {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  // (price in lots, quantity in lots) of each order
+  let mut orders = Vec::new();
+  for index in 0..bump_index {
+    let node = array_ref![slab_data, SERUM_SLAB_NODES_OFFSET + index * SERUM_SLAB_NODE_SIZE, SERUM_SLAB_NODE_SIZE];
+    if u32::from_le_bytes(*array_ref![node, 0, 4]) != SERUM_SLAB_LEAF_TAG {
+      continue;
+    }
+
+    // the upper half of the order key is the price
+    let price_lots = u64::from_le_bytes(*array_ref![node, 16, 8]);
+    let quantity_lots = u64::from_le_bytes(*array_ref![node, 56, 8]);
+    orders.push((price_lots, quantity_lots));
+  }
+
+  if is_bids {
+    orders.sort_by_key(|(price_lots, _)| std::cmp::Reverse(*price_lots));
+  } else {
+    orders.sort_by_key(|(price_lots, _)| *price_lots);
+  }
+
+  let mut levels: Vec<OrderbookLevel> = Vec::new();
+  let mut last_price_lots = None;
+  for (price_lots, quantity_lots) in orders {
+    let quantity = quantity_lots.checked_mul(base_lot_size).ok_or(ProgramError::InvalidAccountData)?;
+    match levels.last_mut() {
+      Some(level) if last_price_lots == Some(price_lots) => {
+        level.quantity = level.quantity.checked_add(quantity).ok_or(ProgramError::InvalidAccountData)?;
+      },
+      _ => levels.push(OrderbookLevel {
+        price: price_lots as f64 * quote_lot_size as f64 / base_lot_size as f64,
+        quantity,
+      }),
+    }
+    last_price_lots = Some(price_lots);
+  }
+
+  Ok(levels)
+}
+
+/// Quotes a Serum market order by walking the book, decoded with [serum_orderbook_levels]
+/// from the asks to buy or from the bids to sell.
+/// Buying spends quote against the asks, best first; selling spends base against the bids, best first.
+/// The taker fee, in basis points, is charged in quote.
+pub fn quote_serum(levels: &[OrderbookLevel], amount_in: u64, is_buy: bool, taker_fee_bps: u64) -> Option<Quote> {
+  let best_price = levels.first()?.price;
+  let fee_rate = taker_fee_bps as f64 / 10000.0;
+
+  let mut remaining = if is_buy {
+    // the fee comes on top of the quote spent on the book
+    amount_in as f64 / (1.0 + fee_rate)
+  } else {
+    amount_in as f64
+  };
+  let spent = remaining;
+
+  let mut filled = 0.0;
+  for level in levels {
+    if remaining <= 0.0 {
+      break;
+    }
+
+    if is_buy {
+      let base = (remaining / level.price).min(level.quantity as f64).floor();
+      remaining -= base * level.price;
+      filled += base;
+      if base < level.quantity as f64 {
+        break;
+      }
+    } else {
+      let base = remaining.min(level.quantity as f64);
+      remaining -= base;
+      filled += base * level.price;
+    }
+  }
+
+  if filled <= 0.0 {
+    return None;
+  }
+
+  let (amount_out, fee_paid, price, spot_price) = if is_buy {
+    let quote_used = spent - remaining;
+    (filled, quote_used * fee_rate, best_price / (quote_used / filled), 1.0)
+  } else {
+    let fee = filled * fee_rate;
+    let base_used = spent - remaining;
+    (filled - fee, fee, filled / base_used, best_price)
+  };
+
+  Some(Quote {
+    amount_out: amount_out.floor() as u64,
+    fee_paid: fee_paid.ceil() as u64,
+    price_impact: price_impact(price, spot_price),
+  })
+}
+
+/// relative loss of `price` against `spot_price`
+fn price_impact(price: f64, spot_price: f64) -> f64 {
+  if spot_price <= 0.0 {
+    return 0.0;
+  }
+  (1.0 - price / spot_price).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    solana_program::{program_option::COption, pubkey::Pubkey},
+    stable_swap_client::{fees::Fees, state::SwapTokenInfo},
+  };
+
+  fn token_account_data(amount: u64) -> Vec<u8> {
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account::pack(spl_token::state::Account {
+      amount,
+      state: spl_token::state::AccountState::Initialized,
+      delegate: COption::None,
+      is_native: COption::None,
+      close_authority: COption::None,
+      ..Default::default()
+    }, &mut data).unwrap();
+    data
+  }
+
+  fn raydium_account_data(
+    fee: (u64, u64),
+    need_take_pnl: (u64, u64),
+    open_orders_total: (u64, u64),
+  ) -> (Vec<u8>, Vec<u8>) {
+    let mut amm_data = vec![0; RAYDIUM_NEED_TAKE_PNL_OFFSET + 16];
+    amm_data[RAYDIUM_SWAP_FEE_NUMERATOR_OFFSET..][..8].copy_from_slice(&fee.0.to_le_bytes());
+    amm_data[RAYDIUM_SWAP_FEE_NUMERATOR_OFFSET + 8..][..8].copy_from_slice(&fee.1.to_le_bytes());
+    amm_data[RAYDIUM_NEED_TAKE_PNL_OFFSET..][..8].copy_from_slice(&need_take_pnl.0.to_le_bytes());
+    amm_data[RAYDIUM_NEED_TAKE_PNL_OFFSET + 8..][..8].copy_from_slice(&need_take_pnl.1.to_le_bytes());
+
+    let mut open_orders_data = vec![0; SERUM_OPEN_ORDERS_NATIVE_COIN_TOTAL_OFFSET + 24];
+    open_orders_data[SERUM_OPEN_ORDERS_NATIVE_COIN_TOTAL_OFFSET..][..8].copy_from_slice(&open_orders_total.0.to_le_bytes());
+    open_orders_data[SERUM_OPEN_ORDERS_NATIVE_COIN_TOTAL_OFFSET + 16..][..8].copy_from_slice(&open_orders_total.1.to_le_bytes());
+    (amm_data, open_orders_data)
+  }
+
+  fn mercurial_pool(balances: Vec<u64>, precision_multipliers: Vec<u64>, amp: u64) -> MercurialPoolState {
+    MercurialPoolState {
+      amp,
+      fee_numerator: 4_000_000,
+      fee_denominator: MERCURIAL_FEE_DENOMINATOR,
+      balances,
+      precision_multipliers,
+    }
+  }
+
+  fn saber_swap_info(amp: u64) -> SwapInfo {
+    let token = SwapTokenInfo {
+      reserves: Pubkey::default(),
+      mint: Pubkey::default(),
+      admin_fees: Pubkey::default(),
+      index: 0,
+    };
+    SwapInfo {
+      is_initialized: true,
+      is_paused: false,
+      nonce: 0,
+      initial_amp_factor: amp,
+      target_amp_factor: amp,
+      start_ramp_ts: 0,
+      stop_ramp_ts: 0,
+      future_admin_deadline: 0,
+      future_admin_key: Pubkey::default(),
+      admin_key: Pubkey::default(),
+      token_a: token,
+      token_b: SwapTokenInfo { index: 1, ..token },
+      pool_mint: Pubkey::default(),
+      fees: Fees {
+        admin_trade_fee_numerator: 0,
+        admin_trade_fee_denominator: 1,
+        admin_withdraw_fee_numerator: 0,
+        admin_withdraw_fee_denominator: 1,
+        trade_fee_numerator: 4,
+        trade_fee_denominator: 10000,
+        withdraw_fee_numerator: 0,
+        withdraw_fee_denominator: 1,
+      },
+    }
+  }
+
+  /// leaf node of a Serum slab
+  fn serum_leaf(price_lots: u64, quantity_lots: u64) -> [u8; SERUM_SLAB_NODE_SIZE] {
+    let mut node = [0; SERUM_SLAB_NODE_SIZE];
+    node[..4].copy_from_slice(&SERUM_SLAB_LEAF_TAG.to_le_bytes());
+    node[16..24].copy_from_slice(&price_lots.to_le_bytes());
+    node[56..64].copy_from_slice(&quantity_lots.to_le_bytes());
+    node
+  }
+
+  fn serum_slab(nodes: &[[u8; SERUM_SLAB_NODE_SIZE]]) -> Vec<u8> {
+    let mut data = vec![0; SERUM_SLAB_NODES_OFFSET];
+    data[13..21].copy_from_slice(&(nodes.len() as u64).to_le_bytes());
+    for node in nodes {
+      data.extend_from_slice(node);
+    }
+    data
+  }
+
+  #[test]
+  fn constant_product_takes_the_fee_from_the_input() {
+    // 0.25% of 1000 rounded up is 3, 997 in gives 1_000_000 * 997 / 1_000_997
+    let quote = quote_constant_product(1000, 1_000_000, 1_000_000, 25, 10000).unwrap();
+    assert_eq!((quote.amount_out, quote.fee_paid), (996, 3));
+
+    let quote = quote_constant_product(1_000_000, 1_000_000, 4_000_000, 0, 10000).unwrap();
+    assert_eq!((quote.amount_out, quote.fee_paid), (2_000_000, 0));
+    assert!((quote.price_impact - 0.5).abs() < 1e-9);
+  }
+
+  #[test]
+  fn constant_product_of_zero_and_dust_amounts() {
+    assert_eq!(quote_constant_product(0, 1_000_000, 1_000_000, 25, 10000), Some(Quote {
+      amount_out: 0,
+      fee_paid: 0,
+      price_impact: 0.0,
+    }));
+
+    // the rounded up fee eats a single token
+    let quote = quote_constant_product(1, 1_000_000, 1_000_000, 25, 10000).unwrap();
+    assert_eq!((quote.amount_out, quote.fee_paid), (0, 1));
+  }
+
+  #[test]
+  fn constant_product_rejects_empty_pools() {
+    assert_eq!(quote_constant_product(1000, 0, 1_000_000, 25, 10000), None);
+    assert_eq!(quote_constant_product(1000, 1_000_000, 0, 25, 10000), None);
+    assert_eq!(quote_constant_product(1000, 1_000_000, 1_000_000, 25, 0), None);
+  }
+
+  #[test]
+  fn raydium_reserves_include_open_orders_less_pnl() {
+    let (amm_data, open_orders_data) = raydium_account_data((25, 10000), (10, 20), (100, 200));
+    let pool = RaydiumPoolState::from_account_data(
+      &amm_data,
+      &open_orders_data,
+      &token_account_data(1_000_000),
+      &token_account_data(2_000_000),
+    ).unwrap();
+    assert_eq!(pool, RaydiumPoolState {
+      coin_amount: 1_000_090,
+      pc_amount: 2_000_180,
+      swap_fee_numerator: 25,
+      swap_fee_denominator: 10000,
+    });
+
+    let coin_to_pc = quote_raydium(&pool, 1000, true).unwrap();
+    assert_eq!(coin_to_pc, quote_constant_product(1000, 1_000_090, 2_000_180, 25, 10000).unwrap());
+    let pc_to_coin = quote_raydium(&pool, 1000, false).unwrap();
+    assert_eq!(pc_to_coin, quote_constant_product(1000, 2_000_180, 1_000_090, 25, 10000).unwrap());
+  }
+
+  #[test]
+  fn raydium_rejects_malformed_pools() {
+    let coin_vault = token_account_data(u64::MAX);
+    let pc_vault = token_account_data(1);
+
+    // reserves overflowing a u64
+    let (amm_data, open_orders_data) = raydium_account_data((25, 10000), (0, 0), (1, 0));
+    let result = RaydiumPoolState::from_account_data(&amm_data, &open_orders_data, &coin_vault, &pc_vault);
+    assert_eq!(result, Err(ProgramError::InvalidAccountData));
+
+    for fee in [(25, 0), (2, 1)] {
+      let (amm_data, open_orders_data) = raydium_account_data(fee, (0, 0), (0, 0));
+      let result = RaydiumPoolState::from_account_data(&amm_data, &open_orders_data, &pc_vault, &pc_vault);
+      assert_eq!(result, Err(ProgramError::InvalidAccountData));
+    }
+
+    let (amm_data, open_orders_data) = raydium_account_data((25, 10000), (0, 0), (0, 0));
+    let result = RaydiumPoolState::from_account_data(&amm_data[..100], &open_orders_data, &pc_vault, &pc_vault);
+    assert_eq!(result, Err(ProgramError::InvalidAccountData));
+  }
+
+  #[test]
+  fn mercurial_matches_the_curve_reference() {
+    // expected amounts from the reference StableSwap get_y, fee 0.04% of the output
+    let pool = mercurial_pool(vec![1_000_000_000, 1_000_000_000], vec![1, 1], 100);
+    let quote = quote_mercurial(&pool, 0, 1, 1_000_000).unwrap();
+    assert_eq!((quote.amount_out, quote.fee_paid), (999_591, 399));
+
+    let pool = mercurial_pool(vec![1_000_000_000, 2_000_000_000], vec![1, 1], 100);
+    let quote = quote_mercurial(&pool, 0, 1, 100_000_000).unwrap();
+    assert_eq!((quote.amount_out, quote.fee_paid), (100_678_937, 40_287));
+
+    // 6 and 9 decimals tokens
+    let pool = mercurial_pool(vec![1_000_000_000, 1_000_000_000_000], vec![1000, 1], 100);
+    let quote = quote_mercurial(&pool, 0, 1, 1_000_000).unwrap();
+    assert_eq!((quote.amount_out, quote.fee_paid), (999_590_103, 399_996));
+
+    let pool = mercurial_pool(vec![1_000_000_000; 3], vec![1; 3], 200);
+    let quote = quote_mercurial(&pool, 2, 0, 50_000_000).unwrap();
+    assert_eq!((quote.amount_out, quote.fee_paid), (49_967_539, 19_995));
+  }
+
+  #[test]
+  fn mercurial_of_zero_and_dust_amounts() {
+    let pool = mercurial_pool(vec![1_000_000_000, 1_000_000_000], vec![1, 1], 100);
+    assert_eq!(quote_mercurial(&pool, 0, 1, 0), Some(Quote { amount_out: 0, fee_paid: 0, price_impact: 0.0 }));
+
+    // rounding down takes the single token
+    let quote = quote_mercurial(&pool, 0, 1, 1).unwrap();
+    assert_eq!((quote.amount_out, quote.fee_paid), (0, 0));
+  }
+
+  #[test]
+  fn mercurial_rejects_malformed_pools() {
+    let pool = mercurial_pool(vec![1_000_000_000, 1_000_000_000], vec![1, 0], 100);
+    assert_eq!(quote_mercurial(&pool, 0, 1, 1_000_000), None);
+
+    let pool = mercurial_pool(vec![1_000_000_000, 1_000_000_000], vec![1], 100);
+    assert_eq!(quote_mercurial(&pool, 0, 1, 1_000_000), None);
+
+    let pool = mercurial_pool(vec![1_000_000_000, 1_000_000_000], vec![1, 1], 0);
+    assert_eq!(quote_mercurial(&pool, 0, 1, 1_000_000), None);
+
+    let pool = mercurial_pool(vec![1_000_000_000, 1_000_000_000], vec![1, 1], 100);
+    assert_eq!(quote_mercurial(&pool, 0, 0, 1_000_000), None);
+    assert_eq!(quote_mercurial(&pool, 0, 2, 1_000_000), None);
+
+    // balances too large for the invariant
+    let pool = mercurial_pool(vec![u64::MAX, u64::MAX], vec![u64::MAX, u64::MAX], 100);
+    assert_eq!(quote_mercurial(&pool, 0, 1, 1_000_000), None);
+
+    let mut swap_data = vec![0; MERCURIAL_PRECISION_MULTIPLIERS_OFFSET + 8 * 4];
+    swap_data[MERCURIAL_TOKEN_ACCOUNTS_LENGTH_OFFSET..][..4].copy_from_slice(&2u32.to_le_bytes());
+    swap_data[MERCURIAL_PRECISION_MULTIPLIERS_OFFSET..][..8].copy_from_slice(&1u64.to_le_bytes());
+    let result = MercurialPoolState::from_account_data(&swap_data, &[1, 1]);
+    assert_eq!(result, Err(ProgramError::InvalidAccountData));
+
+    swap_data[MERCURIAL_PRECISION_MULTIPLIERS_OFFSET + 8..][..8].copy_from_slice(&1u64.to_le_bytes());
+    assert!(MercurialPoolState::from_account_data(&swap_data, &[1, 1]).is_ok());
+    let result = MercurialPoolState::from_account_data(&swap_data, &[1, 1, 1]);
+    assert_eq!(result, Err(ProgramError::InvalidAccountData));
+  }
+
+  #[test]
+  fn saber_charges_the_fee_on_the_output() {
+    let swap_info = saber_swap_info(100);
+    let quote = quote_saber(&swap_info, 1_000_000, 1_000_000_000, 1_000_000_000, 0).unwrap();
+    assert_eq!(quote.fee_paid, (quote.amount_out + quote.fee_paid) * 4 / 10000);
+    assert!(quote.amount_out + quote.fee_paid <= 1_000_000);
+    assert!(quote.amount_out + quote.fee_paid > 999_000);
+    assert!(quote.price_impact < 0.001);
+
+    assert_eq!(quote_saber(&swap_info, 0, 1_000_000_000, 1_000_000_000, 0), Some(Quote {
+      amount_out: 0,
+      fee_paid: 0,
+      price_impact: 0.0,
+    }));
+  }
+
+  #[test]
+  fn serum_levels_merge_orders_best_first() {
+    let slab = serum_slab(&[serum_leaf(105, 2), serum_leaf(100, 1), [0; SERUM_SLAB_NODE_SIZE], serum_leaf(105, 3)]);
+
+    // 10 base per lot, 1000 quote per lot
+    let asks = serum_orderbook_levels(&slab, false, 10, 1000).unwrap();
+    assert_eq!(asks, vec![
+      OrderbookLevel { price: 10_000.0, quantity: 10 },
+      OrderbookLevel { price: 10_500.0, quantity: 50 },
+    ]);
+    let bids = serum_orderbook_levels(&slab, true, 10, 1000).unwrap();
+    assert_eq!(bids, vec![
+      OrderbookLevel { price: 10_500.0, quantity: 50 },
+      OrderbookLevel { price: 10_000.0, quantity: 10 },
+    ]);
+
+    let mut market_data = vec![0; SERUM_MARKET_LOT_SIZES_OFFSET + 16];
+    market_data[SERUM_MARKET_LOT_SIZES_OFFSET..][..8].copy_from_slice(&10u64.to_le_bytes());
+    market_data[SERUM_MARKET_LOT_SIZES_OFFSET + 8..][..8].copy_from_slice(&1000u64.to_le_bytes());
+    assert_eq!(serum_market_lot_sizes(&market_data), Ok((10, 1000)));
+  }
+
+  #[test]
+  fn serum_rejects_malformed_slabs() {
+    let mut slab = serum_slab(&[serum_leaf(100, 1)]);
+    slab[13..21].copy_from_slice(&2u64.to_le_bytes());
+    assert_eq!(serum_orderbook_levels(&slab, true, 10, 1000), Err(ProgramError::InvalidAccountData));
+
+    let slab = serum_slab(&[serum_leaf(100, u64::MAX)]);
+    assert_eq!(serum_orderbook_levels(&slab, true, 10, 1000), Err(ProgramError::InvalidAccountData));
+    assert_eq!(serum_orderbook_levels(&slab[..20], true, 10, 1000), Err(ProgramError::InvalidAccountData));
+    assert_eq!(serum_market_lot_sizes(&[0; 100]), Err(ProgramError::InvalidAccountData));
+  }
+
+  #[test]
+  fn serum_walks_the_book() {
+    let asks = [
+      OrderbookLevel { price: 2.0, quantity: 100 },
+      OrderbookLevel { price: 4.0, quantity: 100 },
+    ];
+
+    // 400 quote buys the first level then 50 of the second
+    let quote = quote_serum(&asks, 400, true, 0).unwrap();
+    assert_eq!((quote.amount_out, quote.fee_paid), (150, 0));
+    assert!((quote.price_impact - (1.0 - 2.0 / (400.0 / 150.0))).abs() < 1e-9);
+
+    let bids = [
+      OrderbookLevel { price: 4.0, quantity: 100 },
+      OrderbookLevel { price: 2.0, quantity: 100 },
+    ];
+
+    // 150 base sells for 500 quote, less the 0.1% taker fee
+    let quote = quote_serum(&bids, 150, false, 10).unwrap();
+    assert_eq!((quote.amount_out, quote.fee_paid), (499, 1));
+
+    assert_eq!(quote_serum(&[], 100, true, 0), None);
+    assert_eq!(quote_serum(&asks, 1, true, 0), None);
+  }
+}
@@ -0,0 +1,476 @@
+//! Pool registry file format and loader
+//!
+//! A registry lists the pools of each venue with the same fields as the
+//! `RaydiumPoolInfo`, `SaberPoolInfo` and `MercurialPoolInfo` types of the
+//! TypeScript tests, as JSON:
+//!
+//! ```json
+//! {
+//!   "raydium": [{ "coinMint": "...", "pcMint": "...", "ammId": "...", ... }],
+//!   "saber": [{ "ammId": "...", "tokenAMint": "...", ... }],
+//!   "mercurial": [{ "name": "wUSD-4Pool", "ammId": "...", "accounts": ["..."], ... }]
+//! }
+//! ```
+//!
+//! or as TOML with `[[raydium]]`, `[[saber]]` and `[[mercurial]]` tables.
+//! Loading checks every pubkey, mint and venue program id and turns each entry into the
+//! [RoutePool](crate::client::RoutePool) that gives its ordered account list.
+
+use {
+  crate::{
+    client::{MercurialPool, RaydiumPool, RoutePool, SaberPool},
+    constant::{MERCURIAL_SWAP_PROGRAM_ID, RAYDIUM_SWAP_PROGRAM_ID, SABER_SWAP_PROGRAM_ID},
+  },
+
+  serde::{Deserialize, Serialize},
+
+  solana_program::{
+    instruction::AccountMeta,
+    pubkey::Pubkey,
+  },
+
+  std::{fs, path::Path, str::FromStr},
+
+  thiserror::Error,
+};
+
+/// Errors of the pool registry
+#[derive(Debug, Error)]
+pub enum RegistryError {
+  #[error("Cannot read the registry file: {0}")]
+  Io(#[from] std::io::Error),
+
+  #[error("Invalid JSON registry: {0}")]
+  Json(#[from] serde_json::Error),
+
+  #[error("Invalid TOML registry: {0}")]
+  Toml(#[from] toml::de::Error),
+
+  #[error("{pool}: invalid pubkey for {field}: {value}")]
+  InvalidPubkey { pool: String, field: &'static str, value: String },
+
+  #[error("{pool}: invalid mints, {reason}")]
+  InvalidMints { pool: String, reason: &'static str },
+
+  #[error("{pool}: expected {expected} token accounts, found {found}")]
+  InvalidTokenAccounts { pool: String, expected: usize, found: usize },
+
+  #[error("{pool}: expected the {expected} venue program, found {found}")]
+  InvalidProgramId { pool: String, expected: Pubkey, found: Pubkey },
+}
+
+/// Raydium pool entry, mirrors `RaydiumPoolInfo`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RaydiumPoolInfo {
+  pub coin_mint: String,
+  pub pc_mint: String,
+  pub amm_id: String,
+  pub amm_authority: String,
+  pub amm_open_orders: String,
+  pub amm_target_orders: String,
+  pub pool_coin_token_account: String,
+  pub pool_pc_token_account: String,
+  pub serum_program_id: String,
+  pub serum_market: String,
+  pub serum_bids: String,
+  pub serum_asks: String,
+  pub serum_event_queue: String,
+  pub serum_coin_vault_account: String,
+  pub serum_pc_vault_account: String,
+  pub serum_vault_signer: String,
+  pub program_id: String,
+}
+
+/// Saber pool entry, mirrors `SaberPoolInfo`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SaberPoolInfo {
+  pub amm_id: String,
+  pub amm_authority: String,
+  pub token_a_mint: String,
+  pub token_a_fee_account: String,
+  pub token_a_reserve: String,
+  pub token_b_mint: String,
+  pub token_b_fee_account: String,
+  pub token_b_reserve: String,
+  pub program_id: String,
+}
+
+/// Mercurial pool entry, mirrors `MercurialPoolInfo`
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MercurialPoolInfo {
+  pub name: String,
+  pub amm_id: String,
+  pub amm_authority: String,
+  pub accounts: Vec<String>,
+  pub program_id: String,
+
+  /// mints of `accounts`, in the same order; may be left out when they are read from chain
+  #[serde(default)]
+  pub mints: Vec<String>,
+}
+
+/// Pool registry file
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PoolRegistry {
+  #[serde(default)]
+  pub raydium: Vec<RaydiumPoolInfo>,
+
+  #[serde(default)]
+  pub saber: Vec<SaberPoolInfo>,
+
+  #[serde(default)]
+  pub mercurial: Vec<MercurialPoolInfo>,
+}
+
+/// Validated pool of the registry
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegistryPool {
+  /// name of the pool, its amm id when the registry has none
+  pub name: String,
+
+  pub pool: RoutePool,
+
+  /// mints of the pool tokens, coin then pc for Raydium, in pool order otherwise
+  pub mints: Vec<Pubkey>,
+}
+
+impl RegistryPool {
+  /// accounts of a route leg swapping from `source_mint`, in the order the processor expects
+  pub fn account_metas(&self, source_mint: &Pubkey) -> Vec<AccountMeta> {
+    self.pool.account_metas(source_mint)
+  }
+}
+
+fn parse_pubkey(pool: &str, field: &'static str, value: &str) -> Result<Pubkey, RegistryError> {
+  Pubkey::from_str(value).map_err(|_| RegistryError::InvalidPubkey {
+    pool: pool.to_string(),
+    field,
+    value: value.to_string(),
+  })
+}
+
+/// the program id must be the one of the venue, which the processor swaps through
+fn parse_program_id(pool: &str, value: &str, expected: Pubkey) -> Result<Pubkey, RegistryError> {
+  let program_id = parse_pubkey(pool, "programId", value)?;
+  if program_id != expected {
+    return Err(RegistryError::InvalidProgramId { pool: pool.to_string(), expected, found: program_id });
+  }
+  Ok(program_id)
+}
+
+/// mints must be set and distinct
+fn check_mints(pool: &str, mints: &[Pubkey]) -> Result<(), RegistryError> {
+  if mints.iter().any(|mint| *mint == Pubkey::default()) {
+    return Err(RegistryError::InvalidMints { pool: pool.to_string(), reason: "default pubkey" });
+  }
+  for (index, mint) in mints.iter().enumerate() {
+    if mints[index + 1..].contains(mint) {
+      return Err(RegistryError::InvalidMints { pool: pool.to_string(), reason: "duplicated mint" });
+    }
+  }
+  Ok(())
+}
+
+impl RaydiumPoolInfo {
+  pub fn to_registry_pool(&self) -> Result<RegistryPool, RegistryError> {
+    let name = &self.amm_id;
+    let mints = vec![
+      parse_pubkey(name, "coinMint", &self.coin_mint)?,
+      parse_pubkey(name, "pcMint", &self.pc_mint)?,
+    ];
+    check_mints(name, &mints)?;
+
+    let pool = RaydiumPool {
+      amm_id: parse_pubkey(name, "ammId", &self.amm_id)?,
+      amm_authority: parse_pubkey(name, "ammAuthority", &self.amm_authority)?,
+      amm_open_orders: parse_pubkey(name, "ammOpenOrders", &self.amm_open_orders)?,
+      amm_target_orders: parse_pubkey(name, "ammTargetOrders", &self.amm_target_orders)?,
+      pool_coin_token_account: parse_pubkey(name, "poolCoinTokenAccount", &self.pool_coin_token_account)?,
+      pool_pc_token_account: parse_pubkey(name, "poolPcTokenAccount", &self.pool_pc_token_account)?,
+      serum_program_id: parse_pubkey(name, "serumProgramId", &self.serum_program_id)?,
+      serum_market: parse_pubkey(name, "serumMarket", &self.serum_market)?,
+      serum_bids: parse_pubkey(name, "serumBids", &self.serum_bids)?,
+      serum_asks: parse_pubkey(name, "serumAsks", &self.serum_asks)?,
+      serum_event_queue: parse_pubkey(name, "serumEventQueue", &self.serum_event_queue)?,
+      serum_coin_vault_account: parse_pubkey(name, "serumCoinVaultAccount", &self.serum_coin_vault_account)?,
+      serum_pc_vault_account: parse_pubkey(name, "serumPcVaultAccount", &self.serum_pc_vault_account)?,
+      serum_vault_signer: parse_pubkey(name, "serumVaultSigner", &self.serum_vault_signer)?,
+      program_id: parse_program_id(name, &self.program_id, RAYDIUM_SWAP_PROGRAM_ID)?,
+    };
+
+    Ok(RegistryPool {
+      name: name.clone(),
+      pool: RoutePool::Raydium(pool),
+      mints,
+    })
+  }
+}
+
+impl SaberPoolInfo {
+  pub fn to_registry_pool(&self) -> Result<RegistryPool, RegistryError> {
+    let name = &self.amm_id;
+    let pool = SaberPool {
+      swap: parse_pubkey(name, "ammId", &self.amm_id)?,
+      swap_authority: parse_pubkey(name, "ammAuthority", &self.amm_authority)?,
+      token_a_mint: parse_pubkey(name, "tokenAMint", &self.token_a_mint)?,
+      token_a_reserve: parse_pubkey(name, "tokenAReserve", &self.token_a_reserve)?,
+      token_a_admin_fee_account: parse_pubkey(name, "tokenAFeeAccount", &self.token_a_fee_account)?,
+      token_b_mint: parse_pubkey(name, "tokenBMint", &self.token_b_mint)?,
+      token_b_reserve: parse_pubkey(name, "tokenBReserve", &self.token_b_reserve)?,
+      token_b_admin_fee_account: parse_pubkey(name, "tokenBFeeAccount", &self.token_b_fee_account)?,
+      program_id: parse_program_id(name, &self.program_id, SABER_SWAP_PROGRAM_ID)?,
+    };
+
+    let mints = vec![pool.token_a_mint, pool.token_b_mint];
+    check_mints(name, &mints)?;
+
+    Ok(RegistryPool {
+      name: name.clone(),
+      pool: RoutePool::Saber(pool),
+      mints,
+    })
+  }
+}
+
+impl MercurialPoolInfo {
+  pub fn to_registry_pool(&self) -> Result<RegistryPool, RegistryError> {
+    let name = if self.name.is_empty() { &self.amm_id } else { &self.name };
+
+    let mut token_accounts = [Pubkey::default(); 4];
+    if self.accounts.len() != token_accounts.len() {
+      return Err(RegistryError::InvalidTokenAccounts {
+        pool: name.clone(),
+        expected: token_accounts.len(),
+        found: self.accounts.len(),
+      });
+    }
+    for (token_account, value) in token_accounts.iter_mut().zip(self.accounts.iter()) {
+      *token_account = parse_pubkey(name, "accounts", value)?;
+    }
+
+    let mints = self.mints
+      .iter()
+      .map(|mint| parse_pubkey(name, "mints", mint))
+      .collect::<Result<Vec<Pubkey>, RegistryError>>()?;
+    if !mints.is_empty() && mints.len() != token_accounts.len() {
+      return Err(RegistryError::InvalidMints { pool: name.clone(), reason: "one mint per token account expected" });
+    }
+    check_mints(name, &mints)?;
+
+    let pool = MercurialPool {
+      swap: parse_pubkey(name, "ammId", &self.amm_id)?,
+      pool_authority: parse_pubkey(name, "ammAuthority", &self.amm_authority)?,
+      token_accounts,
+      program_id: parse_program_id(name, &self.program_id, MERCURIAL_SWAP_PROGRAM_ID)?,
+    };
+
+    Ok(RegistryPool {
+      name: name.clone(),
+      pool: RoutePool::Mercurial(pool),
+      mints,
+    })
+  }
+}
+
+impl PoolRegistry {
+  pub fn from_json_str(data: &str) -> Result<Self, RegistryError> {
+    Ok(serde_json::from_str(data)?)
+  }
+
+  pub fn from_toml_str(data: &str) -> Result<Self, RegistryError> {
+    Ok(toml::from_str(data)?)
+  }
+
+  /// Reads a registry file, as TOML for a `.toml` file and as JSON otherwise
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, RegistryError> {
+    let path = path.as_ref();
+    let data = fs::read_to_string(path)?;
+    if path.extension().is_some_and(|extension| extension == "toml") {
+      Self::from_toml_str(&data)
+    } else {
+      Self::from_json_str(&data)
+    }
+  }
+
+  /// Validates every entry, Raydium pools first, then Saber and Mercurial
+  pub fn pools(&self) -> Result<Vec<RegistryPool>, RegistryError> {
+    let raydium = self.raydium.iter().map(RaydiumPoolInfo::to_registry_pool);
+    let saber = self.saber.iter().map(SaberPoolInfo::to_registry_pool);
+    let mercurial = self.mercurial.iter().map(MercurialPoolInfo::to_registry_pool);
+    raydium.chain(saber).chain(mercurial).collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    crate::instruction::AggregatorPath,
+  };
+
+  fn key() -> String {
+    Pubkey::new_unique().to_string()
+  }
+
+  fn raydium_info() -> RaydiumPoolInfo {
+    RaydiumPoolInfo {
+      coin_mint: key(),
+      pc_mint: key(),
+      amm_id: key(),
+      amm_authority: key(),
+      amm_open_orders: key(),
+      amm_target_orders: key(),
+      pool_coin_token_account: key(),
+      pool_pc_token_account: key(),
+      serum_program_id: key(),
+      serum_market: key(),
+      serum_bids: key(),
+      serum_asks: key(),
+      serum_event_queue: key(),
+      serum_coin_vault_account: key(),
+      serum_pc_vault_account: key(),
+      serum_vault_signer: key(),
+      program_id: RAYDIUM_SWAP_PROGRAM_ID.to_string(),
+    }
+  }
+
+  fn saber_info() -> SaberPoolInfo {
+    SaberPoolInfo {
+      amm_id: key(),
+      amm_authority: key(),
+      token_a_mint: key(),
+      token_a_fee_account: key(),
+      token_a_reserve: key(),
+      token_b_mint: key(),
+      token_b_fee_account: key(),
+      token_b_reserve: key(),
+      program_id: SABER_SWAP_PROGRAM_ID.to_string(),
+    }
+  }
+
+  fn mercurial_info() -> MercurialPoolInfo {
+    MercurialPoolInfo {
+      name: "4Pool".to_string(),
+      amm_id: key(),
+      amm_authority: key(),
+      accounts: (0..4).map(|_| key()).collect(),
+      program_id: MERCURIAL_SWAP_PROGRAM_ID.to_string(),
+      mints: (0..4).map(|_| key()).collect(),
+    }
+  }
+
+  #[test]
+  fn loads_json_and_toml_registries() {
+    let registry = PoolRegistry {
+      raydium: vec![raydium_info()],
+      saber: vec![saber_info()],
+      mercurial: vec![mercurial_info()],
+    };
+
+    let json = serde_json::to_string(&registry).unwrap();
+    assert!(json.contains("\"coinMint\""));
+    assert_eq!(PoolRegistry::from_json_str(&json).unwrap(), registry);
+    let toml = toml::to_string(&registry).unwrap();
+    assert_eq!(PoolRegistry::from_toml_str(&toml).unwrap(), registry);
+
+    let pools = registry.pools().unwrap();
+    assert_eq!(pools.iter().map(|pool| pool.pool.path()).collect::<Vec<_>>(), vec![
+      AggregatorPath::Raydium,
+      AggregatorPath::Saber,
+      AggregatorPath::Mercurial,
+    ]);
+    assert_eq!(pools[0].name, registry.raydium[0].amm_id);
+    assert_eq!(pools[0].mints, vec![
+      Pubkey::from_str(&registry.raydium[0].coin_mint).unwrap(),
+      Pubkey::from_str(&registry.raydium[0].pc_mint).unwrap(),
+    ]);
+    assert_eq!(pools[2].name, "4Pool");
+    assert_eq!(pools[2].mints.len(), 4);
+
+    // a missing venue is an empty list
+    assert_eq!(PoolRegistry::from_json_str("{}").unwrap(), PoolRegistry::default());
+    assert!(matches!(PoolRegistry::from_json_str("{\"raydium\": {}}"), Err(RegistryError::Json(_))));
+    assert!(matches!(PoolRegistry::from_toml_str("[[raydium]]\ncoinMint = 1"), Err(RegistryError::Toml(_))));
+  }
+
+  #[test]
+  fn rejects_invalid_pubkeys() {
+    let mut raydium = raydium_info();
+    raydium.serum_bids = "not a pubkey".to_string();
+    let error = raydium.to_registry_pool().unwrap_err();
+    assert!(matches!(error, RegistryError::InvalidPubkey { field: "serumBids", ref value, .. } if value == "not a pubkey"));
+
+    let mut saber = saber_info();
+    saber.token_b_mint = String::new();
+    assert!(matches!(saber.to_registry_pool(), Err(RegistryError::InvalidPubkey { field: "tokenBMint", .. })));
+
+    let mut mercurial = mercurial_info();
+    mercurial.accounts[3] = "1".to_string();
+    assert!(matches!(mercurial.to_registry_pool(), Err(RegistryError::InvalidPubkey { field: "accounts", .. })));
+
+    // the first invalid entry fails the registry
+    let registry = PoolRegistry { raydium: vec![raydium_info()], saber: vec![saber], mercurial: vec![] };
+    assert!(matches!(registry.pools(), Err(RegistryError::InvalidPubkey { field: "tokenBMint", .. })));
+  }
+
+  #[test]
+  fn rejects_mismatched_mints() {
+    let mut raydium = raydium_info();
+    raydium.pc_mint = raydium.coin_mint.clone();
+    assert!(matches!(raydium.to_registry_pool(), Err(RegistryError::InvalidMints { reason: "duplicated mint", .. })));
+
+    let mut saber = saber_info();
+    saber.token_a_mint = Pubkey::default().to_string();
+    assert!(matches!(saber.to_registry_pool(), Err(RegistryError::InvalidMints { reason: "default pubkey", .. })));
+
+    let mut mercurial = mercurial_info();
+    mercurial.mints.pop();
+    assert!(matches!(mercurial.to_registry_pool(), Err(RegistryError::InvalidMints { .. })));
+
+    let mut mercurial = mercurial_info();
+    mercurial.mints[3] = mercurial.mints[0].clone();
+    assert!(matches!(mercurial.to_registry_pool(), Err(RegistryError::InvalidMints { reason: "duplicated mint", .. })));
+
+    // mints left to be read from chain
+    let mut mercurial = mercurial_info();
+    mercurial.mints.clear();
+    assert!(mercurial.to_registry_pool().unwrap().mints.is_empty());
+  }
+
+  #[test]
+  fn rejects_other_venue_program_ids() {
+    let mut raydium = raydium_info();
+    raydium.program_id = SABER_SWAP_PROGRAM_ID.to_string();
+    let error = raydium.to_registry_pool().unwrap_err();
+    assert!(matches!(
+      error,
+      RegistryError::InvalidProgramId { expected, found, .. } if expected == RAYDIUM_SWAP_PROGRAM_ID && found == SABER_SWAP_PROGRAM_ID
+    ));
+
+    let mut saber = saber_info();
+    saber.program_id = key();
+    assert!(matches!(saber.to_registry_pool(), Err(RegistryError::InvalidProgramId { expected, .. }) if expected == SABER_SWAP_PROGRAM_ID));
+
+    let mut mercurial = mercurial_info();
+    mercurial.program_id = RAYDIUM_SWAP_PROGRAM_ID.to_string();
+    assert!(matches!(mercurial.to_registry_pool(), Err(RegistryError::InvalidProgramId { .. })));
+
+    // an unparsable program id is still an invalid pubkey
+    let mut mercurial = mercurial_info();
+    mercurial.program_id = "1".to_string();
+    assert!(matches!(mercurial.to_registry_pool(), Err(RegistryError::InvalidPubkey { field: "programId", .. })));
+  }
+
+  #[test]
+  fn rejects_wrong_token_account_counts() {
+    for count in [3, 5] {
+      let mut mercurial = mercurial_info();
+      mercurial.accounts = (0..count).map(|_| key()).collect();
+      let error = mercurial.to_registry_pool().unwrap_err();
+      assert!(matches!(error, RegistryError::InvalidTokenAccounts { expected: 4, found, .. } if found == count));
+    }
+  }
+}
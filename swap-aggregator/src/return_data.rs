@@ -0,0 +1,34 @@
+//! Return data of the swap instructions
+//! Borsh encoded, read with `get_return_data` after a CPI or from the
+//! `returnData` of a `simulateTransaction` response.
+
+use borsh::{BorshDeserialize, BorshSchema, BorshSerialize};
+
+/// Result of `SimulateRoute`
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct SimulationResult {
+  // Amount taken from the source, fee included
+  pub amount_in: u64,
+
+  // Aggregator fee
+  pub fee: u64,
+
+  // Amount out of each leg of the route, in order
+  pub legs_amount_out: Vec<u64>,
+
+  // Amount received on the destination
+  pub amount_out: u64,
+}
+
+/// Result of `RouteSwap`, for CPI callers
+#[derive(Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, BorshSchema)]
+pub struct SwapResult {
+  // Amount taken from the source, fee included
+  pub amount_in: u64,
+
+  // Aggregator fee
+  pub fee: u64,
+
+  // Amount received on the destination
+  pub amount_out: u64,
+}
@@ -0,0 +1,334 @@
+//! Route finding over a pool graph
+//!
+//! The pools form a graph with a node per mint and an edge per pool and
+//! direction. Every path up to the maximum number of hops is quoted with the
+//! [quote](crate::quote) functions and the path with the largest amount out wins.
+//! A single leg route is executed by a `RouteSwap` instruction with the leg's accounts, a two leg
+//! route by a `RouteSwapWithOptions` instruction swapping through the intermediate token account.
+
+use {
+  crate::{
+    client::RoutePool,
+    instruction::AggregatorPath,
+    quote::{self, MercurialPoolState, Quote, RaydiumPoolState},
+    split::{self, Split},
+  },
+
+  solana_program::{
+    instruction::AccountMeta,
+    pubkey::Pubkey,
+  },
+
+  stable_swap_client::state::SwapInfo,
+
+  std::collections::HashMap,
+};
+
+/// Default maximum number of legs of a route
+pub const DEFAULT_MAX_HOPS: usize = 2;
+
+/// Most legs of a route the program executes in one instruction
+pub const MAX_HOPS: usize = 2;
+
+/// Pricing data of a pool
+#[derive(Clone, Debug, PartialEq)]
+pub enum PoolState {
+  Raydium(RaydiumPoolState),
+  Saber {
+    swap_info: Box<SwapInfo>,
+    token_a_amount: u64,
+    token_b_amount: u64,
+  },
+  Mercurial(MercurialPoolState),
+}
+
+/// Pool the router can trade through
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouterPool {
+  /// accounts of the pool
+  pub pool: RoutePool,
+
+  /// mints of the pool tokens, coin then pc for Raydium, in pool order otherwise
+  pub mints: Vec<Pubkey>,
+
+  /// pricing data of the pool
+  pub state: PoolState,
+}
+
+impl RouterPool {
+  /// Quotes a swap of `amount_in` from `source_mint` to `destination_mint` through the pool
+  pub fn quote(
+    &self,
+    source_mint: &Pubkey,
+    destination_mint: &Pubkey,
+    amount_in: u64,
+    current_ts: i64,
+  ) -> Option<Quote> {
+    let i = self.mints.iter().position(|mint| mint == source_mint)?;
+    let j = self.mints.iter().position(|mint| mint == destination_mint)?;
+    if i == j {
+      return None;
+    }
+
+    match &self.state {
+      PoolState::Raydium(state) => quote::quote_raydium(state, amount_in, i == 0),
+      PoolState::Saber { swap_info, token_a_amount, token_b_amount } => {
+        let (source_reserve, destination_reserve) = if i == 0 {
+          (*token_a_amount, *token_b_amount)
+        } else {
+          (*token_b_amount, *token_a_amount)
+        };
+        quote::quote_saber(swap_info, amount_in, source_reserve, destination_reserve, current_ts)
+      },
+      PoolState::Mercurial(state) => quote::quote_mercurial(state, i, j, amount_in),
+    }
+  }
+}
+
+/// Leg of a route
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteLeg {
+  /// index of the pool in the router
+  pub pool_index: usize,
+  pub path: AggregatorPath,
+  pub source_mint: Pubkey,
+  pub destination_mint: Pubkey,
+  pub amount_in: u64,
+  pub quote: Quote,
+
+  /// pool accounts of the leg, in the order the processor expects
+  pub accounts: Vec<AccountMeta>,
+}
+
+/// Route found by the router
+#[derive(Clone, Debug, PartialEq)]
+pub struct Route {
+  pub legs: Vec<RouteLeg>,
+  pub amount_in: u64,
+  pub amount_out: u64,
+}
+
+impl Route {
+  /// the `AggregatorPath` of each leg
+  pub fn paths(&self) -> Vec<AggregatorPath> {
+    self.legs.iter().map(|leg| leg.path.clone()).collect()
+  }
+}
+
+/// Route finder over a set of pools
+#[derive(Clone, Debug)]
+pub struct Router {
+  pools: Vec<RouterPool>,
+
+  /// pools trading each mint, as (pool index, destination mint)
+  graph: HashMap<Pubkey, Vec<(usize, Pubkey)>>,
+
+  max_hops: usize,
+
+  /// unix timestamp the Saber amplification is ramped to
+  current_ts: i64,
+}
+
+impl Router {
+  /// Builds the token graph of `pools`, routes have at most `max_hops` legs, clamped to [MAX_HOPS]
+  pub fn new(pools: Vec<RouterPool>, max_hops: usize, current_ts: i64) -> Self {
+    let mut graph: HashMap<Pubkey, Vec<(usize, Pubkey)>> = HashMap::new();
+    for (index, pool) in pools.iter().enumerate() {
+      for source_mint in pool.mints.iter() {
+        for destination_mint in pool.mints.iter().filter(|mint| *mint != source_mint) {
+          graph.entry(*source_mint).or_default().push((index, *destination_mint));
+        }
+      }
+    }
+
+    Self {
+      pools,
+      graph,
+      max_hops: max_hops.min(MAX_HOPS),
+      current_ts,
+    }
+  }
+
+  pub fn pools(&self) -> &[RouterPool] {
+    &self.pools
+  }
+
+  /// Finds the route with the largest amount out, none if the mints are not connected
+  pub fn find_best_route(&self, input_mint: &Pubkey, output_mint: &Pubkey, amount_in: u64) -> Option<Route> {
+    if input_mint == output_mint || amount_in == 0 || self.max_hops == 0 {
+      return None;
+    }
+
+    let mut best = None;
+    let mut legs = Vec::with_capacity(self.max_hops);
+    self.search(input_mint, output_mint, amount_in, &mut legs, &mut best);
+
+    best.map(|legs: Vec<RouteLeg>| Route {
+      amount_in,
+      amount_out: legs.last().map(|leg| leg.quote.amount_out).unwrap_or_default(),
+      legs,
+    })
+  }
+
+  /// Splits `amount_in` across the pools trading `input_mint` for `output_mint` directly
+  pub fn find_best_split(&self, input_mint: &Pubkey, output_mint: &Pubkey, amount_in: u64, steps: u64) -> Option<Split> {
+    split::split_amount(&self.pools, input_mint, output_mint, amount_in, steps, self.current_ts)
+  }
+
+  /// depth first search over the paths that visit a mint at most once
+  fn search(
+    &self,
+    mint: &Pubkey,
+    output_mint: &Pubkey,
+    amount_in: u64,
+    legs: &mut Vec<RouteLeg>,
+    best: &mut Option<Vec<RouteLeg>>,
+  ) {
+    let Some(edges) = self.graph.get(mint) else {
+      return;
+    };
+
+    for (pool_index, destination_mint) in edges.iter() {
+      let visited = legs.iter().any(|leg| leg.source_mint == *destination_mint || leg.pool_index == *pool_index);
+      if visited {
+        continue;
+      }
+
+      let pool = &self.pools[*pool_index];
+      let quote = match pool.quote(mint, destination_mint, amount_in, self.current_ts) {
+        Some(quote) if quote.amount_out > 0 => quote,
+        _ => continue,
+      };
+
+      legs.push(RouteLeg {
+        pool_index: *pool_index,
+        path: pool.pool.path(),
+        source_mint: *mint,
+        destination_mint: *destination_mint,
+        amount_in,
+        quote,
+        accounts: pool.pool.account_metas(mint),
+      });
+
+      if destination_mint == output_mint {
+        // fewer legs win a tie
+        let is_better = match best.as_ref().and_then(|best| best.last().map(|leg| (leg.quote.amount_out, best.len()))) {
+          Some((amount_out, len)) => quote.amount_out > amount_out || (quote.amount_out == amount_out && legs.len() < len),
+          None => true,
+        };
+        if is_better {
+          *best = Some(legs.clone());
+        }
+      } else if legs.len() < self.max_hops {
+        self.search(destination_mint, output_mint, quote.amount_out, legs, best);
+      }
+
+      legs.pop();
+    }
+  }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+  use {
+    super::*,
+    crate::client::RaydiumPool,
+  };
+
+  /// Raydium pool of `reserves` of `mints`, with the 0.25% Raydium fee
+  pub(crate) fn raydium_pool(mints: (Pubkey, Pubkey), reserves: (u64, u64)) -> RouterPool {
+    RouterPool {
+      pool: RoutePool::Raydium(RaydiumPool {
+        amm_id: Pubkey::new_unique(),
+        amm_authority: Pubkey::new_unique(),
+        amm_open_orders: Pubkey::new_unique(),
+        amm_target_orders: Pubkey::new_unique(),
+        pool_coin_token_account: Pubkey::new_unique(),
+        pool_pc_token_account: Pubkey::new_unique(),
+        serum_program_id: Pubkey::new_unique(),
+        serum_market: Pubkey::new_unique(),
+        serum_bids: Pubkey::new_unique(),
+        serum_asks: Pubkey::new_unique(),
+        serum_event_queue: Pubkey::new_unique(),
+        serum_coin_vault_account: Pubkey::new_unique(),
+        serum_pc_vault_account: Pubkey::new_unique(),
+        serum_vault_signer: Pubkey::new_unique(),
+        program_id: Pubkey::new_unique(),
+      }),
+      mints: vec![mints.0, mints.1],
+      state: PoolState::Raydium(RaydiumPoolState {
+        coin_amount: reserves.0,
+        pc_amount: reserves.1,
+        swap_fee_numerator: 25,
+        swap_fee_denominator: 10000,
+      }),
+    }
+  }
+
+  fn amount_out(pool: &RouterPool, source_mint: &Pubkey, destination_mint: &Pubkey, amount_in: u64) -> u64 {
+    pool.quote(source_mint, destination_mint, amount_in, 0).unwrap().amount_out
+  }
+
+  #[test]
+  fn direct_route_takes_the_deepest_pool() {
+    let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let pools = vec![
+      raydium_pool((a, b), (1_000_000, 1_000_000)),
+      raydium_pool((b, a), (10_000_000, 10_000_000)),
+    ];
+    let router = Router::new(pools.clone(), DEFAULT_MAX_HOPS, 0);
+
+    let route = router.find_best_route(&a, &b, 100_000).unwrap();
+    assert_eq!(route.paths(), vec![AggregatorPath::Raydium]);
+    assert_eq!(route.legs[0].pool_index, 1);
+    assert_eq!(route.legs[0].accounts, pools[1].pool.account_metas(&a));
+    assert_eq!(route.amount_out, amount_out(&pools[1], &a, &b, 100_000));
+    assert!(route.amount_out > amount_out(&pools[0], &a, &b, 100_000));
+  }
+
+  #[test]
+  fn two_leg_route_through_an_intermediate_mint() {
+    let (a, b, c) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let pools = vec![
+      raydium_pool((a, b), (10_000_000, 20_000_000)),
+      raydium_pool((b, c), (20_000_000, 10_000_000)),
+      // a shallow direct pool
+      raydium_pool((a, c), (100_000, 100_000)),
+    ];
+    let router = Router::new(pools.clone(), DEFAULT_MAX_HOPS, 0);
+
+    let route = router.find_best_route(&a, &c, 100_000).unwrap();
+    let intermediate_amount = amount_out(&pools[0], &a, &b, 100_000);
+    assert_eq!(route.legs.iter().map(|leg| leg.pool_index).collect::<Vec<_>>(), vec![0, 1]);
+    assert_eq!((route.legs[1].source_mint, route.legs[1].amount_in), (b, intermediate_amount));
+    assert_eq!(route.amount_out, amount_out(&pools[1], &b, &c, intermediate_amount));
+    assert!(route.amount_out > amount_out(&pools[2], &a, &c, 100_000));
+
+    // a single hop only has the direct pool
+    let router = Router::new(pools.clone(), 1, 0);
+    let route = router.find_best_route(&a, &c, 100_000).unwrap();
+    assert_eq!(route.legs.iter().map(|leg| leg.pool_index).collect::<Vec<_>>(), vec![2]);
+  }
+
+  #[test]
+  fn no_route_between_unconnected_mints() {
+    let (a, b, c, d) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let pools = vec![
+      raydium_pool((a, b), (1_000_000, 1_000_000)),
+      raydium_pool((b, c), (1_000_000, 1_000_000)),
+      raydium_pool((c, d), (1_000_000, 1_000_000)),
+    ];
+
+    // d is three hops away
+    let router = Router::new(pools.clone(), DEFAULT_MAX_HOPS, 0);
+    assert_eq!(router.find_best_route(&a, &d, 1000), None);
+    // the program executes two legs at most
+    assert_eq!(Router::new(pools, 3, 0).find_best_route(&a, &d, 1000), None);
+
+    assert_eq!(router.find_best_route(&a, &Pubkey::new_unique(), 1000), None);
+    assert_eq!(router.find_best_route(&a, &a, 1000), None);
+    assert_eq!(router.find_best_route(&a, &b, 0), None);
+    // dust with no output
+    assert_eq!(router.find_best_route(&a, &b, 1), None);
+  }
+}
@@ -0,0 +1,214 @@
+//! Split of a trade across parallel pools
+//!
+//! The input is cut into equal steps and each step goes to the pool whose
+//! quote gains the most from it. Since every venue curve has a decreasing
+//! marginal price, this greedy allocation ends close to the point where the
+//! marginal prices of the pools used are equal, whatever the curve of each.
+
+use {
+  crate::{
+    quote::Quote,
+    router::RouterPool,
+  },
+
+  solana_program::pubkey::Pubkey,
+};
+
+/// Default number of steps an amount is cut into, 1% each
+pub const DEFAULT_SPLIT_STEPS: u64 = 100;
+
+/// Basis points of a whole split
+pub const SPLIT_BPS_DENOMINATOR: u64 = 10000;
+
+/// Part of a split going through a pool
+#[derive(Clone, Debug, PartialEq)]
+pub struct SplitLeg {
+  /// index of the pool in the pools given to the solver
+  pub pool_index: usize,
+
+  /// share of the input, in basis points
+  pub weight_bps: u64,
+
+  pub amount_in: u64,
+  pub quote: Quote,
+}
+
+/// Split of an amount across pools
+#[derive(Clone, Debug, PartialEq)]
+pub struct Split {
+  /// pools receiving a part of the input, largest part first
+  pub legs: Vec<SplitLeg>,
+  pub amount_in: u64,
+  pub amount_out: u64,
+}
+
+/// Splits `amount_in` of `source_mint` across the pools trading it for `destination_mint`
+/// to maximize the total amount out, none if no pool can take the trade.
+pub fn split_amount(
+  pools: &[RouterPool],
+  source_mint: &Pubkey,
+  destination_mint: &Pubkey,
+  amount_in: u64,
+  steps: u64,
+  current_ts: i64,
+) -> Option<Split> {
+  let candidates: Vec<usize> = pools
+    .iter()
+    .enumerate()
+    .filter(|(_, pool)| pool.mints.contains(source_mint) && pool.mints.contains(destination_mint))
+    .map(|(index, _)| index)
+    .collect();
+  if candidates.is_empty() || amount_in == 0 || source_mint == destination_mint {
+    return None;
+  }
+
+  let quote = |index: usize, amount: u64| -> Option<Quote> {
+    if amount == 0 {
+      return None;
+    }
+    pools[index].quote(source_mint, destination_mint, amount, current_ts)
+  };
+
+  let steps = steps.clamp(1, amount_in);
+  let step_amount = amount_in / steps;
+
+  // (amount in, quote) of each candidate
+  let mut allocations: Vec<(u64, Option<Quote>)> = vec![(0, None); candidates.len()];
+  let mut allocated = 0u64;
+  while allocated < amount_in {
+    // the last step takes the rounding remainder
+    let step = if amount_in - allocated < 2 * step_amount {
+      amount_in - allocated
+    } else {
+      step_amount
+    };
+
+    let mut best: Option<(usize, u64, Quote)> = None;
+    for (candidate, (amount, current)) in allocations.iter().enumerate() {
+      let current_out = current.map(|quote| quote.amount_out).unwrap_or_default();
+      let Some(next) = quote(candidates[candidate], amount + step) else {
+        continue;
+      };
+
+      let gain = next.amount_out.saturating_sub(current_out);
+      if best.is_none_or(|(_, best_gain, _)| gain > best_gain) {
+        best = Some((candidate, gain, next));
+      }
+    }
+
+    let (candidate, _, next) = best?;
+    allocations[candidate] = (allocations[candidate].0 + step, Some(next));
+    allocated += step;
+  }
+
+  let mut legs: Vec<SplitLeg> = allocations
+    .into_iter()
+    .enumerate()
+    .filter_map(|(candidate, (amount, quote))| Some(SplitLeg {
+      pool_index: candidates[candidate],
+      weight_bps: (amount as u128 * SPLIT_BPS_DENOMINATOR as u128 / amount_in as u128) as u64,
+      amount_in: amount,
+      quote: quote?,
+    }))
+    .collect();
+  legs.sort_by_key(|leg| std::cmp::Reverse(leg.amount_in));
+
+  // the largest part takes the basis points lost to rounding
+  let weight_total: u64 = legs.iter().map(|leg| leg.weight_bps).sum();
+  legs[0].weight_bps += SPLIT_BPS_DENOMINATOR - weight_total;
+
+  Some(Split {
+    amount_in,
+    amount_out: legs.iter().map(|leg| leg.quote.amount_out).sum(),
+    legs,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    crate::router::tests::raydium_pool,
+  };
+
+  fn weight_total(split: &Split) -> u64 {
+    split.legs.iter().map(|leg| leg.weight_bps).sum()
+  }
+
+  fn single_pool_amount_out(pool: &RouterPool, source_mint: &Pubkey, destination_mint: &Pubkey, amount_in: u64) -> u64 {
+    pool.quote(source_mint, destination_mint, amount_in, 0).unwrap().amount_out
+  }
+
+  #[test]
+  fn equal_pools_share_the_trade() {
+    let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let pools = vec![
+      raydium_pool((a, b), (1_000_000, 1_000_000)),
+      raydium_pool((a, b), (1_000_000, 1_000_000)),
+    ];
+
+    let split = split_amount(&pools, &a, &b, 200_000, DEFAULT_SPLIT_STEPS, 0).unwrap();
+    assert_eq!(split.legs.iter().map(|leg| leg.weight_bps).collect::<Vec<_>>(), vec![5000, 5000]);
+    assert_eq!(split.legs.iter().map(|leg| leg.amount_in).sum::<u64>(), 200_000);
+    assert_eq!(split.amount_out, split.legs.iter().map(|leg| leg.quote.amount_out).sum::<u64>());
+    assert!(split.amount_out > single_pool_amount_out(&pools[0], &a, &b, 200_000));
+  }
+
+  #[test]
+  fn deeper_pool_takes_the_larger_part() {
+    let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let pools = vec![
+      raydium_pool((a, b), (1_000_000, 1_000_000)),
+      raydium_pool((b, a), (3_000_000, 3_000_000)),
+      // pools of other mints are left out
+      raydium_pool((a, Pubkey::new_unique()), (1_000_000, 1_000_000)),
+    ];
+
+    let split = split_amount(&pools, &a, &b, 400_000, DEFAULT_SPLIT_STEPS, 0).unwrap();
+    assert_eq!(split.legs.iter().map(|leg| leg.pool_index).collect::<Vec<_>>(), vec![1, 0]);
+    assert_eq!(weight_total(&split), SPLIT_BPS_DENOMINATOR);
+    // the reserves are 1:3, so are the parts
+    assert_eq!(split.legs.iter().map(|leg| leg.weight_bps).collect::<Vec<_>>(), vec![7500, 2500]);
+
+    let best_single = single_pool_amount_out(&pools[1], &a, &b, 400_000);
+    assert!(split.amount_out > best_single);
+  }
+
+  #[test]
+  fn weights_add_up_with_rounding() {
+    let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let pools = vec![
+      raydium_pool((a, b), (1_000_000, 1_000_000)),
+      raydium_pool((a, b), (2_000_000, 2_000_000)),
+      raydium_pool((a, b), (3_000_000, 3_000_000)),
+    ];
+
+    for (amount_in, steps) in [(1_000_003, 7), (999_999, DEFAULT_SPLIT_STEPS), (5, DEFAULT_SPLIT_STEPS)] {
+      let split = split_amount(&pools, &a, &b, amount_in, steps, 0).unwrap();
+      assert_eq!(weight_total(&split), SPLIT_BPS_DENOMINATOR);
+      assert_eq!(split.legs.iter().map(|leg| leg.amount_in).sum::<u64>(), amount_in);
+    }
+  }
+
+  #[test]
+  fn single_pool_takes_the_whole_trade() {
+    let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let pools = vec![raydium_pool((a, b), (1_000_000, 1_000_000))];
+
+    let split = split_amount(&pools, &a, &b, 100_000, DEFAULT_SPLIT_STEPS, 0).unwrap();
+    assert_eq!(split.legs.len(), 1);
+    assert_eq!(split.legs[0].weight_bps, SPLIT_BPS_DENOMINATOR);
+    assert_eq!(split.amount_out, single_pool_amount_out(&pools[0], &a, &b, 100_000));
+  }
+
+  #[test]
+  fn no_split_without_a_pool() {
+    let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let pools = vec![raydium_pool((a, b), (1_000_000, 1_000_000))];
+
+    assert_eq!(split_amount(&pools, &a, &Pubkey::new_unique(), 100_000, DEFAULT_SPLIT_STEPS, 0), None);
+    assert_eq!(split_amount(&pools, &a, &b, 0, DEFAULT_SPLIT_STEPS, 0), None);
+    assert_eq!(split_amount(&pools, &a, &a, 100_000, DEFAULT_SPLIT_STEPS, 0), None);
+    assert_eq!(split_amount(&[], &a, &b, 100_000, DEFAULT_SPLIT_STEPS, 0), None);
+  }
+}
@@ -0,0 +1,375 @@
+//! Multisig admin signers, admin roles, pause and route switches
+
+mod common;
+
+use {
+  common::*,
+
+  borsh::{BorshDeserialize, BorshSerialize},
+
+  solana_program::pubkey::Pubkey,
+  solana_program_test::ProgramTestContext,
+  solana_sdk::{
+    instruction::{AccountMeta, Instruction, InstructionError},
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+  },
+
+  swap_aggregator::{
+    client,
+    constant::{MAX_ADMIN_SIGNERS, MAX_ALLOWED_POOLS},
+    error::AggregatorError,
+    event::{AdminSignersUpdatedEvent, AggregatorEvent, PauseUpdatedEvent, PoolAllowlistUpdatedEvent, RolesUpdatedEvent,
+      RouteUpdatedEvent,
+    },
+    instruction::{AggregatorInstruction, AggregatorPath},
+    processor::ProgramState,
+  },
+};
+
+fn aggregator_error(error: AggregatorError) -> TransactionError {
+  TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+async fn program_state(context: &mut ProgramTestContext, program_id: &Pubkey) -> ProgramState {
+  let account = context.banks_client.get_account(client::state_address(program_id)).await.unwrap().unwrap();
+  ProgramState::try_from_slice(&account.data).unwrap()
+}
+
+/// state in multisig mode, with `threshold` of `admin_signers`
+fn multisig_state(admin_signers: &[&Keypair], threshold: u8) -> ProgramState {
+  let mut state = initialized_state(&Pubkey::new_unique());
+  for (i, admin_signer) in admin_signers.iter().enumerate() {
+    state.admin_signers[i] = admin_signer.pubkey();
+  }
+  state.admin_signer_count = admin_signers.len() as u8;
+  state.admin_threshold = threshold;
+  state
+}
+
+/// SetRoles signed by `signers`, the first one in the state owner slot
+fn set_roles(env: &TestEnv, signers: &[&Keypair], fee_admin: &Pubkey) -> Instruction {
+  let mut instruction = client::set_roles(&env.program_id, &signers[0].pubkey(), fee_admin, &Pubkey::default(), &Pubkey::default()).unwrap();
+  let others = signers[1..].iter().map(|signer| signer.pubkey()).collect::<Vec<_>>();
+  client::append_admin_signers(&mut instruction, &others);
+  instruction
+}
+
+#[tokio::test]
+async fn set_admin_signers_switches_to_multisig() {
+  let (mut program_test, env) = TestEnv::new();
+  let owner = Keypair::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&owner.pubkey()));
+  let mut context = program_test.start_with_context().await;
+
+  let admin_signers = [Keypair::new(), Keypair::new(), Keypair::new()];
+  let keys = admin_signers.iter().map(|signer| signer.pubkey()).collect::<Vec<_>>();
+  let instruction = client::set_admin_signers(&env.program_id, &owner.pubkey(), &keys, 2).unwrap();
+  let events = process_events(&mut context, &[instruction], &[&owner]).await;
+  assert_eq!(events, vec![AggregatorEvent::AdminSignersUpdated(AdminSignersUpdatedEvent {
+    admin_signers: keys.clone(),
+    threshold: 2,
+  })]);
+
+  let state = program_state(&mut context, &env.program_id).await;
+  assert_eq!(&state.admin_signers[..3], &keys[..]);
+  assert_eq!(state.admin_signers[3..], [Pubkey::default(); MAX_ADMIN_SIGNERS - 3]);
+  assert_eq!((state.admin_signer_count, state.admin_threshold), (3, 2));
+
+  // the owner key alone no longer administers the state
+  let fee_admin = Pubkey::new_unique();
+  let instruction = set_roles(&env, &[&owner], &fee_admin);
+  let error = process(&mut context, &[instruction], &[&owner]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::NotEnoughAdminSignatures));
+
+  let instruction = set_roles(&env, &[&admin_signers[0], &admin_signers[2]], &fee_admin);
+  process(&mut context, &[instruction], &[&admin_signers[0], &admin_signers[2]]).await.unwrap();
+  assert_eq!(program_state(&mut context, &env.program_id).await.fee_admin, fee_admin);
+
+  // and the signers can go back to a single owner
+  let mut instruction = client::set_admin_signers(&env.program_id, &admin_signers[0].pubkey(), &[], 0).unwrap();
+  client::append_admin_signers(&mut instruction, &[admin_signers[1].pubkey()]);
+  let events = process_events(&mut context, &[instruction], &[&admin_signers[0], &admin_signers[1]]).await;
+  assert_eq!(events, vec![AggregatorEvent::AdminSignersUpdated(AdminSignersUpdatedEvent {
+    admin_signers: vec![],
+    threshold: 0,
+  })]);
+
+  let state = program_state(&mut context, &env.program_id).await;
+  assert_eq!((state.admin_signer_count, state.admin_threshold), (0, 0));
+  let instruction = set_roles(&env, &[&owner], &Pubkey::default());
+  process(&mut context, &[instruction], &[&owner]).await.unwrap();
+}
+
+#[tokio::test]
+async fn multisig_counts_each_admin_signer_once() {
+  let (mut program_test, env) = TestEnv::new();
+  let admin_signers = [Keypair::new(), Keypair::new(), Keypair::new()];
+  let state = multisig_state(&[&admin_signers[0], &admin_signers[1], &admin_signers[2]], 2);
+  add_program_state(&mut program_test, &env.program_id, &state);
+  let mut context = program_test.start_with_context().await;
+  let fee_admin = Pubkey::new_unique();
+
+  let instruction = set_roles(&env, &[&admin_signers[1]], &fee_admin);
+  let error = process(&mut context, &[instruction], &[&admin_signers[1]]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::NotEnoughAdminSignatures));
+
+  // the same signer twice
+  let instruction = set_roles(&env, &[&admin_signers[1], &admin_signers[1]], &fee_admin);
+  let error = process(&mut context, &[instruction], &[&admin_signers[1]]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::NotEnoughAdminSignatures));
+
+  // a signer outside of the admin signers
+  let outsider = Keypair::new();
+  let instruction = set_roles(&env, &[&outsider, &admin_signers[1]], &fee_admin);
+  let error = process(&mut context, &[instruction], &[&outsider, &admin_signers[1]]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::NotEnoughAdminSignatures));
+
+  // an admin signer listed without signing
+  let mut instruction = set_roles(&env, &[&admin_signers[1]], &fee_admin);
+  instruction.accounts.push(AccountMeta::new_readonly(admin_signers[2].pubkey(), false));
+  let error = process(&mut context, &[instruction], &[&admin_signers[1]]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::NotEnoughAdminSignatures));
+
+  let instruction = set_roles(&env, &[&admin_signers[1], &admin_signers[2]], &fee_admin);
+  process(&mut context, &[instruction], &[&admin_signers[1], &admin_signers[2]]).await.unwrap();
+  assert_eq!(program_state(&mut context, &env.program_id).await.fee_admin, fee_admin);
+}
+
+#[tokio::test]
+async fn set_admin_signers_rejects_invalid_sets() {
+  let (mut program_test, env) = TestEnv::new();
+  let owner = Keypair::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&owner.pubkey()));
+  let mut context = program_test.start_with_context().await;
+
+  let first = Pubkey::new_unique();
+  let second = Pubkey::new_unique();
+  let too_many = (0..=MAX_ADMIN_SIGNERS).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+  for (admin_signers, threshold) in [
+    (vec![first, first], 1),
+    (vec![first, second, first], 2),
+    (vec![first, second], 3),
+    (vec![first, second], 0),
+  ] {
+    let instruction = client::set_admin_signers(&env.program_id, &owner.pubkey(), &admin_signers, threshold).unwrap();
+    let error = process(&mut context, &[instruction], &[&owner]).await.unwrap_err();
+    assert_eq!(error, aggregator_error(AggregatorError::InvalidAdminSigners));
+  }
+
+  // the client refuses more admin signers than the state holds, and so does the program
+  let error = client::set_admin_signers(&env.program_id, &owner.pubkey(), &too_many, 1).unwrap_err();
+  assert_eq!(error, AggregatorError::InvalidAdminSigners.into());
+
+  let mut instruction = client::set_admin_signers(&env.program_id, &owner.pubkey(), &too_many[..MAX_ADMIN_SIGNERS], 1).unwrap();
+  instruction.accounts.push(AccountMeta::new_readonly(too_many[MAX_ADMIN_SIGNERS], false));
+  instruction.data = AggregatorInstruction::SetAdminSigners { signer_count: too_many.len() as u8, threshold: 1 }.try_to_vec().unwrap();
+  let error = process(&mut context, &[instruction], &[&owner]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidAdminSigners));
+
+  let other = Keypair::new();
+  let instruction = client::set_admin_signers(&env.program_id, &other.pubkey(), &[first, second], 1).unwrap();
+  let error = process(&mut context, &[instruction], &[&other]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidStateOwner));
+}
+
+#[tokio::test]
+async fn set_roles_needs_the_state_owner() {
+  let (mut program_test, env) = TestEnv::new();
+  let owner = Keypair::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&owner.pubkey()));
+  let mut context = program_test.start_with_context().await;
+
+  let other = Keypair::new();
+  let instruction = set_roles(&env, &[&other], &other.pubkey());
+  let error = process(&mut context, &[instruction], &[&other]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidStateOwner));
+
+  let (fee_admin, route_admin, pause_guardian) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+  let instruction = client::set_roles(&env.program_id, &owner.pubkey(), &fee_admin, &route_admin, &pause_guardian).unwrap();
+  let events = process_events(&mut context, &[instruction], &[&owner]).await;
+  assert_eq!(events, vec![AggregatorEvent::RolesUpdated(RolesUpdatedEvent { fee_admin, route_admin, pause_guardian })]);
+
+  let state = program_state(&mut context, &env.program_id).await;
+  assert_eq!((state.fee_admin, state.route_admin, state.pause_guardian), (fee_admin, route_admin, pause_guardian));
+}
+
+#[tokio::test]
+async fn pause_guardian_pauses_and_the_owner_resumes() {
+  let (mut program_test, env) = TestEnv::new();
+  let owner = Keypair::new();
+  let pause_guardian = Keypair::new();
+  let mut state = initialized_state(&owner.pubkey());
+  state.pause_guardian = pause_guardian.pubkey();
+  add_program_state(&mut program_test, &env.program_id, &state);
+  let mut context = program_test.start_with_context().await;
+
+  let other = Keypair::new();
+  let instruction = client::set_pause(&env.program_id, &other.pubkey(), true).unwrap();
+  let error = process(&mut context, &[instruction], &[&other]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidPauseGuardian));
+
+  let instruction = client::set_pause(&env.program_id, &pause_guardian.pubkey(), true).unwrap();
+  let events = process_events(&mut context, &[instruction], &[&pause_guardian]).await;
+  assert_eq!(events, vec![AggregatorEvent::PauseUpdated(PauseUpdatedEvent {
+    is_paused: true,
+    authority: pause_guardian.pubkey(),
+  })]);
+  assert!(program_state(&mut context, &env.program_id).await.is_paused);
+
+  let instruction = env.route_swap(&env.raydium, USER_BALANCE / 10, 0);
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::ProgramPaused));
+
+  // the guardian can't resume
+  let instruction = client::set_pause(&env.program_id, &pause_guardian.pubkey(), false).unwrap();
+  let error = process(&mut context, &[instruction], &[&pause_guardian]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidStateOwner));
+
+  let instruction = client::set_pause(&env.program_id, &owner.pubkey(), false).unwrap();
+  let events = process_events(&mut context, &[instruction], &[&owner]).await;
+  assert_eq!(events, vec![AggregatorEvent::PauseUpdated(PauseUpdatedEvent { is_paused: false, authority: owner.pubkey() })]);
+  assert!(!program_state(&mut context, &env.program_id).await.is_paused);
+
+  let instruction = env.route_swap(&env.raydium, USER_BALANCE / 10, 1);
+  process(&mut context, &[instruction], &[&env.user]).await.unwrap();
+}
+
+#[tokio::test]
+async fn route_admin_switches_routes() {
+  let (mut program_test, env) = TestEnv::new();
+  let route_admin = Keypair::new();
+  let pause_guardian = Keypair::new();
+  let mut state = initialized_state(&Pubkey::new_unique());
+  state.route_admin = route_admin.pubkey();
+  state.pause_guardian = pause_guardian.pubkey();
+  add_program_state(&mut program_test, &env.program_id, &state);
+  let mut context = program_test.start_with_context().await;
+
+  // other roles don't reach the routes
+  let instruction = client::set_route_enabled(&env.program_id, &pause_guardian.pubkey(), AggregatorPath::Saber, false).unwrap();
+  let error = process(&mut context, &[instruction], &[&pause_guardian]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidRouteAdmin));
+
+  let instruction = client::set_route_enabled(&env.program_id, &route_admin.pubkey(), AggregatorPath::Saber, false).unwrap();
+  let events = process_events(&mut context, &[instruction], &[&route_admin]).await;
+  assert_eq!(events, vec![AggregatorEvent::RouteUpdated(RouteUpdatedEvent {
+    route: AggregatorPath::Saber,
+    is_enabled: false,
+  })]);
+  assert_eq!(program_state(&mut context, &env.program_id).await.disabled_routes, 1 << AggregatorPath::Saber as u8);
+
+  let instruction = env.route_swap(&env.saber, USER_BALANCE / 10, 0);
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::RouteDisabled));
+
+  let instruction = env.route_swap(&env.raydium, USER_BALANCE / 10, 0);
+  process(&mut context, &[instruction], &[&env.user]).await.unwrap();
+
+  let instruction = client::set_route_enabled(&env.program_id, &route_admin.pubkey(), AggregatorPath::Saber, true).unwrap();
+  process(&mut context, &[instruction], &[&route_admin]).await.unwrap();
+  assert_eq!(program_state(&mut context, &env.program_id).await.disabled_routes, 0);
+
+  let instruction = env.route_swap(&env.saber, USER_BALANCE / 10, 1);
+  process(&mut context, &[instruction], &[&env.user]).await.unwrap();
+}
+
+#[tokio::test]
+async fn route_admin_manages_the_pool_allowlist() {
+  let (mut program_test, env) = TestEnv::new();
+  let route_admin = Keypair::new();
+  let pause_guardian = Keypair::new();
+  let mut state = initialized_state(&Pubkey::new_unique());
+  state.route_admin = route_admin.pubkey();
+  state.pause_guardian = pause_guardian.pubkey();
+  add_program_state(&mut program_test, &env.program_id, &state);
+  let mut context = program_test.start_with_context().await;
+  let saber_pool = env.saber.address();
+
+  // other roles don't reach the allowlist
+  let instruction = client::set_pool_allowed(&env.program_id, &pause_guardian.pubkey(), &saber_pool, true).unwrap();
+  let error = process(&mut context, &[instruction], &[&pause_guardian]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidRouteAdmin));
+
+  let instruction = client::set_pool_allowed(&env.program_id, &route_admin.pubkey(), &saber_pool, true).unwrap();
+  let events = process_events(&mut context, &[instruction], &[&route_admin]).await;
+  assert_eq!(events, vec![AggregatorEvent::PoolAllowlistUpdated(PoolAllowlistUpdatedEvent {
+    pool: saber_pool,
+    is_allowed: true,
+    allowed_pools: vec![saber_pool],
+  })]);
+  let state = program_state(&mut context, &env.program_id).await;
+  assert_eq!((state.allowed_pool_count, state.allowed_pools[0]), (1, saber_pool));
+
+  // once the allowlist has a pool, the others are rejected
+  let instruction = env.route_swap(&env.raydium, USER_BALANCE / 10, 0);
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::PoolNotAllowed));
+
+  let instruction = env.route_swap(&env.saber, USER_BALANCE / 10, 0);
+  process(&mut context, &[instruction], &[&env.user]).await.unwrap();
+
+  // removing the last pool allows every pool again
+  let instruction = client::set_pool_allowed(&env.program_id, &route_admin.pubkey(), &saber_pool, false).unwrap();
+  let events = process_events(&mut context, &[instruction], &[&route_admin]).await;
+  assert_eq!(events, vec![AggregatorEvent::PoolAllowlistUpdated(PoolAllowlistUpdatedEvent {
+    pool: saber_pool,
+    is_allowed: false,
+    allowed_pools: vec![],
+  })]);
+  assert_eq!(program_state(&mut context, &env.program_id).await.allowed_pool_count, 0);
+
+  let instruction = env.route_swap(&env.raydium, USER_BALANCE / 10, 1);
+  process(&mut context, &[instruction], &[&env.user]).await.unwrap();
+}
+
+#[tokio::test]
+async fn pool_allowlist_is_bounded() {
+  let (mut program_test, env) = TestEnv::new();
+  let owner = Keypair::new();
+  let mut state = initialized_state(&owner.pubkey());
+  let allowed_pools = [(); MAX_ALLOWED_POOLS].map(|_| Pubkey::new_unique());
+  state.allowed_pools = allowed_pools;
+  state.allowed_pool_count = MAX_ALLOWED_POOLS as u8;
+  add_program_state(&mut program_test, &env.program_id, &state);
+  let mut context = program_test.start_with_context().await;
+
+  let instruction = client::set_pool_allowed(&env.program_id, &owner.pubkey(), &Pubkey::new_unique(), true).unwrap();
+  let error = process(&mut context, &[instruction], &[&owner]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::PoolAllowlistFull));
+
+  // allowing a listed pool again changes nothing, removing one keeps the others in order
+  let instruction = client::set_pool_allowed(&env.program_id, &owner.pubkey(), &allowed_pools[3], true).unwrap();
+  process(&mut context, &[instruction], &[&owner]).await.unwrap();
+
+  let instruction = client::set_pool_allowed(&env.program_id, &owner.pubkey(), &allowed_pools[0], false).unwrap();
+  process(&mut context, &[instruction], &[&owner]).await.unwrap();
+  let state = program_state(&mut context, &env.program_id).await;
+  assert_eq!(state.allowed_pool_count as usize, MAX_ALLOWED_POOLS - 1);
+  assert_eq!(state.allowed_pools[..MAX_ALLOWED_POOLS - 1], allowed_pools[1..]);
+  assert_eq!(state.allowed_pools[MAX_ALLOWED_POOLS - 1], Pubkey::default());
+}
+
+#[tokio::test]
+async fn fee_admin_needs_the_admin_signers_in_multisig_mode() {
+  let (mut program_test, env) = TestEnv::new();
+  let admin_signers = [Keypair::new(), Keypair::new()];
+  let fee_admin = Keypair::new();
+  let mut state = multisig_state(&[&admin_signers[0], &admin_signers[1]], 2);
+  state.fee_admin = fee_admin.pubkey();
+  add_program_state(&mut program_test, &env.program_id, &state);
+  let mut context = program_test.start_with_context().await;
+
+  let instruction = client::queue_fee_change(&env.program_id, &fee_admin.pubkey(), 2, 1000, 3600).unwrap();
+  let error = process(&mut context, &[instruction], &[&fee_admin]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::NotEnoughAdminSignatures));
+
+  let instruction = client::update_discount_tiers(&env.program_id, &fee_admin.pubkey(), &env.mint_a, vec![]).unwrap();
+  let error = process(&mut context, &[instruction], &[&fee_admin]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::NotEnoughAdminSignatures));
+
+  let mut instruction = client::queue_fee_change(&env.program_id, &fee_admin.pubkey(), 2, 1000, 3600).unwrap();
+  client::append_admin_signers(&mut instruction, &[admin_signers[0].pubkey(), admin_signers[1].pubkey()]);
+  process(&mut context, &[instruction], &[&fee_admin, &admin_signers[0], &admin_signers[1]]).await.unwrap();
+  assert!(program_state(&mut context, &env.program_id).await.pending_fee_change.is_pending);
+}
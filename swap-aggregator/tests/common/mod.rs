@@ -0,0 +1,526 @@
+//! Shared setup of the integration tests
+//!
+//! The Raydium, Saber and Mercurial programs are replaced by mock programs
+//! deployed at their mainnet ids. Each mock takes the real instruction layout
+//! of its venue and swaps with constant product math over SPL token vaults
+//! owned by the pool authority, a PDA of the mock seeded with the pool key.
+//! A pool account holding a u64 caps the amount each swap takes, as a venue
+//! filling an order in part, and the rest stays in the source.
+
+#![allow(dead_code)]
+
+use {
+  borsh::{BorshDeserialize, BorshSerialize},
+
+  solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+  },
+  solana_program_test::{processor, BanksClient, BanksTransactionResultWithMetadata, ProgramTest, ProgramTestContext},
+  solana_sdk::{
+    account::Account,
+    instruction::Instruction,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transaction_context::TransactionReturnData,
+  },
+
+  swap_aggregator::{
+    client::{self, MercurialPool, RaydiumPool, RoutePool, SaberPool},
+    constant::*,
+    decoder,
+    event::AggregatorEvent,
+    processor::ProgramState,
+  },
+
+  std::convert::TryInto,
+};
+
+/// Error of the mock programs when the output is below the minimum amount out
+pub const MOCK_SLIPPAGE_ERROR: u32 = 0x100;
+
+/// Initial balance of every pool vault
+pub const POOL_RESERVE: u64 = 1_000_000_000_000;
+
+/// Initial balance of the user source account
+pub const USER_BALANCE: u64 = 1_000_000_000;
+
+/// Aggregator fee ratio of the test state
+pub const FEE_NUMERATOR: u64 = 1;
+pub const FEE_DENOMINATOR: u64 = 1000;
+
+pub fn raydium_program_id() -> Pubkey {
+  RAYDIUM_SWAP_PROGRAM_ID
+}
+
+pub fn saber_program_id() -> Pubkey {
+  stable_swap_client::ID
+}
+
+pub fn mercurial_program_id() -> Pubkey {
+  MERCURIAL_SWAP_PROGRAM_ID
+}
+
+/// amount out of the mock pools
+pub fn constant_product(amount_in: u64, reserve_in: u64, reserve_out: u64) -> u64 {
+  (reserve_out as u128 * amount_in as u128 / (reserve_in as u128 + amount_in as u128)) as u64
+}
+
+/// aggregator fee of the test state
+pub fn aggregator_fee(amount_in: u64) -> u64 {
+  (amount_in * FEE_NUMERATOR / FEE_DENOMINATOR).max(1)
+}
+
+fn unpack_amounts(data: &[u8]) -> Result<(u64, u64), ProgramError> {
+  let amount_in = data.get(1..9).and_then(|slice| slice.try_into().ok()).map(u64::from_le_bytes);
+  let minimum_amount_out = data.get(9..17).and_then(|slice| slice.try_into().ok()).map(u64::from_le_bytes);
+  amount_in.zip(minimum_amount_out).ok_or(ProgramError::InvalidInstructionData)
+}
+
+/// constant product swap from `source` to `destination` through the vaults of `pool`
+#[allow(clippy::too_many_arguments)]
+fn mock_swap<'a>(
+  program_id: &Pubkey,
+  token_program: &AccountInfo<'a>,
+  pool: &AccountInfo<'a>,
+  authority: &AccountInfo<'a>,
+  vaults: &[AccountInfo<'a>],
+  source: &AccountInfo<'a>,
+  destination: &AccountInfo<'a>,
+  user_authority: &AccountInfo<'a>,
+  amount_in: u64,
+  minimum_amount_out: u64,
+) -> ProgramResult {
+  let (authority_key, bump) = Pubkey::find_program_address(&[pool.key.as_ref()], program_id);
+  if *authority.key != authority_key {
+    return Err(ProgramError::InvalidAccountData);
+  }
+
+  let source_mint = spl_token::state::Account::unpack(&source.data.borrow())?.mint;
+  let destination_mint = spl_token::state::Account::unpack(&destination.data.borrow())?.mint;
+  let find_vault = |mint: Pubkey| -> Result<(&AccountInfo<'a>, u64), ProgramError> {
+    vaults
+      .iter()
+      .filter_map(|vault| {
+        let token = spl_token::state::Account::unpack(&vault.data.borrow()).ok()?;
+        (token.mint == mint && token.owner == authority_key).then_some((vault, token.amount))
+      })
+      .next()
+      .ok_or(ProgramError::InvalidAccountData)
+  };
+  let (source_vault, reserve_in) = find_vault(source_mint)?;
+  let (destination_vault, reserve_out) = find_vault(destination_mint)?;
+
+  let fill_limit = pool.data.borrow().get(..8).and_then(|data| data.try_into().ok()).map(u64::from_le_bytes);
+  let amount_in = fill_limit.map_or(amount_in, |fill_limit| amount_in.min(fill_limit));
+  let amount_out = constant_product(amount_in, reserve_in, reserve_out);
+  if amount_out < minimum_amount_out {
+    return Err(ProgramError::Custom(MOCK_SLIPPAGE_ERROR));
+  }
+
+  invoke(
+    &spl_token::instruction::transfer(token_program.key, source.key, source_vault.key, user_authority.key, &[], amount_in)?,
+    &[source.clone(), source_vault.clone(), user_authority.clone(), token_program.clone()],
+  )?;
+  invoke_signed(
+    &spl_token::instruction::transfer(token_program.key, destination_vault.key, destination.key, authority.key, &[], amount_out)?,
+    &[destination_vault.clone(), destination.clone(), authority.clone(), token_program.clone()],
+    &[&[pool.key.as_ref(), &[bump]]],
+  )
+}
+
+/// Raydium AMM `SwapBaseIn`, tag 9
+pub fn process_mock_raydium(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+  if data.first() != Some(&9) || accounts.len() < 18 {
+    return Err(ProgramError::InvalidInstructionData);
+  }
+  let (amount_in, minimum_amount_out) = unpack_amounts(data)?;
+
+  mock_swap(
+    program_id,
+    &accounts[0],
+    &accounts[1],
+    &accounts[2],
+    &accounts[5..7],
+    &accounts[15],
+    &accounts[16],
+    &accounts[17],
+    amount_in,
+    minimum_amount_out,
+  )
+}
+
+/// Saber `Swap`, tag 1
+pub fn process_mock_saber(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+  if data.first() != Some(&1) || accounts.len() < 9 {
+    return Err(ProgramError::InvalidInstructionData);
+  }
+  let (amount_in, minimum_amount_out) = unpack_amounts(data)?;
+
+  mock_swap(
+    program_id,
+    &accounts[8],
+    &accounts[0],
+    &accounts[1],
+    &accounts[4..6],
+    &accounts[3],
+    &accounts[6],
+    &accounts[2],
+    amount_in,
+    minimum_amount_out,
+  )
+}
+
+/// Mercurial `Exchange`, tag 4
+pub fn process_mock_mercurial(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+  if data.first() != Some(&4) || accounts.len() < 8 {
+    return Err(ProgramError::InvalidInstructionData);
+  }
+  let (amount_in, minimum_amount_out) = unpack_amounts(data)?;
+  let last = accounts.len() - 1;
+
+  mock_swap(
+    program_id,
+    &accounts[1],
+    &accounts[0],
+    &accounts[2],
+    &accounts[4..last - 1],
+    &accounts[last - 1],
+    &accounts[last],
+    &accounts[3],
+    amount_in,
+    minimum_amount_out,
+  )
+}
+
+pub fn add_mint(program_test: &mut ProgramTest, mint: &Pubkey) {
+  let mut data = vec![0; spl_token::state::Mint::LEN];
+  spl_token::state::Mint::pack(spl_token::state::Mint {
+    mint_authority: COption::None,
+    supply: u64::MAX / 2,
+    decimals: 6,
+    is_initialized: true,
+    freeze_authority: COption::None,
+  }, &mut data).unwrap();
+
+  program_test.add_account(*mint, Account {
+    lamports: Rent::default().minimum_balance(data.len()),
+    data,
+    owner: spl_token::id(),
+    executable: false,
+    rent_epoch: 0,
+  });
+}
+
+/// Adds a token account, a wrapped SOL account holds its amount in lamports above the rent
+pub fn add_token_account(program_test: &mut ProgramTest, mint: &Pubkey, owner: &Pubkey, amount: u64) -> Pubkey {
+  let pubkey = Pubkey::new_unique();
+  let rent_exempt_reserve = Rent::default().minimum_balance(spl_token::state::Account::LEN);
+  let is_native = *mint == spl_token::native_mint::id();
+
+  let mut data = vec![0; spl_token::state::Account::LEN];
+  spl_token::state::Account::pack(spl_token::state::Account {
+    mint: *mint,
+    owner: *owner,
+    amount,
+    state: spl_token::state::AccountState::Initialized,
+    is_native: if is_native { COption::Some(rent_exempt_reserve) } else { COption::None },
+    ..spl_token::state::Account::default()
+  }, &mut data).unwrap();
+
+  program_test.add_account(pubkey, Account {
+    lamports: rent_exempt_reserve + if is_native { amount } else { 0 },
+    data,
+    owner: spl_token::id(),
+    executable: false,
+    rent_epoch: 0,
+  });
+  pubkey
+}
+
+/// Writes the program state account, sized, owned and with the bump of the one `UpdateState` creates
+pub fn add_program_state(program_test: &mut ProgramTest, program_id: &Pubkey, program_state: &ProgramState) {
+  let bump = client::find_state_address(program_id).1;
+  let data = ProgramState { bump, ..program_state.clone() }.try_to_vec().unwrap();
+  add_state_data(program_test, program_id, data);
+}
+
+/// Writes the program state account with raw `data`, like a state of an older program version
+pub fn add_state_data(program_test: &mut ProgramTest, program_id: &Pubkey, data: Vec<u8>) {
+  program_test.add_account(client::state_address(program_id), Account {
+    lamports: Rent::default().minimum_balance(data.len()),
+    data,
+    owner: *program_id,
+    executable: false,
+    rent_epoch: 0,
+  });
+}
+
+/// Initialized program state charging the test fee ratio
+pub fn initialized_state(state_owner: &Pubkey) -> ProgramState {
+  let mut program_state = ProgramState::try_from_slice(&[0; ProgramState::LEN]).unwrap();
+  program_state.is_initialized = true;
+  program_state.state_owner = *state_owner;
+  program_state.fee_numerator = FEE_NUMERATOR;
+  program_state.fee_denominator = FEE_DENOMINATOR;
+  program_state.fee_change_delay = DEFAULT_FEE_CHANGE_DELAY;
+  program_state
+}
+
+/// Test environment with a pool of each venue between `mint_a` and `mint_b`
+pub struct TestEnv {
+  pub program_id: Pubkey,
+  pub mint_a: Pubkey,
+  pub mint_b: Pubkey,
+  pub user: Keypair,
+  /// token account of `mint_a` holding `USER_BALANCE`
+  pub source: Pubkey,
+  /// empty token account of `mint_b`
+  pub destination: Pubkey,
+  /// token account of `mint_a` owned by the program state
+  pub fee_vault: Pubkey,
+  pub raydium: RoutePool,
+  pub saber: RoutePool,
+  pub mercurial: RoutePool,
+  /// vaults of `mint_a` and `mint_b` of each pool
+  pub raydium_vaults: (Pubkey, Pubkey),
+  pub saber_vaults: (Pubkey, Pubkey),
+  pub mercurial_vaults: (Pubkey, Pubkey),
+}
+
+impl TestEnv {
+  /// Program test with the aggregator, the mock venues, the pools and the user accounts.
+  /// The state account is left for the caller to add.
+  pub fn new() -> (ProgramTest, Self) {
+    let program_id = Pubkey::new_unique();
+    let program_test = ProgramTest::new(
+      "swap_aggregator",
+      program_id,
+      processor!(swap_aggregator::process_instruction),
+    );
+    Self::with_program_test(program_test, program_id)
+  }
+
+  /// Same environment with the native mint as `mint_a`, for wrapped SOL swaps
+  pub fn native() -> (ProgramTest, Self) {
+    let program_id = Pubkey::new_unique();
+    let program_test = ProgramTest::new(
+      "swap_aggregator",
+      program_id,
+      processor!(swap_aggregator::process_instruction),
+    );
+    Self::with_mint_a(program_test, program_id, spl_token::native_mint::id())
+  }
+
+  /// Same environment around a program test already holding the aggregator at `program_id`
+  pub fn with_program_test(program_test: ProgramTest, program_id: Pubkey) -> (ProgramTest, Self) {
+    Self::with_mint_a(program_test, program_id, Pubkey::new_unique())
+  }
+
+  fn with_mint_a(mut program_test: ProgramTest, program_id: Pubkey, mint_a: Pubkey) -> (ProgramTest, Self) {
+    program_test.add_program("mock_raydium", raydium_program_id(), processor!(process_mock_raydium));
+    program_test.add_program("mock_saber", saber_program_id(), processor!(process_mock_saber));
+    program_test.add_program("mock_mercurial", mercurial_program_id(), processor!(process_mock_mercurial));
+
+    let mint_b = Pubkey::new_unique();
+    add_mint(&mut program_test, &mint_a);
+    add_mint(&mut program_test, &mint_b);
+
+    let user = Keypair::new();
+    program_test.add_account(user.pubkey(), Account {
+      lamports: 1_000_000_000,
+      ..Account::default()
+    });
+    let source = add_token_account(&mut program_test, &mint_a, &user.pubkey(), USER_BALANCE);
+    let destination = add_token_account(&mut program_test, &mint_b, &user.pubkey(), 0);
+    let fee_vault = add_token_account(&mut program_test, &mint_a, &client::state_address(&program_id), 0);
+
+    // raydium
+    let amm_id = Pubkey::new_unique();
+    let amm_authority = Pubkey::find_program_address(&[amm_id.as_ref()], &raydium_program_id()).0;
+    let raydium_vaults = (
+      add_token_account(&mut program_test, &mint_a, &amm_authority, POOL_RESERVE),
+      add_token_account(&mut program_test, &mint_b, &amm_authority, POOL_RESERVE),
+    );
+    let raydium = RoutePool::Raydium(RaydiumPool {
+      amm_id,
+      amm_authority,
+      amm_open_orders: Pubkey::new_unique(),
+      amm_target_orders: Pubkey::new_unique(),
+      pool_coin_token_account: raydium_vaults.0,
+      pool_pc_token_account: raydium_vaults.1,
+      serum_program_id: SERUM_PROGRAM_ID,
+      serum_market: Pubkey::new_unique(),
+      serum_bids: Pubkey::new_unique(),
+      serum_asks: Pubkey::new_unique(),
+      serum_event_queue: Pubkey::new_unique(),
+      serum_coin_vault_account: Pubkey::new_unique(),
+      serum_pc_vault_account: Pubkey::new_unique(),
+      serum_vault_signer: Pubkey::new_unique(),
+      program_id: raydium_program_id(),
+    });
+
+    // saber, the reserves are deeper than the other pools
+    let swap = Pubkey::new_unique();
+    let swap_authority = Pubkey::find_program_address(&[swap.as_ref()], &saber_program_id()).0;
+    let saber_vaults = (
+      add_token_account(&mut program_test, &mint_a, &swap_authority, POOL_RESERVE * 2),
+      add_token_account(&mut program_test, &mint_b, &swap_authority, POOL_RESERVE * 2),
+    );
+    let saber = RoutePool::Saber(SaberPool {
+      swap,
+      swap_authority,
+      token_a_mint: mint_a,
+      token_a_reserve: saber_vaults.0,
+      token_a_admin_fee_account: add_token_account(&mut program_test, &mint_a, &swap_authority, 0),
+      token_b_mint: mint_b,
+      token_b_reserve: saber_vaults.1,
+      token_b_admin_fee_account: add_token_account(&mut program_test, &mint_b, &swap_authority, 0),
+      program_id: saber_program_id(),
+    });
+
+    // mercurial, with two more tokens in the pool
+    let mercurial_swap = Pubkey::new_unique();
+    let pool_authority = Pubkey::find_program_address(&[mercurial_swap.as_ref()], &mercurial_program_id()).0;
+    let mercurial_vaults = (
+      add_token_account(&mut program_test, &mint_a, &pool_authority, POOL_RESERVE),
+      add_token_account(&mut program_test, &mint_b, &pool_authority, POOL_RESERVE / 2),
+    );
+    let mint_c = Pubkey::new_unique();
+    let mint_d = Pubkey::new_unique();
+    add_mint(&mut program_test, &mint_c);
+    add_mint(&mut program_test, &mint_d);
+    let mercurial = RoutePool::Mercurial(MercurialPool {
+      swap: mercurial_swap,
+      pool_authority,
+      token_accounts: [
+        mercurial_vaults.0,
+        mercurial_vaults.1,
+        add_token_account(&mut program_test, &mint_c, &pool_authority, POOL_RESERVE),
+        add_token_account(&mut program_test, &mint_d, &pool_authority, POOL_RESERVE),
+      ],
+      program_id: mercurial_program_id(),
+    });
+
+    (program_test, Self {
+      program_id,
+      mint_a,
+      mint_b,
+      user,
+      source,
+      destination,
+      fee_vault,
+      raydium,
+      saber,
+      mercurial,
+      raydium_vaults,
+      saber_vaults,
+      mercurial_vaults,
+    })
+  }
+
+  /// RouteSwap of `amount_in` from `source` to `destination` through `pool`
+  pub fn route_swap(&self, pool: &RoutePool, amount_in: u64, amount_out: u64) -> Instruction {
+    client::route_swap(
+      &self.program_id,
+      &self.user.pubkey(),
+      &self.source,
+      &self.destination,
+      &self.fee_vault,
+      &self.mint_a,
+      pool,
+      None,
+      amount_in,
+      amount_out,
+    ).unwrap()
+  }
+}
+
+/// Signs `instructions` with the payer and `signers` and processes them
+pub async fn process(
+  context: &mut ProgramTestContext,
+  instructions: &[Instruction],
+  signers: &[&Keypair],
+) -> Result<(), TransactionError> {
+  let mut all_signers = vec![&context.payer];
+  all_signers.extend_from_slice(signers);
+
+  let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+  let transaction = Transaction::new_signed_with_payer(
+    instructions,
+    Some(&context.payer.pubkey()),
+    &all_signers,
+    blockhash,
+  );
+  context.banks_client
+    .process_transaction(transaction)
+    .await
+    .map_err(|error| error.unwrap())
+}
+
+/// Processes `instructions` like [process], the transaction must succeed
+pub async fn process_with_metadata(
+  context: &mut ProgramTestContext,
+  instructions: &[Instruction],
+  signers: &[&Keypair],
+) -> BanksTransactionResultWithMetadata {
+  let mut all_signers = vec![&context.payer];
+  all_signers.extend_from_slice(signers);
+
+  let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+  let transaction = Transaction::new_signed_with_payer(
+    instructions,
+    Some(&context.payer.pubkey()),
+    &all_signers,
+    blockhash,
+  );
+  loop {
+    let outcome = context.banks_client.process_transaction_with_metadata(transaction.clone()).await.unwrap();
+    // the bank records the status of a transaction sent by [process] before it unlocks its accounts
+    if outcome.result == Err(TransactionError::AccountInUse) {
+      continue;
+    }
+    outcome.result.clone().unwrap();
+    return outcome;
+  }
+}
+
+/// Processes `instructions` like [process] and returns the log messages of the successful transaction
+pub async fn process_logs(
+  context: &mut ProgramTestContext,
+  instructions: &[Instruction],
+  signers: &[&Keypair],
+) -> Vec<String> {
+  process_with_metadata(context, instructions, signers).await.metadata.unwrap().log_messages
+}
+
+/// Processes `instructions` like [process] and returns the return data of the successful transaction
+pub async fn process_return_data(
+  context: &mut ProgramTestContext,
+  instructions: &[Instruction],
+  signers: &[&Keypair],
+) -> Option<TransactionReturnData> {
+  process_with_metadata(context, instructions, signers).await.metadata.unwrap().return_data
+}
+
+/// Processes `instructions` like [process] and decodes the aggregator events of the logs
+pub async fn process_events(
+  context: &mut ProgramTestContext,
+  instructions: &[Instruction],
+  signers: &[&Keypair],
+) -> Vec<AggregatorEvent> {
+  let program_id = instructions[0].program_id;
+  let log_messages = process_logs(context, instructions, signers).await;
+  decoder::decode_events(&program_id, &log_messages).concat()
+}
+
+pub async fn token_balance(banks_client: &mut BanksClient, token_account: &Pubkey) -> u64 {
+  let account = banks_client.get_account(*token_account).await.unwrap().unwrap();
+  spl_token::state::Account::unpack(&account.data).unwrap().amount
+}
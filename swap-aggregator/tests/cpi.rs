@@ -0,0 +1,348 @@
+//! Return data of the swaps and the CPI helpers, through a mock caller program
+//!
+//! The caller swaps either for a signer of the transaction or for its own PDA,
+//! signing with `invoke_signed` through all three venue adapters. A second
+//! caller swaps a two leg route with the options for its PDA.
+
+mod common;
+
+use {
+  common::*,
+
+  borsh::{BorshDeserialize, BorshSerialize},
+
+  solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::set_return_data,
+    pubkey::Pubkey,
+  },
+  solana_program_test::{processor, ProgramTest},
+  solana_sdk::{
+    account::Account,
+    instruction::InstructionError,
+    signature::Signer,
+    transaction::TransactionError,
+  },
+
+  swap_aggregator::{
+    client::{self, RoutePool},
+    cpi::{self, RouteSwapAccounts, RouteSwapWithOptionsAccounts},
+    error::AggregatorError,
+    instruction::{AggregatorPath, RouteOptions},
+    return_data::SwapResult,
+  },
+};
+
+const AMOUNT_IN: u64 = 10_000_000;
+
+/// Seed of the PDA authority of the mock caller
+const AUTHORITY_SEED: &[u8] = b"authority";
+
+/// Swaps through the aggregator with the CPI helpers and returns the swap result as its own return data
+///
+/// Accounts: the aggregator program, then the `RouteSwap` accounts. Data: route, amount in, minimum amount
+/// out and the bump of the PDA authority, when the user transfer authority is the PDA of the caller.
+fn process_mock_caller(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+  let (route, amount_in, minimum_amount_out, authority_bump) = <(AggregatorPath, u64, u64, Option<u8>)>::try_from_slice(data)?;
+  let route_swap_accounts = RouteSwapAccounts {
+    aggregator_program: accounts[0].clone(),
+    program_state: accounts[1].clone(),
+    user_transfer_authority: accounts[2].clone(),
+    source_token_account: accounts[3].clone(),
+    destination_token_account: accounts[4].clone(),
+    fee_vault: accounts[5].clone(),
+    token_program: accounts[6].clone(),
+    pool_accounts: accounts[7..].to_vec(),
+    discount_token_account: None,
+  };
+
+  let result = match authority_bump {
+    Some(bump) => cpi::route_swap_as_pda(
+      program_id,
+      route_swap_accounts,
+      route,
+      amount_in,
+      minimum_amount_out,
+      &[AUTHORITY_SEED, &[bump]],
+    )?,
+    None => cpi::route_swap(route_swap_accounts, route, amount_in, minimum_amount_out, &[])?,
+  };
+  set_return_data(&result.try_to_vec()?);
+  Ok(())
+}
+
+/// Swaps through the aggregator with `route_swap_with_options_as_pda`, for the PDA authority of the caller,
+/// and returns the swap result as its own return data
+///
+/// Accounts: the aggregator program, then the `RouteSwapWithOptions` accounts, without a created destination.
+/// Data: both routes, amount in, minimum amount out and the bump of the PDA authority.
+fn process_mock_options_caller(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+  let (route1, route2, amount_in, minimum_amount_out, authority_bump) =
+    <(AggregatorPath, AggregatorPath, u64, u64, u8)>::try_from_slice(data)?;
+  let (intermediate_account, route_swap_index) = match route2 {
+    AggregatorPath::Skip => (None, 4),
+    _ => (Some((accounts[4].clone(), accounts[5].clone())), 6),
+  };
+  let route_swap_accounts = &accounts[route_swap_index..];
+  let options_accounts = RouteSwapWithOptionsAccounts {
+    system_program: accounts[1].clone(),
+    rent_sysvar: accounts[2].clone(),
+    native_mint: accounts[3].clone(),
+    destination_account: None,
+    intermediate_account,
+    route_swap: RouteSwapAccounts {
+      aggregator_program: accounts[0].clone(),
+      program_state: route_swap_accounts[0].clone(),
+      user_transfer_authority: route_swap_accounts[1].clone(),
+      source_token_account: route_swap_accounts[2].clone(),
+      destination_token_account: route_swap_accounts[3].clone(),
+      fee_vault: route_swap_accounts[4].clone(),
+      token_program: route_swap_accounts[5].clone(),
+      pool_accounts: route_swap_accounts[6..].to_vec(),
+      discount_token_account: None,
+    },
+  };
+
+  let result = cpi::route_swap_with_options_as_pda(
+    program_id,
+    options_accounts,
+    route1,
+    route2,
+    amount_in,
+    minimum_amount_out,
+    RouteOptions::default(),
+    &[AUTHORITY_SEED, &[authority_bump]],
+  )?;
+  set_return_data(&result.try_to_vec()?);
+  Ok(())
+}
+
+/// Mock caller instruction swapping `AMOUNT_IN` through `pool` for `authority`
+#[allow(clippy::too_many_arguments)]
+fn caller_instruction(
+  caller_program_id: &Pubkey,
+  env: &TestEnv,
+  pool: &RoutePool,
+  authority: &Pubkey,
+  source: &Pubkey,
+  destination: &Pubkey,
+  minimum_amount_out: u64,
+  authority_bump: Option<u8>,
+) -> Instruction {
+  let route_swap = client::route_swap(
+    &env.program_id,
+    authority,
+    source,
+    destination,
+    &env.fee_vault,
+    &env.mint_a,
+    pool,
+    None,
+    AMOUNT_IN,
+    minimum_amount_out,
+  ).unwrap();
+
+  let mut accounts = vec![AccountMeta::new_readonly(env.program_id, false)];
+  accounts.extend(route_swap.accounts.into_iter().map(|mut meta| {
+    // the PDA authority is signed by the caller, not by the transaction
+    meta.is_signer &= authority_bump.is_none();
+    meta
+  }));
+  Instruction {
+    program_id: *caller_program_id,
+    accounts,
+    data: (pool.path(), AMOUNT_IN, minimum_amount_out, authority_bump).try_to_vec().unwrap(),
+  }
+}
+
+/// Test environment with the mock caller, its PDA authority holding `USER_BALANCE` of `mint_a`
+fn pda_env() -> (ProgramTest, TestEnv, Pubkey, (Pubkey, u8), Pubkey, Pubkey) {
+  let caller_program_id = Pubkey::new_unique();
+  let (mut program_test, env) = TestEnv::new();
+  program_test.add_program("mock_caller", caller_program_id, processor!(process_mock_caller));
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+
+  let (authority, bump) = Pubkey::find_program_address(&[AUTHORITY_SEED], &caller_program_id);
+  let source = add_token_account(&mut program_test, &env.mint_a, &authority, USER_BALANCE);
+  let destination = add_token_account(&mut program_test, &env.mint_b, &authority, 0);
+  (program_test, env, caller_program_id, (authority, bump), source, destination)
+}
+
+/// swaps `AMOUNT_IN` of the PDA authority through the pool and checks the PDA balances
+async fn check_pda_route_swap(pool: fn(&TestEnv) -> &RoutePool, reserve_in: u64, reserve_out: u64) {
+  let (program_test, env, caller_program_id, (authority, bump), source, destination) = pda_env();
+  let mut context = program_test.start_with_context().await;
+
+  let fee = aggregator_fee(AMOUNT_IN);
+  let expected_out = constant_product(AMOUNT_IN - fee, reserve_in, reserve_out);
+  let instruction = caller_instruction(&caller_program_id, &env, pool(&env), &authority, &source, &destination, expected_out, Some(bump));
+  let return_data = process_return_data(&mut context, &[instruction], &[]).await.unwrap();
+  assert_eq!(SwapResult::try_from_slice(&return_data.data).unwrap(), SwapResult {
+    amount_in: AMOUNT_IN,
+    fee,
+    amount_out: expected_out,
+  });
+
+  let banks_client = &mut context.banks_client;
+  assert_eq!(token_balance(banks_client, &source).await, USER_BALANCE - AMOUNT_IN);
+  assert_eq!(token_balance(banks_client, &destination).await, expected_out);
+  assert_eq!(token_balance(banks_client, &env.fee_vault).await, fee);
+}
+
+#[tokio::test]
+async fn route_swap_returns_the_swap_result() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let mut context = program_test.start_with_context().await;
+
+  let fee = aggregator_fee(AMOUNT_IN);
+  let expected_out = constant_product(AMOUNT_IN - fee, POOL_RESERVE * 2, POOL_RESERVE * 2);
+  let instruction = env.route_swap(&env.saber, AMOUNT_IN, expected_out);
+  let return_data = process_return_data(&mut context, &[instruction], &[&env.user]).await.unwrap();
+
+  assert_eq!(return_data.program_id, env.program_id);
+  assert_eq!(SwapResult::try_from_slice(&return_data.data).unwrap(), SwapResult {
+    amount_in: AMOUNT_IN,
+    fee,
+    amount_out: expected_out,
+  });
+}
+
+#[tokio::test]
+async fn cpi_route_swap_reads_the_swap_result() {
+  let caller_program_id = Pubkey::new_unique();
+  let (mut program_test, env) = TestEnv::new();
+  program_test.add_program("mock_caller", caller_program_id, processor!(process_mock_caller));
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let mut context = program_test.start_with_context().await;
+
+  let fee = aggregator_fee(AMOUNT_IN);
+  let expected_out = constant_product(AMOUNT_IN - fee, POOL_RESERVE, POOL_RESERVE / 2);
+  let instruction = caller_instruction(
+    &caller_program_id,
+    &env,
+    &env.mercurial,
+    &env.user.pubkey(),
+    &env.source,
+    &env.destination,
+    expected_out,
+    None,
+  );
+
+  let return_data = process_return_data(&mut context, &[instruction], &[&env.user]).await.unwrap();
+  assert_eq!(return_data.program_id, caller_program_id);
+  assert_eq!(SwapResult::try_from_slice(&return_data.data).unwrap(), SwapResult {
+    amount_in: AMOUNT_IN,
+    fee,
+    amount_out: expected_out,
+  });
+  assert_eq!(token_balance(&mut context.banks_client, &env.destination).await, expected_out);
+}
+
+#[tokio::test]
+async fn pda_authority_raydium_route_swap() {
+  check_pda_route_swap(|env| &env.raydium, POOL_RESERVE, POOL_RESERVE).await;
+}
+
+#[tokio::test]
+async fn pda_authority_saber_route_swap() {
+  check_pda_route_swap(|env| &env.saber, POOL_RESERVE * 2, POOL_RESERVE * 2).await;
+}
+
+#[tokio::test]
+async fn pda_authority_mercurial_route_swap() {
+  check_pda_route_swap(|env| &env.mercurial, POOL_RESERVE, POOL_RESERVE / 2).await;
+}
+
+#[tokio::test]
+async fn route_swap_as_pda_rejects_other_seeds() {
+  let (program_test, env, caller_program_id, (authority, bump), source, destination) = pda_env();
+  let mut context = program_test.start_with_context().await;
+
+  let other_bump = Some(bump.wrapping_sub(1));
+  let instruction = caller_instruction(&caller_program_id, &env, &env.raydium, &authority, &source, &destination, 1, other_bump);
+  let error = process(&mut context, &[instruction], &[]).await.unwrap_err();
+  assert_eq!(error, TransactionError::InstructionError(
+    0,
+    InstructionError::Custom(AggregatorError::InvalidUserAuthoritySeeds as u32),
+  ));
+}
+
+#[tokio::test]
+async fn route_swap_rejects_unsigned_user_authority() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let mut context = program_test.start_with_context().await;
+
+  let mut instruction = env.route_swap(&env.raydium, AMOUNT_IN, 1);
+  instruction.accounts[1].is_signer = false;
+  let error = process(&mut context, &[instruction], &[]).await.unwrap_err();
+  assert_eq!(error, TransactionError::InstructionError(
+    0,
+    InstructionError::Custom(AggregatorError::MissingUserSignature as u32),
+  ));
+}
+
+#[tokio::test]
+async fn pda_authority_two_leg_route_swap_with_options() {
+  let caller_program_id = Pubkey::new_unique();
+  let (mut program_test, env) = TestEnv::new();
+  program_test.add_program("mock_options_caller", caller_program_id, processor!(process_mock_options_caller));
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+
+  // the PDA authority pays the rent of the intermediate account, and gets it back
+  let (authority, bump) = Pubkey::find_program_address(&[AUTHORITY_SEED], &caller_program_id);
+  let authority_lamports = 1_000_000_000;
+  program_test.add_account(authority, Account { lamports: authority_lamports, ..Account::default() });
+  let source = add_token_account(&mut program_test, &env.mint_a, &authority, USER_BALANCE);
+  let destination = add_token_account(&mut program_test, &env.mint_a, &authority, 0);
+  let mut context = program_test.start_with_context().await;
+
+  let fee = aggregator_fee(AMOUNT_IN);
+  let first_out = constant_product(AMOUNT_IN - fee, POOL_RESERVE, POOL_RESERVE);
+  let expected_out = constant_product(first_out, POOL_RESERVE * 2, POOL_RESERVE * 2);
+  let route_swap = client::route_swap_with_options(
+    &env.program_id,
+    &authority,
+    &source,
+    &destination,
+    &env.fee_vault,
+    &env.mint_a,
+    &env.mint_a,
+    &env.raydium,
+    Some((&env.saber, &env.mint_b)),
+    None,
+    AMOUNT_IN,
+    expected_out,
+    RouteOptions::default(),
+  ).unwrap();
+
+  let mut accounts = vec![AccountMeta::new_readonly(env.program_id, false)];
+  accounts.extend(route_swap.accounts.into_iter().map(|mut meta| {
+    // the PDA authority is signed by the caller, not by the transaction
+    meta.is_signer = false;
+    meta
+  }));
+  let instruction = Instruction {
+    program_id: caller_program_id,
+    accounts,
+    data: (AggregatorPath::Raydium, AggregatorPath::Saber, AMOUNT_IN, expected_out, bump).try_to_vec().unwrap(),
+  };
+
+  let return_data = process_return_data(&mut context, &[instruction], &[]).await.unwrap();
+  assert_eq!(return_data.program_id, caller_program_id);
+  assert_eq!(SwapResult::try_from_slice(&return_data.data).unwrap(), SwapResult {
+    amount_in: AMOUNT_IN,
+    fee,
+    amount_out: expected_out,
+  });
+
+  let banks_client = &mut context.banks_client;
+  let intermediate = client::intermediate_token_address(&env.program_id, &authority, &env.mint_b);
+  assert_eq!(banks_client.get_account(intermediate).await.unwrap(), None);
+  assert_eq!(banks_client.get_balance(authority).await.unwrap(), authority_lamports);
+  assert_eq!(token_balance(banks_client, &source).await, USER_BALANCE - AMOUNT_IN);
+  assert_eq!(token_balance(banks_client, &destination).await, expected_out);
+}
@@ -0,0 +1,177 @@
+//! Decoding of aggregator transactions and venue instructions
+
+mod common;
+
+use {
+  common::*,
+
+  base64::{engine::general_purpose::STANDARD, Engine},
+
+  borsh::BorshSerialize,
+
+  solana_program::{
+    instruction::{CompiledInstruction, Instruction},
+    message::Message,
+    pubkey::Pubkey,
+  },
+  solana_sdk::signature::Signer,
+
+  swap_aggregator::{
+    client::RoutePool,
+    decoder::{decode_events, decode_transaction, AccountRole, InnerInstructions, VenueInstruction},
+    dex::raydium::{self, AmmInstruction, DepositInstruction, InitializeInstruction, MonitorStepInstruction, SwapInstruction},
+    event::{AggregatorEvent, PauseUpdatedEvent, RouteUpdatedEvent, SwapEvent, SwapLegEvent},
+    instruction::{AggregatorInstruction, AggregatorPath},
+  },
+};
+
+const AMOUNT_IN: u64 = 10_000_000;
+
+/// Compiles `instruction` against the account keys of a message
+fn compile(account_keys: &[Pubkey], instruction: &Instruction) -> CompiledInstruction {
+  let position = |pubkey: &Pubkey| account_keys.iter().position(|key| key == pubkey).unwrap() as u8;
+  CompiledInstruction {
+    program_id_index: position(&instruction.program_id),
+    accounts: instruction.accounts.iter().map(|meta| position(&meta.pubkey)).collect(),
+    data: instruction.data.clone(),
+  }
+}
+
+fn role_of<'a>(accounts: &'a [AccountRole], role: &str) -> Option<&'a Pubkey> {
+  accounts.iter().find(|account| account.role == role)?.pubkey.as_ref()
+}
+
+#[test]
+fn amm_instruction_unpacks_what_it_packs() {
+  let instructions = vec![
+    AmmInstruction::Initialize(InitializeInstruction { nonce: 254 }),
+    AmmInstruction::Initialize2,
+    AmmInstruction::MonitorStep(MonitorStepInstruction { plan_order_limit: 1, place_order_limit: 2, cancel_order_limit: 3 }),
+    AmmInstruction::Deposit(DepositInstruction { max_coin_amount: 4, max_pc_amount: 5, base_side: 1 }),
+    AmmInstruction::WithdrawPnl,
+    AmmInstruction::Swap(SwapInstruction { amount_in: AMOUNT_IN, minimum_amount_out: u64::MAX }),
+    AmmInstruction::PreInitialize(InitializeInstruction { nonce: 7 }),
+  ];
+  for instruction in instructions {
+    assert_eq!(AmmInstruction::unpack(&instruction.pack().unwrap()).unwrap(), instruction);
+  }
+
+  assert!(AmmInstruction::unpack(&[]).is_err());
+  assert!(AmmInstruction::unpack(&[6]).is_err());
+  assert!(AmmInstruction::unpack(&[9, 1, 2, 3]).is_err());
+}
+
+#[tokio::test]
+async fn decodes_route_swap_transaction() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let mut context = program_test.start_with_context().await;
+
+  let fee = aggregator_fee(AMOUNT_IN);
+  let expected_out = constant_product(AMOUNT_IN - fee, POOL_RESERVE, POOL_RESERVE);
+  let instruction = env.route_swap(&env.raydium, AMOUNT_IN, expected_out);
+  let log_messages = process_logs(&mut context, std::slice::from_ref(&instruction), &[&env.user]).await;
+
+  // the CPI to the venue, as the RPC reports it in the inner instructions
+  let pool = match &env.raydium {
+    RoutePool::Raydium(pool) => pool,
+    _ => unreachable!(),
+  };
+  let venue_instruction = raydium::swap(
+    &pool.program_id,
+    &pool.amm_id,
+    &pool.amm_authority,
+    &pool.amm_open_orders,
+    &pool.amm_target_orders,
+    &pool.pool_coin_token_account,
+    &pool.pool_pc_token_account,
+    &pool.serum_program_id,
+    &pool.serum_market,
+    &pool.serum_bids,
+    &pool.serum_asks,
+    &pool.serum_event_queue,
+    &pool.serum_coin_vault_account,
+    &pool.serum_pc_vault_account,
+    &pool.serum_vault_signer,
+    &env.source,
+    &env.destination,
+    &env.user.pubkey(),
+    AMOUNT_IN - fee,
+    expected_out,
+  ).unwrap();
+
+  let message = Message::new(&[instruction], Some(&context.payer.pubkey()));
+  let inner_instructions = vec![InnerInstructions {
+    index: 0,
+    instructions: vec![compile(&message.account_keys, &venue_instruction)],
+  }];
+  let decoded = decode_transaction(
+    &env.program_id,
+    &message.account_keys,
+    &message.instructions,
+    &inner_instructions,
+    &log_messages,
+  );
+
+  assert_eq!(decoded.len(), 1);
+  let decoded = &decoded[0];
+  assert_eq!(decoded.instruction, Some(AggregatorInstruction::RouteSwap {
+    route1: AggregatorPath::Raydium,
+    route2: AggregatorPath::Skip,
+    amount_in: AMOUNT_IN,
+    amount_out: expected_out,
+  }));
+  assert_eq!(role_of(&decoded.accounts, "user transfer authority"), Some(&env.user.pubkey()));
+  assert_eq!(role_of(&decoded.accounts, "fee vault"), Some(&env.fee_vault));
+  assert_eq!(role_of(&decoded.accounts, "amm"), Some(&pool.amm_id));
+  assert_eq!(role_of(&decoded.accounts, "raydium program"), Some(&pool.program_id));
+
+  assert_eq!(decoded.venue_instructions.len(), 1);
+  let venue = &decoded.venue_instructions[0];
+  assert_eq!(venue.instruction, VenueInstruction::Raydium(AmmInstruction::Swap(SwapInstruction {
+    amount_in: AMOUNT_IN - fee,
+    minimum_amount_out: expected_out,
+  })));
+  assert_eq!(role_of(&venue.accounts, "user source token account"), Some(&env.source));
+  assert_eq!(role_of(&venue.accounts, "user destination token account"), Some(&env.destination));
+  assert_eq!(role_of(&venue.accounts, "user owner"), Some(&env.user.pubkey()));
+
+  assert_eq!(decoded.events, vec![AggregatorEvent::Swap(SwapEvent {
+    user: env.user.pubkey(),
+    input_mint: env.mint_a,
+    output_mint: env.mint_b,
+    amount_in: AMOUNT_IN,
+    fee,
+    legs: vec![SwapLegEvent { venue: AggregatorPath::Raydium, amount_out: expected_out }],
+    amount_out: expected_out,
+  })]);
+}
+
+#[test]
+fn decodes_events_of_on_chain_logs() {
+  let program_id = Pubkey::new_unique();
+  let venue_program_id = Pubkey::new_unique();
+  let paused = AggregatorEvent::PauseUpdated(PauseUpdatedEvent { is_paused: true, authority: Pubkey::new_unique() });
+  let route = AggregatorEvent::RouteUpdated(RouteUpdatedEvent { route: AggregatorPath::Saber, is_enabled: false });
+  let program_data = |event: &AggregatorEvent| format!("Program data: {}", STANDARD.encode(event.try_to_vec().unwrap()));
+
+  // `sol_log_data` lines as a validator logs them, next to the data of a venue CPI and a failed instruction
+  let log_messages: Vec<String> = vec![
+    format!("Program {} invoke [1]", program_id),
+    "Program log: Instruction: SetPause".to_string(),
+    program_data(&paused),
+    format!("Program {} invoke [2]", venue_program_id),
+    program_data(&route),
+    format!("Program {} success", venue_program_id),
+    format!("Program {} consumed 4000 of 200000 compute units", program_id),
+    format!("Program {} success", program_id),
+    format!("Program {} invoke [1]", program_id),
+    format!("Program log: {}", program_data(&route)),
+    format!("Program {} success", program_id),
+    format!("Program {} invoke [1]", program_id),
+    "Program data: not base64".to_string(),
+    format!("Program {} failed: custom program error: 0x1", program_id),
+  ];
+
+  assert_eq!(decode_events(&program_id, &log_messages), vec![vec![paused], vec![route], vec![]]);
+}
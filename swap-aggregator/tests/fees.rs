@@ -0,0 +1,481 @@
+//! Fee beneficiaries, fee distribution, discount tiers and the fee timelock
+
+mod common;
+
+use {
+  common::*,
+
+  borsh::BorshDeserialize,
+
+  solana_program::{clock::Clock, pubkey::Pubkey},
+  solana_program_test::ProgramTestContext,
+  solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+  },
+
+  swap_aggregator::{
+    client,
+    constant::{DEFAULT_FEE_CHANGE_DELAY, MAX_DISCOUNT_TIERS, MAX_FEE_BENEFICIARIES, MAX_FEE_CHANGE_DELAY},
+    error::AggregatorError,
+    event::{AggregatorEvent, DiscountTiersUpdatedEvent, FeeBeneficiariesUpdatedEvent, FeeChangeEvent, StateUpdatedEvent},
+    processor::{DiscountTier, FeeBeneficiary, PendingFeeChange, ProgramState},
+  },
+};
+
+const AMOUNT_IN: u64 = 10_000_000;
+
+fn aggregator_error(error: AggregatorError) -> TransactionError {
+  TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+async fn program_state(context: &mut ProgramTestContext, program_id: &Pubkey) -> ProgramState {
+  let account = context.banks_client.get_account(client::state_address(program_id)).await.unwrap().unwrap();
+  ProgramState::try_from_slice(&account.data).unwrap()
+}
+
+/// moves to the next slot, so retried transactions get a new blockhash, and the clock `seconds` ahead
+async fn warp_clock(context: &mut ProgramTestContext, seconds: i64) -> i64 {
+  let mut clock = context.banks_client.get_sysvar::<Clock>().await.unwrap();
+  context.warp_to_slot(clock.slot + 1).unwrap();
+  clock.slot += 1;
+  clock.unix_timestamp += seconds;
+  context.set_sysvar(&clock);
+  clock.unix_timestamp
+}
+
+#[tokio::test]
+async fn update_fee_beneficiaries_replaces_the_table() {
+  let (mut program_test, env) = TestEnv::new();
+  let owner = Keypair::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&owner.pubkey()));
+  let mut context = program_test.start_with_context().await;
+
+  let treasury = Pubkey::new_unique();
+  let team = Pubkey::new_unique();
+  let instruction = client::update_fee_beneficiaries(&env.program_id, &owner.pubkey(), &[(treasury, 3), (team, 1)]).unwrap();
+  let events = process_events(&mut context, &[instruction], &[&owner]).await;
+
+  let mut beneficiaries = [FeeBeneficiary::default(); MAX_FEE_BENEFICIARIES];
+  beneficiaries[0] = FeeBeneficiary { owner: treasury, weight: 3 };
+  beneficiaries[1] = FeeBeneficiary { owner: team, weight: 1 };
+  assert_eq!(events, vec![AggregatorEvent::FeeBeneficiariesUpdated(FeeBeneficiariesUpdatedEvent {
+    beneficiaries: beneficiaries[..2].to_vec(),
+  })]);
+  assert_eq!(program_state(&mut context, &env.program_id).await.beneficiaries, beneficiaries);
+
+  // a shorter table clears the other slots
+  let instruction = client::update_fee_beneficiaries(&env.program_id, &owner.pubkey(), &[(team, 5)]).unwrap();
+  process(&mut context, &[instruction], &[&owner]).await.unwrap();
+
+  let mut beneficiaries = [FeeBeneficiary::default(); MAX_FEE_BENEFICIARIES];
+  beneficiaries[0] = FeeBeneficiary { owner: team, weight: 5 };
+  assert_eq!(program_state(&mut context, &env.program_id).await.beneficiaries, beneficiaries);
+}
+
+#[tokio::test]
+async fn update_fee_beneficiaries_needs_the_state_owner() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let mut context = program_test.start_with_context().await;
+
+  let other = Keypair::new();
+  let instruction = client::update_fee_beneficiaries(&env.program_id, &other.pubkey(), &[(other.pubkey(), 1)]).unwrap();
+  let error = process(&mut context, &[instruction], &[&other]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidStateOwner));
+}
+
+#[tokio::test]
+async fn update_fee_beneficiaries_rejects_invalid_weights() {
+  let (mut program_test, env) = TestEnv::new();
+  let owner = Keypair::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&owner.pubkey()));
+  let mut context = program_test.start_with_context().await;
+
+  let too_many: Vec<(Pubkey, u64)> = (0..=MAX_FEE_BENEFICIARIES).map(|_| (Pubkey::new_unique(), 1)).collect();
+  let invalid_tables = [
+    vec![],
+    vec![(Pubkey::new_unique(), 1), (Pubkey::new_unique(), 0)],
+    vec![(Pubkey::new_unique(), u64::MAX), (Pubkey::new_unique(), 1)],
+    too_many,
+  ];
+  for beneficiaries in invalid_tables.iter() {
+    let instruction = client::update_fee_beneficiaries(&env.program_id, &owner.pubkey(), beneficiaries).unwrap();
+    let error = process(&mut context, &[instruction], &[&owner]).await.unwrap_err();
+    assert_eq!(error, aggregator_error(AggregatorError::InvalidFeeBeneficiaries));
+  }
+}
+
+#[tokio::test]
+async fn distribute_fees_pays_out_pro_rata_and_keeps_the_dust() {
+  let (mut program_test, env) = TestEnv::new();
+  let (treasury, team, insurance) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+  let mut state = initialized_state(&Pubkey::new_unique());
+  state.beneficiaries[0] = FeeBeneficiary { owner: treasury, weight: 5 };
+  state.beneficiaries[1] = FeeBeneficiary { owner: team, weight: 3 };
+  state.beneficiaries[2] = FeeBeneficiary { owner: insurance, weight: 1 };
+  add_program_state(&mut program_test, &env.program_id, &state);
+
+  let fee_vault = add_token_account(&mut program_test, &env.mint_a, &client::state_address(&env.program_id), 100);
+  let beneficiary_accounts = [
+    add_token_account(&mut program_test, &env.mint_a, &treasury, 0),
+    add_token_account(&mut program_test, &env.mint_a, &team, 0),
+    add_token_account(&mut program_test, &env.mint_a, &insurance, 0),
+  ];
+  let mut context = program_test.start_with_context().await;
+
+  // anyone can distribute
+  let instruction = client::distribute_fees(&env.program_id, &fee_vault, &beneficiary_accounts).unwrap();
+  process(&mut context, &[instruction], &[]).await.unwrap();
+
+  // 100 * 5/9, 100 * 3/9 and 100 * 1/9, rounded down
+  let banks_client = &mut context.banks_client;
+  assert_eq!(token_balance(banks_client, &beneficiary_accounts[0]).await, 55);
+  assert_eq!(token_balance(banks_client, &beneficiary_accounts[1]).await, 33);
+  assert_eq!(token_balance(banks_client, &beneficiary_accounts[2]).await, 11);
+  assert_eq!(token_balance(banks_client, &fee_vault).await, 1);
+}
+
+#[tokio::test]
+async fn distribute_fees_rejects_invalid_accounts() {
+  let (mut program_test, env) = TestEnv::new();
+  let treasury = Pubkey::new_unique();
+  let mut state = initialized_state(&Pubkey::new_unique());
+  state.beneficiaries[0] = FeeBeneficiary { owner: treasury, weight: 1 };
+  add_program_state(&mut program_test, &env.program_id, &state);
+
+  let state_address = client::state_address(&env.program_id);
+  let fee_vault = add_token_account(&mut program_test, &env.mint_a, &state_address, 100);
+  let other_vault = add_token_account(&mut program_test, &env.mint_a, &Pubkey::new_unique(), 100);
+  let treasury_account = add_token_account(&mut program_test, &env.mint_a, &treasury, 0);
+  let other_owner_account = add_token_account(&mut program_test, &env.mint_a, &Pubkey::new_unique(), 0);
+  let other_mint_account = add_token_account(&mut program_test, &env.mint_b, &treasury, 0);
+  let mut context = program_test.start_with_context().await;
+
+  let cases = [
+    (other_vault, treasury_account, AggregatorError::InvalidFeeOwner),
+    (fee_vault, other_owner_account, AggregatorError::InvalidBeneficiaryAccount),
+    (fee_vault, other_mint_account, AggregatorError::NotExpectedMint),
+  ];
+  for (vault, beneficiary_account, expected) in cases {
+    let instruction = client::distribute_fees(&env.program_id, &vault, &[beneficiary_account]).unwrap();
+    let error = process(&mut context, &[instruction], &[]).await.unwrap_err();
+    assert_eq!(error, aggregator_error(expected));
+  }
+
+  let banks_client = &mut context.banks_client;
+  assert_eq!(token_balance(banks_client, &fee_vault).await, 100);
+  assert_eq!(token_balance(banks_client, &treasury_account).await, 0);
+}
+
+#[tokio::test]
+async fn distribute_fees_needs_beneficiaries() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let fee_vault = add_token_account(&mut program_test, &env.mint_a, &client::state_address(&env.program_id), 100);
+  let mut context = program_test.start_with_context().await;
+
+  let instruction = client::distribute_fees(&env.program_id, &fee_vault, &[]).unwrap();
+  let error = process(&mut context, &[instruction], &[]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidFeeBeneficiaries));
+}
+
+#[tokio::test]
+async fn update_discount_tiers_sets_the_mint_and_tiers() {
+  let (mut program_test, env) = TestEnv::new();
+  let fee_admin = Keypair::new();
+  let mut state = initialized_state(&Pubkey::new_unique());
+  state.fee_admin = fee_admin.pubkey();
+  add_program_state(&mut program_test, &env.program_id, &state);
+  let mut context = program_test.start_with_context().await;
+
+  let tiers = vec![
+    DiscountTier { min_amount: 100, discount_bps: 1000 },
+    DiscountTier { min_amount: 1000, discount_bps: 2500 },
+  ];
+  let instruction = client::update_discount_tiers(&env.program_id, &fee_admin.pubkey(), &env.mint_b, tiers.clone()).unwrap();
+  let events = process_events(&mut context, &[instruction], &[&fee_admin]).await;
+  assert_eq!(events, vec![AggregatorEvent::DiscountTiersUpdated(DiscountTiersUpdatedEvent {
+    discount_mint: env.mint_b,
+    tiers: tiers.clone(),
+  })]);
+
+  let state = program_state(&mut context, &env.program_id).await;
+  let mut discount_tiers = [DiscountTier::default(); MAX_DISCOUNT_TIERS];
+  discount_tiers[..2].copy_from_slice(&tiers);
+  assert_eq!(state.discount_mint, env.mint_b);
+  assert_eq!(state.discount_tiers, discount_tiers);
+  assert_eq!(state.discount_bps(99), 0);
+  assert_eq!(state.discount_bps(100), 1000);
+  assert_eq!(state.discount_bps(5000), 2500);
+}
+
+#[tokio::test]
+async fn update_discount_tiers_rejects_invalid_tiers() {
+  let (mut program_test, env) = TestEnv::new();
+  let owner = Keypair::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&owner.pubkey()));
+  let mut context = program_test.start_with_context().await;
+
+  let tier = DiscountTier { min_amount: 100, discount_bps: 1000 };
+  let invalid_tiers = [
+    vec![DiscountTier { min_amount: 0, discount_bps: 1000 }],
+    vec![DiscountTier { min_amount: 100, discount_bps: 10001 }],
+    vec![tier; MAX_DISCOUNT_TIERS + 1],
+  ];
+  for tiers in invalid_tiers {
+    let instruction = client::update_discount_tiers(&env.program_id, &owner.pubkey(), &env.mint_b, tiers).unwrap();
+    let error = process(&mut context, &[instruction], &[&owner]).await.unwrap_err();
+    assert_eq!(error, aggregator_error(AggregatorError::InvalidDiscountTiers));
+  }
+
+  // the discount mint must be a token mint
+  let instruction = client::update_discount_tiers(&env.program_id, &owner.pubkey(), &owner.pubkey(), vec![tier]).unwrap();
+  let error = process(&mut context, &[instruction], &[&owner]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::NotExpectedMint));
+
+  let other = Keypair::new();
+  let instruction = client::update_discount_tiers(&env.program_id, &other.pubkey(), &env.mint_b, vec![tier]).unwrap();
+  let error = process(&mut context, &[instruction], &[&other]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidFeeAdmin));
+}
+
+#[tokio::test]
+async fn route_swap_discounts_the_fee_of_discount_holders() {
+  let (mut program_test, env) = TestEnv::new();
+  let discount_mint = Pubkey::new_unique();
+  add_mint(&mut program_test, &discount_mint);
+  let mut state = initialized_state(&Pubkey::new_unique());
+  state.discount_mint = discount_mint;
+  state.discount_tiers[0] = DiscountTier { min_amount: 100, discount_bps: 2000 };
+  state.discount_tiers[1] = DiscountTier { min_amount: 1000, discount_bps: 5000 };
+  add_program_state(&mut program_test, &env.program_id, &state);
+  let discount_account = add_token_account(&mut program_test, &discount_mint, &env.user.pubkey(), 1000);
+  let mut context = program_test.start_with_context().await;
+
+  // the best reached tier applies, as quoted off-chain
+  let fee = aggregator_fee(AMOUNT_IN) / 2;
+  assert_eq!(state.fee_amount(AMOUNT_IN, Some(1000)), fee);
+  assert_eq!(state.fee_amount(AMOUNT_IN, None), aggregator_fee(AMOUNT_IN));
+  let expected_out = constant_product(AMOUNT_IN - fee, POOL_RESERVE, POOL_RESERVE);
+  let instruction = client::route_swap(
+    &env.program_id,
+    &env.user.pubkey(),
+    &env.source,
+    &env.destination,
+    &env.fee_vault,
+    &env.mint_a,
+    &env.raydium,
+    Some(&discount_account),
+    AMOUNT_IN,
+    expected_out,
+  ).unwrap();
+  process(&mut context, &[instruction], &[&env.user]).await.unwrap();
+
+  let banks_client = &mut context.banks_client;
+  assert_eq!(token_balance(banks_client, &env.fee_vault).await, fee);
+  assert_eq!(token_balance(banks_client, &env.destination).await, expected_out);
+}
+
+#[tokio::test]
+async fn route_swap_rejects_discount_account_of_others() {
+  let (mut program_test, env) = TestEnv::new();
+  let discount_mint = Pubkey::new_unique();
+  add_mint(&mut program_test, &discount_mint);
+  let mut state = initialized_state(&Pubkey::new_unique());
+  state.discount_mint = discount_mint;
+  state.discount_tiers[0] = DiscountTier { min_amount: 100, discount_bps: 5000 };
+  add_program_state(&mut program_test, &env.program_id, &state);
+  let other_owner_account = add_token_account(&mut program_test, &discount_mint, &Pubkey::new_unique(), 1000);
+  let other_mint_account = add_token_account(&mut program_test, &env.mint_b, &env.user.pubkey(), 1000);
+  let mut context = program_test.start_with_context().await;
+
+  for discount_account in [other_owner_account, other_mint_account] {
+    let instruction = client::route_swap(
+      &env.program_id,
+      &env.user.pubkey(),
+      &env.source,
+      &env.destination,
+      &env.fee_vault,
+      &env.mint_a,
+      &env.raydium,
+      Some(&discount_account),
+      AMOUNT_IN,
+      0,
+    ).unwrap();
+    let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+    assert_eq!(error, aggregator_error(AggregatorError::InvalidDiscountAccount));
+  }
+}
+
+#[tokio::test]
+async fn update_state_rejects_fee_changes_after_initialization() {
+  let (mut program_test, env) = TestEnv::new();
+  let owner = Keypair::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&owner.pubkey()));
+  let mut context = program_test.start_with_context().await;
+
+  // the fee ratio only changes through the timelocked queue
+  let new_owner = Pubkey::new_unique();
+  for (fee_numerator, fee_denominator) in [(1, 2), (FEE_NUMERATOR, FEE_DENOMINATOR + 1)] {
+    let instruction = client::update_state(&env.program_id, &owner.pubkey(), &new_owner, fee_numerator, fee_denominator).unwrap();
+    let error = process(&mut context, &[instruction], &[&owner]).await.unwrap_err();
+    assert_eq!(error, aggregator_error(AggregatorError::InvalidFeeParameters));
+  }
+  assert_eq!(program_state(&mut context, &env.program_id).await.state_owner, owner.pubkey());
+
+  let instruction = client::update_state(&env.program_id, &owner.pubkey(), &new_owner, FEE_NUMERATOR, FEE_DENOMINATOR).unwrap();
+  let events = process_events(&mut context, &[instruction], &[&owner]).await;
+  assert_eq!(events, vec![AggregatorEvent::StateUpdated(StateUpdatedEvent {
+    initialized: false,
+    previous_owner: owner.pubkey(),
+    state_owner: new_owner,
+    fee_numerator: FEE_NUMERATOR,
+    fee_denominator: FEE_DENOMINATOR,
+  })]);
+
+  let state = program_state(&mut context, &env.program_id).await;
+  assert_eq!(state.state_owner, new_owner);
+  assert_eq!((state.fee_numerator, state.fee_denominator), (FEE_NUMERATOR, FEE_DENOMINATOR));
+}
+
+#[tokio::test]
+async fn execute_fee_change_waits_for_the_delay() {
+  let (mut program_test, env) = TestEnv::new();
+  let owner = Keypair::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&owner.pubkey()));
+  let mut context = program_test.start_with_context().await;
+
+  let now = warp_clock(&mut context, 0).await;
+  let instruction = client::queue_fee_change(&env.program_id, &owner.pubkey(), 2, 1000, 3600).unwrap();
+  let events = process_events(&mut context, &[instruction], &[&owner]).await;
+  let fee_change = FeeChangeEvent {
+    fee_numerator: 2,
+    fee_denominator: 1000,
+    fee_change_delay: 3600,
+    effective_timestamp: now + DEFAULT_FEE_CHANGE_DELAY,
+  };
+  assert_eq!(events, vec![AggregatorEvent::FeeChangeQueued(fee_change.clone())]);
+
+  let state = program_state(&mut context, &env.program_id).await;
+  assert_eq!(state.pending_fee_change, PendingFeeChange {
+    is_pending: true,
+    fee_numerator: 2,
+    fee_denominator: 1000,
+    effective_timestamp: now + DEFAULT_FEE_CHANGE_DELAY,
+  });
+  assert_eq!(state.pending_fee_change_delay, 3600);
+
+  // the queued delay doesn't shorten its own timelock
+  warp_clock(&mut context, 3600).await;
+  let instruction = client::execute_fee_change(&env.program_id, &owner.pubkey()).unwrap();
+  let error = process(&mut context, &[instruction], &[&owner]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::FeeChangeTimelocked));
+
+  warp_clock(&mut context, DEFAULT_FEE_CHANGE_DELAY - 3600).await;
+  let instruction = client::execute_fee_change(&env.program_id, &owner.pubkey()).unwrap();
+  let events = process_events(&mut context, &[instruction], &[&owner]).await;
+  assert_eq!(events, vec![AggregatorEvent::FeeChangeExecuted(fee_change)]);
+
+  let state = program_state(&mut context, &env.program_id).await;
+  assert_eq!((state.fee_numerator, state.fee_denominator), (2, 1000));
+  assert_eq!(state.fee_change_delay, 3600);
+  assert_eq!(state.pending_fee_change, PendingFeeChange::default());
+  assert_eq!(state.pending_fee_change_delay, 0);
+
+  // the next change waits for the new delay
+  let now = warp_clock(&mut context, 0).await;
+  let instruction = client::queue_fee_change(&env.program_id, &owner.pubkey(), 3, 1000, 3600).unwrap();
+  process(&mut context, &[instruction], &[&owner]).await.unwrap();
+  let state = program_state(&mut context, &env.program_id).await;
+  assert_eq!(state.pending_fee_change.effective_timestamp, now + 3600);
+}
+
+#[tokio::test]
+async fn cancel_fee_change_clears_the_queue() {
+  let (mut program_test, env) = TestEnv::new();
+  let owner = Keypair::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&owner.pubkey()));
+  let mut context = program_test.start_with_context().await;
+
+  let instruction = client::execute_fee_change(&env.program_id, &owner.pubkey()).unwrap();
+  let error = process(&mut context, &[instruction], &[&owner]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::NoPendingFeeChange));
+
+  let instruction = client::queue_fee_change(&env.program_id, &owner.pubkey(), 2, 1000, 3600).unwrap();
+  process(&mut context, &[instruction], &[&owner]).await.unwrap();
+  let effective_timestamp = program_state(&mut context, &env.program_id).await.pending_fee_change.effective_timestamp;
+  let instruction = client::cancel_fee_change(&env.program_id, &owner.pubkey()).unwrap();
+  let events = process_events(&mut context, &[instruction], &[&owner]).await;
+  assert_eq!(events, vec![AggregatorEvent::FeeChangeCancelled(FeeChangeEvent {
+    fee_numerator: 2,
+    fee_denominator: 1000,
+    fee_change_delay: 3600,
+    effective_timestamp,
+  })]);
+
+  let state = program_state(&mut context, &env.program_id).await;
+  assert_eq!(state.pending_fee_change, PendingFeeChange::default());
+  assert_eq!(state.pending_fee_change_delay, 0);
+  assert_eq!(state.fee_change_delay, DEFAULT_FEE_CHANGE_DELAY);
+
+  warp_clock(&mut context, DEFAULT_FEE_CHANGE_DELAY).await;
+  let instruction = client::execute_fee_change(&env.program_id, &owner.pubkey()).unwrap();
+  let error = process(&mut context, &[instruction], &[&owner]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::NoPendingFeeChange));
+
+  let instruction = client::cancel_fee_change(&env.program_id, &owner.pubkey()).unwrap();
+  let error = process(&mut context, &[instruction], &[&owner]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::NoPendingFeeChange));
+}
+
+#[tokio::test]
+async fn queue_fee_change_rejects_invalid_parameters() {
+  let (mut program_test, env) = TestEnv::new();
+  let owner = Keypair::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&owner.pubkey()));
+  let mut context = program_test.start_with_context().await;
+
+  for (fee_numerator, fee_denominator, fee_change_delay, expected) in [
+    (2, 0, 3600, AggregatorError::InvalidFeeParameters),
+    (1001, 1000, 3600, AggregatorError::InvalidFeeParameters),
+    (2, 1000, 0, AggregatorError::InvalidFeeChangeDelay),
+    (2, 1000, -1, AggregatorError::InvalidFeeChangeDelay),
+    (2, 1000, MAX_FEE_CHANGE_DELAY + 1, AggregatorError::InvalidFeeChangeDelay),
+  ] {
+    let instruction = client::queue_fee_change(&env.program_id, &owner.pubkey(), fee_numerator, fee_denominator, fee_change_delay).unwrap();
+    let error = process(&mut context, &[instruction], &[&owner]).await.unwrap_err();
+    assert_eq!(error, aggregator_error(expected));
+  }
+}
+
+#[tokio::test]
+async fn fee_changes_need_the_fee_admin() {
+  let (mut program_test, env) = TestEnv::new();
+  let mut state = initialized_state(&Pubkey::new_unique());
+  let fee_admin = Keypair::new();
+  state.fee_admin = fee_admin.pubkey();
+  add_program_state(&mut program_test, &env.program_id, &state);
+  let mut context = program_test.start_with_context().await;
+
+  let other = Keypair::new();
+  let instruction = client::queue_fee_change(&env.program_id, &other.pubkey(), 2, 1000, 3600).unwrap();
+  let error = process(&mut context, &[instruction], &[&other]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidFeeAdmin));
+
+  let instruction = client::queue_fee_change(&env.program_id, &fee_admin.pubkey(), 2, 1000, 3600).unwrap();
+  process(&mut context, &[instruction], &[&fee_admin]).await.unwrap();
+
+  warp_clock(&mut context, DEFAULT_FEE_CHANGE_DELAY).await;
+  let instruction = client::execute_fee_change(&env.program_id, &other.pubkey()).unwrap();
+  let error = process(&mut context, &[instruction], &[&other]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidFeeAdmin));
+  let instruction = client::cancel_fee_change(&env.program_id, &other.pubkey()).unwrap();
+  let error = process(&mut context, &[instruction], &[&other]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidFeeAdmin));
+
+  let instruction = client::execute_fee_change(&env.program_id, &fee_admin.pubkey()).unwrap();
+  process(&mut context, &[instruction], &[&fee_admin]).await.unwrap();
+  assert_eq!(program_state(&mut context, &env.program_id).await.fee_numerator, 2);
+}
@@ -0,0 +1,129 @@
+//! MigrateState of the state accounts of older program versions
+
+mod common;
+
+use {
+  common::*,
+
+  borsh::{BorshDeserialize, BorshSerialize},
+
+  solana_program::{pubkey::Pubkey, rent::Rent},
+  solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+  },
+
+  swap_aggregator::{
+    client,
+    constant::{DEFAULT_FEE_CHANGE_DELAY, MAX_ALLOWED_POOLS},
+    error::AggregatorError,
+    event::{AggregatorEvent, StateMigratedEvent},
+    processor::{FeeBeneficiary, LegacyProgramState, ProgramState},
+  },
+};
+
+const AMOUNT_IN: u64 = 10_000_000;
+
+fn aggregator_error(error: AggregatorError) -> TransactionError {
+  TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+fn legacy_state(state_owner: &Pubkey, fee_owner: &Pubkey) -> Vec<u8> {
+  LegacyProgramState {
+    is_initialized: true,
+    state_owner: *state_owner,
+    fee_owner: *fee_owner,
+    fee_numerator: FEE_NUMERATOR,
+    fee_denominator: FEE_DENOMINATOR,
+  }.try_to_vec().unwrap()
+}
+
+async fn program_state(context: &mut solana_program_test::ProgramTestContext, program_id: &Pubkey) -> (ProgramState, u64) {
+  let account = context.banks_client.get_account(client::state_address(program_id)).await.unwrap().unwrap();
+  (ProgramState::try_from_slice(&account.data).unwrap(), account.lamports)
+}
+
+#[tokio::test]
+async fn migrate_state_upgrades_the_legacy_layout() {
+  let (mut program_test, env) = TestEnv::new();
+  let fee_owner = Pubkey::new_unique();
+  let data = legacy_state(&env.user.pubkey(), &fee_owner);
+  assert_eq!(data.len(), LegacyProgramState::LEN);
+  add_state_data(&mut program_test, &env.program_id, data);
+  let mut context = program_test.start_with_context().await;
+
+  // swaps wait for the migration
+  let instruction = env.route_swap(&env.raydium, AMOUNT_IN, 0);
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::OutdatedStateLayout));
+
+  let instruction = client::migrate_state(&env.program_id, &env.user.pubkey()).unwrap();
+  let events = process_events(&mut context, &[instruction], &[&env.user]).await;
+  assert_eq!(events, vec![AggregatorEvent::StateMigrated(StateMigratedEvent {
+    previous_len: LegacyProgramState::LEN as u64,
+    len: ProgramState::LEN as u64,
+  })]);
+
+  let (state, lamports) = program_state(&mut context, &env.program_id).await;
+  assert_eq!(lamports, Rent::default().minimum_balance(ProgramState::LEN));
+  let mut beneficiaries = [FeeBeneficiary::default(); 8];
+  beneficiaries[0] = FeeBeneficiary { owner: fee_owner, weight: 1 };
+  assert_eq!(state, ProgramState {
+    is_initialized: true,
+    state_owner: env.user.pubkey(),
+    fee_numerator: FEE_NUMERATOR,
+    fee_denominator: FEE_DENOMINATOR,
+    beneficiaries,
+    bump: client::find_state_address(&env.program_id).1,
+    fee_change_delay: DEFAULT_FEE_CHANGE_DELAY,
+    ..ProgramState::default()
+  });
+
+  let fee = aggregator_fee(AMOUNT_IN);
+  let expected_out = constant_product(AMOUNT_IN - fee, POOL_RESERVE, POOL_RESERVE);
+  let instruction = env.route_swap(&env.raydium, AMOUNT_IN, expected_out);
+  process(&mut context, &[instruction], &[&env.user]).await.unwrap();
+  assert_eq!(token_balance(&mut context.banks_client, &env.fee_vault).await, fee);
+
+  // a second migration has nothing to do
+  let instruction = client::migrate_state(&env.program_id, &env.user.pubkey()).unwrap();
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::StateAlreadyMigrated));
+}
+
+#[tokio::test]
+async fn migrate_state_extends_a_shorter_layout() {
+  let (mut program_test, env) = TestEnv::new();
+  let mut state = initialized_state(&env.user.pubkey());
+  state.is_paused = true;
+
+  // a layout without the bump, the fee change delays and the pool allowlist
+  let mut data = state.try_to_vec().unwrap();
+  data.truncate(ProgramState::LEN - 17 - (32 * MAX_ALLOWED_POOLS + 1));
+  add_state_data(&mut program_test, &env.program_id, data);
+  let mut context = program_test.start_with_context().await;
+
+  let instruction = client::migrate_state(&env.program_id, &env.user.pubkey()).unwrap();
+  process(&mut context, &[instruction], &[&env.user]).await.unwrap();
+
+  let (migrated, _) = program_state(&mut context, &env.program_id).await;
+  assert_eq!(migrated, ProgramState { bump: client::find_state_address(&env.program_id).1, ..state });
+}
+
+#[tokio::test]
+async fn migrate_state_needs_the_state_owner() {
+  let (mut program_test, env) = TestEnv::new();
+  add_state_data(&mut program_test, &env.program_id, legacy_state(&Pubkey::new_unique(), &Pubkey::new_unique()));
+  let mut context = program_test.start_with_context().await;
+
+  let instruction = client::migrate_state(&env.program_id, &env.user.pubkey()).unwrap();
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidStateOwner));
+
+  let other = Keypair::new();
+  let mut instruction = client::migrate_state(&env.program_id, &other.pubkey()).unwrap();
+  instruction.accounts[1].is_signer = false;
+  let error = process(&mut context, &[instruction], &[]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidStateSigner));
+}
@@ -0,0 +1,521 @@
+//! RouteSwapWithOptions end to end, against the mock venues
+
+mod common;
+
+use {
+  common::*,
+
+  solana_program::pubkey::Pubkey,
+  solana_program_test::ProgramTest,
+  solana_sdk::{
+    account::Account,
+    instruction::{Instruction, InstructionError},
+    signature::Signer,
+    transaction::{Transaction, TransactionError},
+  },
+
+  spl_associated_token_account::get_associated_token_address,
+  spl_token::error::TokenError,
+
+  swap_aggregator::{
+    client::{self, RoutePool},
+    error::AggregatorError,
+    event::{AggregatorEvent, SwapEvent, SwapLegEvent},
+    instruction::{AggregatorPath, RouteOptions},
+    return_data::SimulationResult,
+  },
+
+  borsh::BorshDeserialize,
+};
+
+const AMOUNT_IN: u64 = 10_000_000;
+
+const WRAP: RouteOptions = RouteOptions {
+  wrap_native_input: true,
+  unwrap_native_output: false,
+  create_destination_account: false,
+  sweep_intermediate_residual: false,
+};
+const UNWRAP: RouteOptions = RouteOptions {
+  wrap_native_input: false,
+  unwrap_native_output: true,
+  create_destination_account: false,
+  sweep_intermediate_residual: false,
+};
+const CREATE_DESTINATION: RouteOptions = RouteOptions {
+  wrap_native_input: false,
+  unwrap_native_output: false,
+  create_destination_account: true,
+  sweep_intermediate_residual: false,
+};
+const SWEEP: RouteOptions = RouteOptions {
+  wrap_native_input: false,
+  unwrap_native_output: false,
+  create_destination_account: false,
+  sweep_intermediate_residual: true,
+};
+
+fn aggregator_error(error: AggregatorError) -> TransactionError {
+  TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+/// Two leg RouteSwapWithOptions of `AMOUNT_IN` from `mint_a` back to `mint_a`, through raydium then saber
+fn round_trip_route_swap(env: &TestEnv, destination: &Pubkey, amount_out: u64, options: RouteOptions) -> Instruction {
+  client::route_swap_with_options(
+    &env.program_id,
+    &env.user.pubkey(),
+    &env.source,
+    destination,
+    &env.fee_vault,
+    &env.mint_a,
+    &env.mint_a,
+    &env.raydium,
+    Some((&env.saber, &env.mint_b)),
+    None,
+    AMOUNT_IN,
+    amount_out,
+    options,
+  ).unwrap()
+}
+
+/// RouteSwapWithOptions of `AMOUNT_IN` through the raydium pool, between `mint_a` and `mint_b`
+#[allow(clippy::too_many_arguments)]
+fn route_swap_with_options(
+  env: &TestEnv,
+  source: &Pubkey,
+  destination: &Pubkey,
+  fee_vault: &Pubkey,
+  source_mint: &Pubkey,
+  destination_mint: &Pubkey,
+  amount_out: u64,
+  options: RouteOptions,
+) -> Instruction {
+  client::route_swap_with_options(
+    &env.program_id,
+    &env.user.pubkey(),
+    source,
+    destination,
+    fee_vault,
+    source_mint,
+    destination_mint,
+    &env.raydium,
+    None,
+    None,
+    AMOUNT_IN,
+    amount_out,
+    options,
+  ).unwrap()
+}
+
+#[tokio::test]
+async fn route_swap_wraps_native_sol_input() {
+  let (mut program_test, env) = TestEnv::native();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let mut context = program_test.start_with_context().await;
+
+  let user_lamports = context.banks_client.get_balance(env.user.pubkey()).await.unwrap();
+  let fee = aggregator_fee(AMOUNT_IN);
+  let expected_out = constant_product(AMOUNT_IN - fee, POOL_RESERVE, POOL_RESERVE);
+
+  let native_account = client::native_token_address(&env.program_id, &env.user.pubkey());
+  let instruction = route_swap_with_options(&env, &native_account, &env.destination, &env.fee_vault, &env.mint_a, &env.mint_b, expected_out, WRAP);
+  process(&mut context, &[instruction], &[&env.user]).await.unwrap();
+
+  let banks_client = &mut context.banks_client;
+  // the rent of the temporary account is refunded
+  assert_eq!(banks_client.get_balance(env.user.pubkey()).await.unwrap(), user_lamports - AMOUNT_IN);
+  assert_eq!(banks_client.get_account(native_account).await.unwrap(), None);
+  assert_eq!(token_balance(banks_client, &env.source).await, USER_BALANCE);
+  assert_eq!(token_balance(banks_client, &env.fee_vault).await, fee);
+  assert_eq!(token_balance(banks_client, &env.destination).await, expected_out);
+}
+
+#[tokio::test]
+async fn route_swap_unwraps_native_sol_output() {
+  let (mut program_test, env) = TestEnv::native();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let source = add_token_account(&mut program_test, &env.mint_b, &env.user.pubkey(), USER_BALANCE);
+  let fee_vault = add_token_account(&mut program_test, &env.mint_b, &client::state_address(&env.program_id), 0);
+  let mut context = program_test.start_with_context().await;
+
+  let user_lamports = context.banks_client.get_balance(env.user.pubkey()).await.unwrap();
+  let fee = aggregator_fee(AMOUNT_IN);
+  let expected_out = constant_product(AMOUNT_IN - fee, POOL_RESERVE, POOL_RESERVE);
+
+  let native_account = client::native_token_address(&env.program_id, &env.user.pubkey());
+  let instruction = route_swap_with_options(&env, &source, &native_account, &fee_vault, &env.mint_b, &env.mint_a, expected_out, UNWRAP);
+  process(&mut context, &[instruction], &[&env.user]).await.unwrap();
+
+  let banks_client = &mut context.banks_client;
+  assert_eq!(banks_client.get_balance(env.user.pubkey()).await.unwrap(), user_lamports + expected_out);
+  assert_eq!(banks_client.get_account(native_account).await.unwrap(), None);
+  assert_eq!(token_balance(banks_client, &source).await, USER_BALANCE - AMOUNT_IN);
+  assert_eq!(token_balance(banks_client, &fee_vault).await, fee);
+}
+
+#[tokio::test]
+async fn route_swap_rejects_other_native_account() {
+  let (mut program_test, env) = TestEnv::native();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let mut context = program_test.start_with_context().await;
+
+  // an existing wrapped SOL account is not the temporary account of the user
+  let instruction = route_swap_with_options(&env, &env.source, &env.destination, &env.fee_vault, &env.mint_a, &env.mint_b, 1, WRAP);
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidNativeAccount));
+
+  let native_account = client::native_token_address(&env.program_id, &env.user.pubkey());
+  let both = RouteOptions { unwrap_native_output: true, ..WRAP };
+  let instruction = route_swap_with_options(
+    &env,
+    &native_account,
+    &native_account,
+    &env.fee_vault,
+    &env.mint_a,
+    &env.mint_a,
+    1,
+    both,
+  );
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidNativeAccount));
+}
+
+#[tokio::test]
+async fn route_swap_creates_destination_account() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let mut context = program_test.start_with_context().await;
+
+  let fee = aggregator_fee(AMOUNT_IN);
+  let expected_out = constant_product(AMOUNT_IN - fee, POOL_RESERVE, POOL_RESERVE);
+
+  let destination = get_associated_token_address(&env.user.pubkey(), &env.mint_b);
+  let instruction = route_swap_with_options(
+    &env,
+    &env.source,
+    &destination,
+    &env.fee_vault,
+    &env.mint_a,
+    &env.mint_b,
+    expected_out,
+    CREATE_DESTINATION,
+  );
+  process(&mut context, &[instruction], &[&env.user]).await.unwrap();
+  assert_eq!(token_balance(&mut context.banks_client, &destination).await, expected_out);
+
+  // the existing account is reused
+  let second_out = constant_product(AMOUNT_IN - fee, POOL_RESERVE + AMOUNT_IN - fee, POOL_RESERVE - expected_out);
+  let instruction = route_swap_with_options(
+    &env,
+    &env.source,
+    &destination,
+    &env.fee_vault,
+    &env.mint_a,
+    &env.mint_b,
+    second_out,
+    CREATE_DESTINATION,
+  );
+  process(&mut context, &[instruction], &[&env.user]).await.unwrap();
+  assert_eq!(token_balance(&mut context.banks_client, &destination).await, expected_out + second_out);
+  assert_eq!(token_balance(&mut context.banks_client, &env.source).await, USER_BALANCE - 2 * AMOUNT_IN);
+}
+
+#[tokio::test]
+async fn route_swap_rejects_destination_not_associated() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let mut context = program_test.start_with_context().await;
+
+  let instruction = route_swap_with_options(
+    &env,
+    &env.source,
+    &env.destination,
+    &env.fee_vault,
+    &env.mint_a,
+    &env.mint_b,
+    1,
+    CREATE_DESTINATION,
+  );
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidDestinationAccount));
+
+  let unwrap_and_create = RouteOptions { create_destination_account: true, ..UNWRAP };
+  let instruction = route_swap_with_options(
+    &env,
+    &env.source,
+    &client::native_token_address(&env.program_id, &env.user.pubkey()),
+    &env.fee_vault,
+    &env.mint_a,
+    &env.mint_b,
+    1,
+    unwrap_and_create,
+  );
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::ConflictingRouteOptions));
+}
+
+#[tokio::test]
+async fn two_leg_route_swaps_through_the_intermediate_account() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let destination = add_token_account(&mut program_test, &env.mint_a, &env.user.pubkey(), 0);
+  let mut context = program_test.start_with_context().await;
+
+  let user_lamports = context.banks_client.get_balance(env.user.pubkey()).await.unwrap();
+  let fee = aggregator_fee(AMOUNT_IN);
+  let first_out = constant_product(AMOUNT_IN - fee, POOL_RESERVE, POOL_RESERVE);
+  let expected_out = constant_product(first_out, POOL_RESERVE * 2, POOL_RESERVE * 2);
+
+  let instruction = round_trip_route_swap(&env, &destination, expected_out, RouteOptions::default());
+  let events = process_events(&mut context, &[instruction], &[&env.user]).await;
+  assert_eq!(events, vec![AggregatorEvent::Swap(SwapEvent {
+    user: env.user.pubkey(),
+    input_mint: env.mint_a,
+    output_mint: env.mint_a,
+    amount_in: AMOUNT_IN,
+    fee,
+    legs: vec![
+      SwapLegEvent { venue: AggregatorPath::Raydium, amount_out: first_out },
+      SwapLegEvent { venue: AggregatorPath::Saber, amount_out: expected_out },
+    ],
+    amount_out: expected_out,
+  })]);
+
+  let banks_client = &mut context.banks_client;
+  let intermediate = client::intermediate_token_address(&env.program_id, &env.user.pubkey(), &env.mint_b);
+  assert_eq!(banks_client.get_account(intermediate).await.unwrap(), None);
+  // the rent of the intermediate account is refunded
+  assert_eq!(banks_client.get_balance(env.user.pubkey()).await.unwrap(), user_lamports);
+  assert_eq!(token_balance(banks_client, &env.source).await, USER_BALANCE - AMOUNT_IN);
+  assert_eq!(token_balance(banks_client, &destination).await, expected_out);
+  assert_eq!(token_balance(banks_client, &env.saber_vaults.1).await, POOL_RESERVE * 2 + first_out);
+}
+
+#[tokio::test]
+async fn two_leg_route_checks_minimum_amount_out_on_the_last_leg() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let destination = add_token_account(&mut program_test, &env.mint_a, &env.user.pubkey(), 0);
+  let mut context = program_test.start_with_context().await;
+
+  let fee = aggregator_fee(AMOUNT_IN);
+  let first_out = constant_product(AMOUNT_IN - fee, POOL_RESERVE, POOL_RESERVE);
+  let expected_out = constant_product(first_out, POOL_RESERVE * 2, POOL_RESERVE * 2);
+
+  let instruction = round_trip_route_swap(&env, &destination, expected_out + 1, RouteOptions::default());
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, TransactionError::InstructionError(0, InstructionError::Custom(MOCK_SLIPPAGE_ERROR)));
+}
+
+#[tokio::test]
+async fn two_leg_route_rejects_other_intermediate_account() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let destination = add_token_account(&mut program_test, &env.mint_a, &env.user.pubkey(), 0);
+  let mut context = program_test.start_with_context().await;
+
+  // a token account of the user is no longer accepted between the legs
+  let mut instruction = round_trip_route_swap(&env, &destination, 1, RouteOptions::default());
+  instruction.accounts[3].pubkey = env.destination;
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidIntermediateAccount));
+}
+
+#[tokio::test]
+async fn simulate_two_leg_route_returns_each_leg_and_rolls_back() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let destination = add_token_account(&mut program_test, &env.mint_a, &env.user.pubkey(), 0);
+  let mut context = program_test.start_with_context().await;
+
+  let fee = aggregator_fee(AMOUNT_IN);
+  let first_out = constant_product(AMOUNT_IN - fee, POOL_RESERVE, POOL_RESERVE);
+  let expected_out = constant_product(first_out, POOL_RESERVE * 2, POOL_RESERVE * 2);
+
+  let instruction = client::simulate_route_with_options(
+    &env.program_id,
+    &env.user.pubkey(),
+    &env.source,
+    &destination,
+    &env.fee_vault,
+    &env.mint_a,
+    &env.mint_a,
+    &env.raydium,
+    Some((&env.saber, &env.mint_b)),
+    None,
+    AMOUNT_IN,
+    RouteOptions::default(),
+  ).unwrap();
+  let transaction = Transaction::new_signed_with_payer(
+    &[instruction],
+    Some(&context.payer.pubkey()),
+    &[&context.payer, &env.user],
+    context.last_blockhash,
+  );
+  let simulation = context.banks_client.simulate_transaction(transaction).await.unwrap();
+  assert_eq!(simulation.result.unwrap(), Err(aggregator_error(AggregatorError::RouteSimulated)));
+
+  let return_data = simulation.simulation_details.unwrap().return_data.unwrap();
+  assert_eq!(return_data.program_id, env.program_id);
+  assert_eq!(SimulationResult::try_from_slice(&return_data.data).unwrap(), SimulationResult {
+    amount_in: AMOUNT_IN,
+    fee,
+    legs_amount_out: vec![first_out, expected_out],
+    amount_out: expected_out,
+  });
+
+  // nothing of the route, nor the intermediate account, is kept
+  let banks_client = &mut context.banks_client;
+  let intermediate = client::intermediate_token_address(&env.program_id, &env.user.pubkey(), &env.mint_b);
+  assert_eq!(banks_client.get_account(intermediate).await.unwrap(), None);
+  assert_eq!(token_balance(banks_client, &env.source).await, USER_BALANCE);
+  assert_eq!(token_balance(banks_client, &destination).await, 0);
+  assert_eq!(token_balance(banks_client, &env.saber_vaults.1).await, POOL_RESERVE * 2);
+}
+
+/// saber pool of `env` filling at most `fill_limit` per swap
+fn limit_saber_fill(program_test: &mut ProgramTest, env: &TestEnv, fill_limit: u64) {
+  let swap = match &env.saber {
+    RoutePool::Saber(pool) => pool.swap,
+    _ => unreachable!(),
+  };
+  program_test.add_account(swap, Account {
+    lamports: 1_000_000_000,
+    data: fill_limit.to_le_bytes().to_vec(),
+    owner: saber_program_id(),
+    ..Account::default()
+  });
+}
+
+#[tokio::test]
+async fn two_leg_route_sweeps_the_residual_into_the_destination() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let destination = add_token_account(&mut program_test, &env.mint_a, &env.user.pubkey(), 0);
+  let fee = aggregator_fee(AMOUNT_IN);
+  let first_out = constant_product(AMOUNT_IN - fee, POOL_RESERVE, POOL_RESERVE);
+  let fill_limit = first_out * 3 / 4;
+  limit_saber_fill(&mut program_test, &env, fill_limit);
+  let mut context = program_test.start_with_context().await;
+
+  // the second leg fills in part, the sweep swaps the rest through the same pool
+  let user_lamports = context.banks_client.get_balance(env.user.pubkey()).await.unwrap();
+  let second_out = constant_product(fill_limit, POOL_RESERVE * 2, POOL_RESERVE * 2);
+  let residual = first_out - fill_limit;
+  let sweep_out = constant_product(residual, POOL_RESERVE * 2 + fill_limit, POOL_RESERVE * 2 - second_out);
+  let expected_out = second_out + sweep_out;
+
+  let instruction = round_trip_route_swap(&env, &destination, second_out, SWEEP);
+  let events = process_events(&mut context, &[instruction], &[&env.user]).await;
+  assert_eq!(events, vec![AggregatorEvent::Swap(SwapEvent {
+    user: env.user.pubkey(),
+    input_mint: env.mint_a,
+    output_mint: env.mint_a,
+    amount_in: AMOUNT_IN,
+    fee,
+    legs: vec![
+      SwapLegEvent { venue: AggregatorPath::Raydium, amount_out: first_out },
+      SwapLegEvent { venue: AggregatorPath::Saber, amount_out: expected_out },
+    ],
+    amount_out: expected_out,
+  })]);
+
+  let banks_client = &mut context.banks_client;
+  let intermediate = client::intermediate_token_address(&env.program_id, &env.user.pubkey(), &env.mint_b);
+  assert_eq!(banks_client.get_account(intermediate).await.unwrap(), None);
+  assert_eq!(banks_client.get_balance(env.user.pubkey()).await.unwrap(), user_lamports);
+  assert_eq!(token_balance(banks_client, &destination).await, expected_out);
+  assert_eq!(token_balance(banks_client, &env.saber_vaults.1).await, POOL_RESERVE * 2 + first_out);
+}
+
+#[tokio::test]
+async fn sweep_checks_the_minimum_amount_out_on_the_total() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let destination = add_token_account(&mut program_test, &env.mint_a, &env.user.pubkey(), 0);
+  let first_out = constant_product(AMOUNT_IN - aggregator_fee(AMOUNT_IN), POOL_RESERVE, POOL_RESERVE);
+  let fill_limit = first_out * 3 / 4;
+  limit_saber_fill(&mut program_test, &env, fill_limit);
+  let mut context = program_test.start_with_context().await;
+
+  let second_out = constant_product(fill_limit, POOL_RESERVE * 2, POOL_RESERVE * 2);
+  let sweep_out = constant_product(first_out - fill_limit, POOL_RESERVE * 2 + fill_limit, POOL_RESERVE * 2 - second_out);
+  let expected_out = second_out + sweep_out;
+
+  let instruction = round_trip_route_swap(&env, &destination, expected_out + 1, SWEEP);
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::SlippageExceeded));
+  assert_eq!(token_balance(&mut context.banks_client, &env.source).await, USER_BALANCE);
+
+  // the second leg alone is below the minimum, the sweep makes up the rest
+  let instruction = round_trip_route_swap(&env, &destination, expected_out, SWEEP);
+  process(&mut context, &[instruction], &[&env.user]).await.unwrap();
+  assert_eq!(token_balance(&mut context.banks_client, &destination).await, expected_out);
+}
+
+#[tokio::test]
+async fn two_leg_route_without_sweep_fails_on_a_residual() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let destination = add_token_account(&mut program_test, &env.mint_a, &env.user.pubkey(), 0);
+  let first_out = constant_product(AMOUNT_IN - aggregator_fee(AMOUNT_IN), POOL_RESERVE, POOL_RESERVE);
+  limit_saber_fill(&mut program_test, &env, first_out / 2);
+  let mut context = program_test.start_with_context().await;
+
+  // the intermediate account can't be closed with a balance
+  let instruction = round_trip_route_swap(&env, &destination, 1, RouteOptions::default());
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, TransactionError::InstructionError(0, InstructionError::Custom(TokenError::NonNativeHasBalance as u32)));
+  assert_eq!(token_balance(&mut context.banks_client, &env.source).await, USER_BALANCE);
+}
+
+#[tokio::test]
+async fn sweep_needs_a_two_leg_route() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let mut context = program_test.start_with_context().await;
+
+  let instruction = route_swap_with_options(&env, &env.source, &env.destination, &env.fee_vault, &env.mint_a, &env.mint_b, 0, SWEEP);
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::ConflictingRouteOptions));
+}
+
+#[tokio::test]
+async fn two_leg_route_closes_a_wrapped_sol_intermediate_account() {
+  let (mut program_test, env) = TestEnv::native();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let source = add_token_account(&mut program_test, &env.mint_b, &env.user.pubkey(), USER_BALANCE);
+  let fee_vault = add_token_account(&mut program_test, &env.mint_b, &client::state_address(&env.program_id), 0);
+  let mut context = program_test.start_with_context().await;
+
+  let user_lamports = context.banks_client.get_balance(env.user.pubkey()).await.unwrap();
+  let fee = aggregator_fee(AMOUNT_IN);
+  let first_out = constant_product(AMOUNT_IN - fee, POOL_RESERVE, POOL_RESERVE);
+  let expected_out = constant_product(first_out, POOL_RESERVE * 2, POOL_RESERVE * 2);
+
+  // from mint_b back to mint_b, through wrapped SOL
+  let instruction = client::route_swap_with_options(
+    &env.program_id,
+    &env.user.pubkey(),
+    &source,
+    &env.destination,
+    &fee_vault,
+    &env.mint_b,
+    &env.mint_b,
+    &env.raydium,
+    Some((&env.saber, &env.mint_a)),
+    None,
+    AMOUNT_IN,
+    expected_out,
+    RouteOptions::default(),
+  ).unwrap();
+  process(&mut context, &[instruction], &[&env.user]).await.unwrap();
+
+  let banks_client = &mut context.banks_client;
+  let intermediate = client::intermediate_token_address(&env.program_id, &env.user.pubkey(), &env.mint_a);
+  assert_eq!(banks_client.get_account(intermediate).await.unwrap(), None);
+  // only the rent was left in the emptied account, and it is refunded
+  assert_eq!(banks_client.get_balance(env.user.pubkey()).await.unwrap(), user_lamports);
+  assert_eq!(token_balance(banks_client, &env.destination).await, expected_out);
+  assert_eq!(token_balance(banks_client, &env.raydium_vaults.0).await, POOL_RESERVE - first_out);
+}
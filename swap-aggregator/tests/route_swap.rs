@@ -0,0 +1,265 @@
+//! RouteSwap end to end, against the mock venues
+
+mod common;
+
+use {
+  common::*,
+
+  solana_program::{instruction::AccountMeta, pubkey::Pubkey, rent::Rent},
+  solana_sdk::{
+    account::Account,
+    instruction::InstructionError,
+    signature::Signer,
+    transaction::{Transaction, TransactionError},
+  },
+
+  swap_aggregator::{
+    client::{self, RoutePool},
+    error::AggregatorError,
+    event::{AggregatorEvent, SwapEvent, SwapLegEvent},
+    instruction::{AggregatorInstruction, AggregatorPath},
+    processor::ProgramState,
+    return_data::SimulationResult,
+  },
+
+  borsh::{BorshDeserialize, BorshSerialize},
+};
+
+const AMOUNT_IN: u64 = 10_000_000;
+
+fn aggregator_error(error: AggregatorError) -> TransactionError {
+  TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+/// swaps `AMOUNT_IN` through the pool and checks every balance delta
+async fn check_route_swap(pool: fn(&TestEnv) -> (&RoutePool, (Pubkey, Pubkey)), reserve_in: u64, reserve_out: u64) {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let mut context = program_test.start_with_context().await;
+
+  let (route_pool, (vault_in, vault_out)) = pool(&env);
+  let fee = aggregator_fee(AMOUNT_IN);
+  let expected_out = constant_product(AMOUNT_IN - fee, reserve_in, reserve_out);
+
+  let instruction = env.route_swap(route_pool, AMOUNT_IN, expected_out);
+  process(&mut context, &[instruction], &[&env.user]).await.unwrap();
+
+  let banks_client = &mut context.banks_client;
+  assert_eq!(token_balance(banks_client, &env.source).await, USER_BALANCE - AMOUNT_IN);
+  assert_eq!(token_balance(banks_client, &env.fee_vault).await, fee);
+  assert_eq!(token_balance(banks_client, &env.destination).await, expected_out);
+  assert_eq!(token_balance(banks_client, &vault_in).await, reserve_in + AMOUNT_IN - fee);
+  assert_eq!(token_balance(banks_client, &vault_out).await, reserve_out - expected_out);
+}
+
+#[tokio::test]
+async fn raydium_route_swap() {
+  check_route_swap(|env| (&env.raydium, env.raydium_vaults), POOL_RESERVE, POOL_RESERVE).await;
+}
+
+#[tokio::test]
+async fn saber_route_swap() {
+  check_route_swap(|env| (&env.saber, env.saber_vaults), POOL_RESERVE * 2, POOL_RESERVE * 2).await;
+}
+
+#[tokio::test]
+async fn mercurial_route_swap() {
+  check_route_swap(|env| (&env.mercurial, env.mercurial_vaults), POOL_RESERVE, POOL_RESERVE / 2).await;
+}
+
+#[tokio::test]
+async fn route_swap_emits_swap_event() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let mut context = program_test.start_with_context().await;
+
+  let fee = aggregator_fee(AMOUNT_IN);
+  let expected_out = constant_product(AMOUNT_IN - fee, POOL_RESERVE, POOL_RESERVE);
+
+  let instruction = env.route_swap(&env.raydium, AMOUNT_IN, expected_out);
+  let events = process_events(&mut context, &[instruction], &[&env.user]).await;
+  assert_eq!(events, vec![AggregatorEvent::Swap(SwapEvent {
+    user: env.user.pubkey(),
+    input_mint: env.mint_a,
+    output_mint: env.mint_b,
+    amount_in: AMOUNT_IN,
+    fee,
+    legs: vec![SwapLegEvent { venue: AggregatorPath::Raydium, amount_out: expected_out }],
+    amount_out: expected_out,
+  })]);
+}
+
+#[tokio::test]
+async fn route_swap_fails_below_minimum_amount_out() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let mut context = program_test.start_with_context().await;
+
+  let expected_out = constant_product(AMOUNT_IN - aggregator_fee(AMOUNT_IN), POOL_RESERVE, POOL_RESERVE);
+  let instruction = env.route_swap(&env.raydium, AMOUNT_IN, expected_out + 1);
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, TransactionError::InstructionError(0, InstructionError::Custom(MOCK_SLIPPAGE_ERROR)));
+
+  // the fee transfer is rolled back with the swap
+  let banks_client = &mut context.banks_client;
+  assert_eq!(token_balance(banks_client, &env.source).await, USER_BALANCE);
+  assert_eq!(token_balance(banks_client, &env.fee_vault).await, 0);
+  assert_eq!(token_balance(banks_client, &env.destination).await, 0);
+}
+
+#[tokio::test]
+async fn simulate_route_returns_the_result_and_rolls_back() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let mut context = program_test.start_with_context().await;
+
+  let fee = aggregator_fee(AMOUNT_IN);
+  let expected_out = constant_product(AMOUNT_IN - fee, POOL_RESERVE, POOL_RESERVE);
+  let instruction = client::simulate_route(
+    &env.program_id,
+    &env.user.pubkey(),
+    &env.source,
+    &env.destination,
+    &env.fee_vault,
+    &env.mint_a,
+    &env.raydium,
+    None,
+    AMOUNT_IN,
+  ).unwrap();
+  let transaction = Transaction::new_signed_with_payer(
+    &[instruction],
+    Some(&context.payer.pubkey()),
+    &[&context.payer, &env.user],
+    context.last_blockhash,
+  );
+  let simulation = context.banks_client.simulate_transaction(transaction).await.unwrap();
+  assert_eq!(simulation.result.unwrap(), Err(aggregator_error(AggregatorError::RouteSimulated)));
+
+  let return_data = simulation.simulation_details.unwrap().return_data.unwrap();
+  assert_eq!(return_data.program_id, env.program_id);
+  assert_eq!(SimulationResult::try_from_slice(&return_data.data).unwrap(), SimulationResult {
+    amount_in: AMOUNT_IN,
+    fee,
+    legs_amount_out: vec![expected_out],
+    amount_out: expected_out,
+  });
+
+  let banks_client = &mut context.banks_client;
+  assert_eq!(token_balance(banks_client, &env.source).await, USER_BALANCE);
+  assert_eq!(token_balance(banks_client, &env.fee_vault).await, 0);
+  assert_eq!(token_balance(banks_client, &env.destination).await, 0);
+}
+
+#[tokio::test]
+async fn route_swap_and_simulate_route_reject_a_second_leg() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let mut context = program_test.start_with_context().await;
+
+  let mut instruction = env.route_swap(&env.raydium, AMOUNT_IN, 0);
+  instruction.data = AggregatorInstruction::RouteSwap {
+    route1: AggregatorPath::Raydium,
+    route2: AggregatorPath::Saber,
+    amount_in: AMOUNT_IN,
+    amount_out: 0,
+  }.try_to_vec().unwrap();
+  let error = process(&mut context, &[instruction.clone()], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::UnsupportedSecondLeg));
+
+  instruction.data = AggregatorInstruction::SimulateRoute {
+    route1: AggregatorPath::Raydium,
+    route2: AggregatorPath::Saber,
+    amount_in: AMOUNT_IN,
+  }.try_to_vec().unwrap();
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::UnsupportedSecondLeg));
+
+  assert_eq!(token_balance(&mut context.banks_client, &env.source).await, USER_BALANCE);
+}
+
+#[tokio::test]
+async fn route_swap_rejects_uninitialized_state() {
+  let (mut program_test, env) = TestEnv::new();
+  let program_state = ProgramState::try_from_slice(&[0; ProgramState::LEN]).unwrap();
+  add_program_state(&mut program_test, &env.program_id, &program_state);
+  let mut context = program_test.start_with_context().await;
+
+  let instruction = env.route_swap(&env.raydium, AMOUNT_IN, 0);
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::NotInitializedState));
+}
+
+#[tokio::test]
+async fn route_swap_rejects_wrong_state_account() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let mut context = program_test.start_with_context().await;
+
+  let mut instruction = env.route_swap(&env.raydium, AMOUNT_IN, 0);
+  instruction.accounts[0] = AccountMeta::new_readonly(Pubkey::new_unique(), false);
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidStateAddress));
+}
+
+#[tokio::test]
+async fn route_swap_rejects_state_with_wrong_bump() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+
+  // same address, but the stored bump does not derive it
+  let (state_address, bump) = client::find_state_address(&env.program_id);
+  let program_state = ProgramState { bump: bump.wrapping_sub(1), ..initialized_state(&Pubkey::new_unique()) };
+  let data = program_state.try_to_vec().unwrap();
+  program_test.add_account(state_address, Account {
+    lamports: Rent::default().minimum_balance(data.len()),
+    data,
+    owner: env.program_id,
+    executable: false,
+    rent_epoch: 0,
+  });
+  let mut context = program_test.start_with_context().await;
+
+  let instruction = env.route_swap(&env.raydium, AMOUNT_IN, 0);
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidStateAddress));
+}
+
+#[tokio::test]
+async fn route_swap_rejects_wrong_token_program() {
+  let (mut program_test, env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  let mut context = program_test.start_with_context().await;
+
+  let mut instruction = env.route_swap(&env.raydium, AMOUNT_IN, 0);
+  instruction.accounts[5] = AccountMeta::new_readonly(Pubkey::new_unique(), false);
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidTokenProgramId));
+}
+
+#[tokio::test]
+async fn route_swap_rejects_fee_vault_not_owned_by_state() {
+  let (mut program_test, mut env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+  env.fee_vault = add_token_account(&mut program_test, &env.mint_a, &env.user.pubkey(), 0);
+  let mut context = program_test.start_with_context().await;
+
+  let instruction = env.route_swap(&env.raydium, AMOUNT_IN, 0);
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, aggregator_error(AggregatorError::InvalidFeeOwner));
+}
+
+#[tokio::test]
+async fn route_swap_rejects_pool_of_other_mints() {
+  let (mut program_test, mut env) = TestEnv::new();
+  add_program_state(&mut program_test, &env.program_id, &initialized_state(&Pubkey::new_unique()));
+
+  // the destination holds a mint the pool does not trade
+  let mint_c = Pubkey::new_unique();
+  add_mint(&mut program_test, &mint_c);
+  env.destination = add_token_account(&mut program_test, &mint_c, &env.user.pubkey(), 0);
+  let mut context = program_test.start_with_context().await;
+
+  let instruction = env.route_swap(&env.saber, AMOUNT_IN, 0);
+  let error = process(&mut context, &[instruction], &[&env.user]).await.unwrap_err();
+  assert_eq!(error, TransactionError::InstructionError(0, InstructionError::InvalidAccountData));
+}
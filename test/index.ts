@@ -19,7 +19,7 @@ import {
 
 import { loadPools } from "./pools";
 import { AggregatorPath } from "./common/types";
-import { user, feeOwner } from "./test_wallets";
+import { user } from "./test_wallets";
 import { str2pubkey } from "./common/utils";
 
 const CLUSTER: string = "devnet"
@@ -38,7 +38,6 @@ const initGlobalState = async() => {
       stateId,
       user.publicKey,
       user.publicKey,
-      feeOwner.publicKey,
       200,
       10000
     )
@@ -88,11 +87,13 @@ const main = async() => {
   const userSourceTokenAccount = await getAssociatedTokenAddress(sourceMint, user.publicKey);
   const userDestTokenAccount = await getAssociatedTokenAddress(destMint, user.publicKey);
 
+  // fees are collected into a vault owned by the state account
   const feeTokenAccount = await getOrCreateAssociatedTokenAccount(
     connection,
     user,
     sourceMint,
-    feeOwner.publicKey
+    await getGlobalStateAddress(),
+    true
   );
 
   // await routeSwap(
@@ -22,7 +22,6 @@ export async function getGlobalStateAccount(connection: Connection): Promise<any
   const GLOBAL_STATE_LAYOUT = struct([
     u8("isInitialized"),
     publicKey("stateOwner"),
-    publicKey("feeOwner"),
     u64('feeNumerator'),
     u64('feeDenominator'),
   ]);  
@@ -43,7 +42,6 @@ export function updateGlobalStateInstruction(
   stateId: PublicKey,
   curStateOwner: PublicKey,
   newStateOwner: PublicKey,
-  feeOwner: PublicKey,
   feeNumerator: number,
   feeDenominator: number
 ): TransactionInstruction {
@@ -52,7 +50,6 @@ export function updateGlobalStateInstruction(
     { pubkey: stateId, isSigner: false, isWritable: true },
     { pubkey: curStateOwner, isSigner: true, isWritable: false },
     { pubkey: newStateOwner, isSigner: false, isWritable: false },
-    { pubkey: feeOwner, isSigner: false, isWritable: false },
 
     { pubkey: SYSTEM_PROGRAM_ID, isSigner: false, isWritable: false },
     { pubkey: SYSVAR_RENT_PUBKEY, isSigner: false, isWritable: false },